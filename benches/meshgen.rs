@@ -0,0 +1,116 @@
+//! Compares the naive and the greedy sector mesher, the
+//! packed vertex format, and the meshes of the coarser
+//! levels of detail.
+//!
+//! A region of the world is generated without meshes,
+//! every sector in it is copied along with the voxels of
+//! its neighbors, and then meshed repeatedly by every
+//! mesher. The total number of vertices and indices
+//! and the average time spent per sector are printed.
+//!
+//! Run with ``cargo bench``.
+
+use sandbox::{
+    entity::sector::{
+        self, GenController, GenSettings, MeshNeighborhood, PaddedSector, PreGeometry, SectorIndex,
+        SectorRegion, TerrainTextures, VertexFormat,
+    },
+    resource::TextureManager,
+    timing::Clock,
+};
+use std::collections::HashMap;
+
+// How many times every sector is meshed by each mesher.
+const ITERATIONS: usize = 10;
+
+// The vertex format of all meshers but the packed one.
+const FULL: VertexFormat = VertexFormat::Full;
+
+fn main() {
+    let atlas = TextureManager::load_terrain_atlas().unwrap();
+    let textures = TextureManager::terrain_textures(&atlas, atlas.layout());
+
+    let mut settings = GenSettings::new(SectorRegion::new(
+        SectorIndex(-4, -3, -4),
+        SectorIndex(4, 1, 4),
+    ));
+    settings.mesh = false;
+
+    let generator = GenController::launch(textures, settings);
+    let generated: HashMap<_, _> = generator
+        .receiver()
+        .iter()
+        .map(|message| (message.world_pos, (message.sector_data, message.light)))
+        .collect();
+
+    // Copying the voxels around each sector is not part
+    // of meshing, so it is done before anything is timed.
+    let sectors: Vec<_> = generated
+        .iter()
+        .map(|(index, (voxels, light))| {
+            let SectorIndex(x, y, z) = *index;
+            let source = MeshNeighborhood::new(*index, voxels, light, |[dx, dy, dz]| {
+                generated
+                    .get(&SectorIndex(x + dx, y + dy, z + dz))
+                    .map(|(voxels, light)| (voxels, light))
+            });
+
+            PaddedSector::from_source(&source)
+        })
+        .collect();
+
+    println!(
+        "meshing {} sectors {} times each",
+        sectors.len(),
+        ITERATIONS
+    );
+
+    let mut results = Vec::new();
+
+    for (name, mesher) in &[
+        ("naive", (|t, s| sector::gen_terrain(t, s, FULL)) as Mesher),
+        ("greedy", |t, s| sector::gen_terrain_greedy(t, s, FULL)),
+        ("greedy packed", |t, s| {
+            sector::gen_terrain_greedy(t, s, VertexFormat::Packed)
+        }),
+        ("lod 1", |t, s| sector::gen_terrain_lod(t, s, 1, FULL)),
+        ("lod 2", |t, s| sector::gen_terrain_lod(t, s, 2, FULL)),
+        ("lod 3", |t, s| sector::gen_terrain_lod(t, s, 3, FULL)),
+    ] {
+        let mut n_vertices = 0;
+        let mut n_indices = 0;
+
+        let clock = Clock::begin();
+
+        for i in 0..ITERATIONS {
+            for source in &sectors {
+                if let Some(pre_geo) = mesher(&textures, source) {
+                    if i == 0 {
+                        n_vertices += pre_geo.vertices.len();
+                        n_indices += pre_geo.indices.len();
+                    }
+                }
+            }
+        }
+
+        let per_sector = clock.elapsed_seconds() / (ITERATIONS * sectors.len()) as f64;
+        results.push((name, n_vertices, n_indices, per_sector));
+    }
+
+    println!(
+        "{:>13} {:>12} {:>12} {:>14}",
+        "mesher", "vertices", "indices", "µs/sector"
+    );
+
+    for (name, n_vertices, n_indices, per_sector) in &results {
+        println!(
+            "{:>13} {:>12} {:>12} {:>14.1}",
+            name,
+            n_vertices,
+            n_indices,
+            per_sector * 1e6
+        );
+    }
+}
+
+type Mesher = fn(&TerrainTextures, &PaddedSector) -> Option<PreGeometry>;
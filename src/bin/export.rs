@@ -0,0 +1,114 @@
+//! Generates a region of the world and exports its
+//! meshes for use in other 3D tools, without opening
+//! a window.
+//!
+//! Usage:
+//!
+//! ```text
+//! export <seed> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z> <out_file> [--workers <n>]
+//! ```
+//!
+//! The minimum sector index is inclusive and the
+//! maximum is exclusive. The format is chosen by the
+//! extension of ``out_file``:
+//!
+//! * ``.obj`` writes a Wavefront OBJ file, along with
+//!   a material library and the terrain atlas as a PNG
+//!   image with the same name.
+//! * ``.glb`` writes a binary glTF file that embeds the
+//!   terrain atlas.
+
+use sandbox::{
+    cli::{fail, Args},
+    entity::sector::{self, GenController},
+    resource::TextureManager,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str = "usage: export <seed> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z> \
+                     <out_file> [--workers <n>]";
+
+fn main() {
+    let args = Args::from_env(USAGE, 8);
+    let out_file = PathBuf::from(args.get::<String>(7));
+
+    let mut settings = args.gen_settings();
+    settings.lods = false;
+
+    let mut options = args.options(8);
+    while let Some(option) = options.next() {
+        options.shared(option, &mut settings);
+    }
+
+    let extension = out_file.extension().and_then(|ext| ext.to_str());
+    if extension != Some("obj") && extension != Some("glb") {
+        fail("the output file must end in .obj or .glb");
+    }
+
+    println!("generating {} sectors...", settings.region.len());
+
+    let atlas = TextureManager::load_terrain_atlas()
+        .unwrap_or_else(|e| fail(&format!("cannot load the terrain textures: {}", e)));
+    let textures = TextureManager::terrain_textures(&atlas, atlas.layout());
+    let generator = GenController::launch(textures, settings);
+
+    let meshes: Vec<_> = generator
+        .receiver()
+        .iter()
+        .filter_map(|message| {
+            let world_pos = message.world_pos;
+            message.pre_geometry.map(|pre_geo| (world_pos, pre_geo))
+        })
+        .collect();
+    let meshes: Vec<_> = meshes
+        .iter()
+        .map(|(index, pre_geo)| (*index, pre_geo))
+        .collect();
+
+    let tile_size = atlas.tile_extent();
+
+    let mut w = BufWriter::new(create(&out_file));
+
+    let result = if extension == Some("obj") {
+        let mtl_path = out_file.with_extension("mtl");
+        let png_path = out_file.with_extension("png");
+
+        let mut png = BufWriter::new(create(&png_path));
+        if let Err(e) = atlas.write_png(&mut png).and_then(|_| png.flush()) {
+            fail(&format!("cannot write {}: {}", png_path.display(), e));
+        }
+
+        let mut mtl = BufWriter::new(create(&mtl_path));
+        if let Err(e) = sector::write_mtl(&mut mtl, &file_name(&png_path)).and_then(|_| mtl.flush())
+        {
+            fail(&format!("cannot write {}: {}", mtl_path.display(), e));
+        }
+
+        sector::write_obj(&mut w, &meshes, tile_size, &file_name(&mtl_path))
+    } else {
+        let mut png = Vec::new();
+        if let Err(e) = atlas.write_png(&mut png) {
+            fail(&format!("cannot encode the terrain atlas: {}", e));
+        }
+
+        sector::write_glb(&mut w, &meshes, tile_size, &png)
+    };
+
+    if let Err(e) = result.and_then(|_| w.flush()) {
+        fail(&format!("cannot write {}: {}", out_file.display(), e));
+    }
+
+    println!("exported {} meshes to {}", meshes.len(), out_file.display());
+}
+
+fn create(path: &Path) -> File {
+    File::create(path).unwrap_or_else(|e| fail(&format!("cannot create {}: {}", path.display(), e)))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
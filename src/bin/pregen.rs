@@ -0,0 +1,131 @@
+//! Generates a region of the world ahead of time
+//! and writes its sectors to a directory in the
+//! save format, without opening a window.
+//!
+//! Usage:
+//!
+//! ```text
+//! pregen <seed> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z> <out_dir> [--mesh] [--workers <n>]
+//! ```
+//!
+//! The minimum sector index is inclusive and the
+//! maximum is exclusive. With ``--mesh``, every
+//! sector is meshed as well, although only the
+//! voxel data is saved.
+
+use sandbox::{
+    cli::{fail, Args},
+    entity::sector::{self, GenController},
+    resource::TextureManager,
+    timing::Clock,
+};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+const USAGE: &str = "usage: pregen <seed> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z> \
+                     <out_dir> [--mesh] [--workers <n>]";
+
+fn main() {
+    let args = Args::from_env(USAGE, 8);
+    let out_dir = PathBuf::from(args.get::<String>(7));
+
+    let mut settings = args.gen_settings();
+    settings.mesh = false;
+
+    let mut options = args.options(8);
+    while let Some(option) = options.next() {
+        match option {
+            "--mesh" => settings.mesh = true,
+            _ => options.shared(option, &mut settings),
+        }
+    }
+
+    if let Err(e) = fs::create_dir_all(&out_dir) {
+        fail(&format!("cannot create {}: {}", out_dir.display(), e));
+    }
+
+    println!(
+        "generating {} sectors with {} workers...",
+        settings.region.len(),
+        settings.n_workers
+    );
+
+    let atlas = TextureManager::load_terrain_atlas()
+        .unwrap_or_else(|e| fail(&format!("cannot load the terrain textures: {}", e)));
+    let textures = TextureManager::terrain_textures(&atlas, atlas.layout());
+
+    let clock = Clock::begin();
+    let generator = GenController::launch(textures, settings);
+
+    let mut n_sectors = 0;
+    let mut n_meshes = 0;
+    let mut n_vertices = 0;
+    let mut n_triangles = 0;
+    let mut n_bytes = 0;
+
+    for message in generator.receiver().iter() {
+        let path = out_dir.join(sector::sector_file_name(message.world_pos));
+
+        let mut w = BufWriter::new(CountingFile::create(&path));
+        if let Err(e) = sector::write_sector(&mut w, message.world_pos, &message.sector_data) {
+            fail(&format!("cannot write {}: {}", path.display(), e));
+        }
+
+        match w.into_inner() {
+            Ok(file) => n_bytes += file.written,
+            Err(e) => fail(&format!("cannot write {}: {}", path.display(), e)),
+        }
+
+        if let Some(pre_geo) = message.pre_geometry {
+            n_meshes += 1;
+            n_vertices += pre_geo.vertices.len();
+            n_triangles += pre_geo.indices.len() / 3;
+        }
+
+        n_sectors += 1;
+    }
+
+    let seconds = clock.elapsed_seconds();
+
+    println!("generated {} sectors in {:.3} s", n_sectors, seconds);
+    println!("  {:.1} sectors/s", n_sectors as f64 / seconds);
+    println!("  {:.1} KiB written", n_bytes as f64 / 1024.);
+
+    if settings.mesh {
+        println!(
+            "  {} meshes, {} vertices, {} triangles",
+            n_meshes, n_vertices, n_triangles
+        );
+    }
+}
+
+// A file that counts the bytes written to it.
+struct CountingFile {
+    file: File,
+    written: usize,
+}
+
+impl CountingFile {
+    fn create(path: &Path) -> CountingFile {
+        match File::create(path) {
+            Ok(file) => CountingFile { file, written: 0 },
+            Err(e) => fail(&format!("cannot create {}: {}", path.display(), e)),
+        }
+    }
+}
+
+impl Write for CountingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written += n;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,109 @@
+//! Generates a region of the world and renders it
+//! into a PNG image on the CPU, without opening a
+//! window.
+//!
+//! Usage:
+//!
+//! ```text
+//! render <seed> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z> <x> <y> <z> <pitch> <yaw> <out_file>
+//!        [--size <width> <height>] [--workers <n>]
+//! ```
+//!
+//! The minimum sector index is inclusive and the
+//! maximum is exclusive. The camera looks from the
+//! world position ``x``, ``y``, ``z``, turned by
+//! ``pitch`` and ``yaw`` degrees like the player.
+//! The same arguments always produce the same image,
+//! so it can be compared against an earlier one.
+
+use sandbox::{
+    cli::{fail, Args},
+    entity::{
+        camera::Camera,
+        player::{Player, EYE_HEIGHT},
+        sector::GenController,
+    },
+    game::FOV,
+    maths::matrix::{Projection, Transform},
+    render::Rasterizer,
+    resource::TextureManager,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+const USAGE: &str = "usage: render <seed> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z> \
+                     <x> <y> <z> <pitch> <yaw> <out_file> \
+                     [--size <width> <height>] [--workers <n>]";
+
+fn main() {
+    let args = Args::from_env(USAGE, 13);
+    let eye: (f32, f32, f32) = (args.get(7), args.get(8), args.get(9));
+    let feet = (eye.0, eye.1 - EYE_HEIGHT, eye.2);
+    let pitch: f32 = args.get(10);
+    let yaw: f32 = args.get(11);
+    let out_file = PathBuf::from(args.get::<String>(12));
+
+    let mut settings = args.gen_settings();
+    settings.lods = false;
+
+    let (mut width, mut height) = (640, 360);
+
+    let mut options = args.options(13);
+    while let Some(option) = options.next() {
+        match option {
+            "--size" => {
+                width = options.value();
+                height = options.value();
+            }
+            _ => options.shared(option, &mut settings),
+        }
+    }
+
+    if width == 0 || height == 0 {
+        fail("the image must not be empty");
+    }
+
+    println!("generating {} sectors...", settings.region.len());
+
+    let atlas = TextureManager::load_terrain_atlas()
+        .unwrap_or_else(|e| fail(&format!("cannot load the terrain textures: {}", e)));
+    let textures = TextureManager::terrain_textures(&atlas, atlas.layout());
+    let generator = GenController::launch(textures, settings);
+
+    let player = Player::with_pos_rot(feet, (pitch.to_radians(), yaw.to_radians()));
+    let mut cam = Camera::new();
+    cam.snap_to(&player);
+
+    let projection = Projection::new(FOV, width as f32 / height as f32, 0.1, 1000.0);
+
+    let mut rasterizer = Rasterizer::new(
+        &atlas,
+        width,
+        height,
+        &projection.to_matrix(),
+        &cam.to_matrix(),
+    );
+
+    // The sectors arrive in any order, but the depth
+    // test makes the image independent of it.
+    for message in generator.receiver().iter() {
+        if let Some(pre_geo) = &message.pre_geometry {
+            rasterizer.draw(&message.world_pos.translation(), pre_geo);
+        }
+    }
+
+    let mut w = BufWriter::new(
+        File::create(&out_file)
+            .unwrap_or_else(|e| fail(&format!("cannot create {}: {}", out_file.display(), e))),
+    );
+
+    let image = rasterizer.into_image();
+    if let Err(e) = image.write_png(&mut w).and_then(|_| w.flush()) {
+        fail(&format!("cannot write {}: {}", out_file.display(), e));
+    }
+
+    println!("rendered {}", out_file.display());
+}
@@ -0,0 +1,79 @@
+//! Implements the climate that decides the colors of
+//! tinted blocks, such as grass.
+//!
+//! The temperature and humidity of the world vary smoothly
+//! between the corners of a coarse grid, each of which takes
+//! a pseudo-random value. Together, they pick a color out of
+//! a small palette: dry places are yellowish, wet ones lush,
+//! and cold ones bluish.
+//!
+//! The climate depends on nothing but the position, so it
+//! is the same in every world.
+
+use crate::util::hash;
+
+// The edge length of a climate grid cell in blocks.
+const CLIMATE_CELL: f32 = 96.;
+
+// The seed that distinguishes climate hashes from other hashes.
+const CLIMATE_SEED: u32 = 0xB10E;
+
+// The grass colors at the ends of the climate ranges.
+const GRASS_DRY: [f32; 3] = [0.75, 0.71, 0.38];
+const GRASS_LUSH: [f32; 3] = [0.38, 0.67, 0.24];
+const GRASS_COLD: [f32; 3] = [0.45, 0.63, 0.55];
+
+/// Return the color of grass at the given world X and Z
+/// position.
+///
+/// The position need not be a whole block, so that the
+/// color can be sampled at the corners of faces.
+pub fn grass_color(x: f32, z: f32) -> [f32; 3] {
+    let [temperature, humidity] = climate(x, z);
+
+    let temperate = lerp3(GRASS_DRY, GRASS_LUSH, humidity);
+    lerp3(GRASS_COLD, temperate, temperature)
+}
+
+/// Return the temperature and humidity at the given world
+/// X and Z position, both in the range [0, 1].
+pub fn climate(x: f32, z: f32) -> [f32; 2] {
+    let (gx, gz) = (x / CLIMATE_CELL, z / CLIMATE_CELL);
+    let (cx, cz) = (gx.floor(), gz.floor());
+
+    // Interpolate smoothly between the four corners of the
+    // grid cell, so that the climate has no visible seams.
+    let s = smoothstep(gx - cx);
+    let t = smoothstep(gz - cz);
+    let (cx, cz) = (cx as i32, cz as i32);
+
+    let mut result = [0.; 2];
+    for (channel, value) in result.iter_mut().enumerate() {
+        let corner = |dx: i32, dz: i32| {
+            let h = hash::hash3(cx + dx, channel as i32, cz + dz, CLIMATE_SEED);
+            (h & 0xFFFF) as f32 / 0xFFFF as f32
+        };
+
+        let back = lerp(corner(0, 0), corner(1, 0), s);
+        let front = lerp(corner(0, 1), corner(1, 1), s);
+        *value = lerp(back, front, t);
+    }
+
+    result
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp3(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        lerp(a[0], b[0], t),
+        lerp(a[1], b[1], t),
+        lerp(a[2], b[2], t),
+    ]
+}
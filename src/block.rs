@@ -1,57 +1,475 @@
 //! Provides the building blocks and materials for the game.
 
-use crate::side::Side;
+use crate::{maths::aabb::Aabb, side::Side};
+use std::io;
 
 /// A type that represents the index of a block texture tile
 /// in the texture atlas.
-type BlockTextureID = u32;
+pub type BlockTextureID = u32;
 
-/// All types of voxels in the game.
+// The number of distinct blocks.
+const BLOCK_COUNT: usize = Block::ALL.len();
+
+// The largest number of tiles that a side of a block may
+// choose from, the main tile included.
+const MAX_VARIANTS: usize = 4;
+
+/// The number of tiles in a set of connected textures,
+/// one for every distinct way in which a face can connect
+/// to its neighbors. See ``connection_tile``.
+pub const CONNECTED_TILES: usize = 47;
+
+// Every distinct neighbor mask, in the order of the tiles.
+#[rustfmt::skip]
+const CONNECTIONS: [u8; CONNECTED_TILES] = [
+    0x00, 0x01, 0x04, 0x05, 0x07, 0x10, 0x11, 0x14, 0x15, 0x17, 0x1C, 0x1D,
+    0x1F, 0x40, 0x41, 0x44, 0x45, 0x47, 0x50, 0x51, 0x54, 0x55, 0x57, 0x5C,
+    0x5D, 0x5F, 0x70, 0x71, 0x74, 0x75, 0x77, 0x7C, 0x7D, 0x7F, 0xC1, 0xC5,
+    0xC7, 0xD1, 0xD5, 0xD7, 0xDD, 0xDF, 0xF1, 0xF5, 0xF7, 0xFD, 0xFF,
+];
+
+/// Returns the index of the tile in a set of connected
+/// textures that shows the connections in ``neighbors``.
+///
+/// Each bit of ``neighbors`` is set if the face connects to
+/// its neighbor in one direction, as seen on the texture.
+/// Bit zero is the neighbor above it, and the following bits
+/// go around clockwise: the upper right, right, lower right,
+/// lower, lower left, left and upper left neighbors.
+///
+/// A diagonal neighbor only matters if both neighbors next
+/// to it connect, since the corner of the border is drawn
+/// otherwise anyway. That leaves ``CONNECTED_TILES`` masks,
+/// and their tiles are ordered by ascending mask.
+pub fn connection_tile(neighbors: u8) -> usize {
+    let mut mask = neighbors & 0b0101_0101;
+
+    for corner in &[1, 3, 5, 7] {
+        let sides = 1 << (corner - 1) | 1 << ((corner + 1) % 8);
+
+        if neighbors & (1 << corner) != 0 && neighbors & sides == sides {
+            mask |= 1 << corner;
+        }
+    }
+
+    CONNECTIONS.binary_search(&mask).unwrap()
+}
+
+/// Describes how the texture of a block is colored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tint {
+    /// The texture is shown as it is.
+    None,
+
+    /// The texture is multiplied by a fixed color.
+    Constant([f32; 3]),
+
+    /// The texture is multiplied by the grass color of
+    /// the climate at the block. See ``biome::grass_color``.
+    Biome,
+
+    /// The brightness of the texture varies from block to
+    /// block by up to the given fraction, based on a hash
+    /// of the block's position.
+    Variation(f32),
+}
+
+/// Describes how the texture of a block may be turned on
+/// a face, so that large areas of the same block do not
+/// show an obvious repeating pattern.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Orientations {
+    /// The texture is always upright.
+    Fixed,
+
+    /// The texture may be mirrored from left to right, which
+    /// keeps its top at the top.
+    Mirror,
+
+    /// The texture may be turned by any number of quarter
+    /// turns.
+    Rotate,
+
+    /// The texture may be turned by any number of quarter
+    /// turns, and mirrored.
+    RotateMirror,
+}
+
+impl Orientations {
+    /// Returns the number of distinct orientations allowed.
+    pub fn count(self) -> u32 {
+        use Orientations::*;
+
+        match self {
+            Fixed => 1,
+            Mirror => 2,
+            Rotate => 4,
+            RotateMirror => 8,
+        }
+    }
+
+    /// Returns the allowed orientation with the number ``n``,
+    /// which wraps around at ``count``.
+    ///
+    /// The orientation number zero is always upright.
+    pub fn get(self, n: u32) -> Orientation {
+        let n = n % self.count();
+
+        match self {
+            Orientations::Mirror => Orientation {
+                quarter_turns: 0,
+                mirrored: n == 1,
+            },
+            _ => Orientation {
+                quarter_turns: (n % 4) as u8,
+                mirrored: n >= 4,
+            },
+        }
+    }
+}
+
+/// One way of laying a texture onto a face.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Orientation {
+    /// The number of quarter turns, between zero and three,
+    /// that the texture is turned by.
+    pub quarter_turns: u8,
+
+    /// Whether the texture is mirrored from left to right
+    /// before it is turned.
+    pub mirrored: bool,
+}
+
+/// All types of voxels in the game.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 pub enum Block {
+    #[default]
     Air,
     TestBlock,
     Stone,
     Soil,
     Grass,
+    Torch,
+    Bricks,
+    Slab,
 }
 
 impl Block {
-    /// Returns the texture ID for the given side of this block.
+    /// All blocks, in the order of their IDs.
+    pub const ALL: &[Block] = &[
+        Block::Air,
+        Block::TestBlock,
+        Block::Stone,
+        Block::Soil,
+        Block::Grass,
+        Block::Torch,
+        Block::Bricks,
+        Block::Slab,
+    ];
+
+    /// Returns the name of the texture tile for the given
+    /// side of this block, or ``None`` for air.
     ///
-    /// Texture IDs start at zero, in the upper left corner of
-    /// the texture atlas.
-    /// They increase from left to right across the atlas.
-    /// At the end of a row, they wrap onto the next "line".
-    pub fn texture_id(self, side: Side) -> BlockTextureID {
+    /// The name is the file name of the tile image, without
+    /// its extension. ``BlockTextures`` resolves the names to
+    /// tile IDs once the texture atlas has been built.
+    pub fn texture_name(self, side: Side) -> Option<&'static str> {
+        use Block::*;
+        use Side::*;
+
+        let name = match (self, side) {
+            (Air, _) => return None,
+            (TestBlock, _) => "test",
+            (Stone, _) => "stone",
+            (Soil, _) => "soil",
+            (Grass, Top) => "grass_top",
+            (Grass, Bottom) => "soil",
+            (Grass, _) => "grass_side",
+            (Torch, _) => "torch",
+            (Bricks, _) => "bricks_connected_0",
+            (Slab, _) => "stone",
+        };
+
+        Some(name)
+    }
+
+    /// Returns the names of the alternative tiles for the
+    /// given side of this block, which may appear in place
+    /// of the tile named by ``texture_name``.
+    ///
+    /// Which of them a face shows depends on a hash of its
+    /// position, so it never changes.
+    pub fn texture_variants(self, side: Side) -> &'static [&'static str] {
+        use Block::*;
+
+        match (self, side) {
+            (Stone, _) => &["stone_alt"],
+            _ => &[],
+        }
+    }
+
+    /// Returns the name of the strip of connected textures of
+    /// this block, or ``None`` if each face shows its tile on
+    /// its own.
+    ///
+    /// The faces of a block with connected textures merge with
+    /// the faces of the same block next to them, so that their
+    /// borders only run around the outline of the whole area.
+    /// The strip holds ``CONNECTED_TILES`` tiles, in the order
+    /// given by ``connection_tile``, and replaces the texture,
+    /// variants and orientations of every side.
+    pub fn connected_texture(self) -> Option<&'static str> {
+        use Block::*;
+
+        match self {
+            Bricks => Some("bricks_connected"),
+            _ => None,
+        }
+    }
+
+    /// Returns the orientations that the texture of the
+    /// given side of this block may take.
+    ///
+    /// Like the variant tile, the orientation of a face
+    /// depends on a hash of its position.
+    pub fn orientations(self, side: Side) -> Orientations {
+        use Block::*;
+        use Side::*;
+
+        match (self, side) {
+            (Stone, _) => Orientations::Rotate,
+            (Soil, _) | (Grass, Top) | (Grass, Bottom) => Orientations::RotateMirror,
+            (Grass, _) => Orientations::Mirror,
+            _ => Orientations::Fixed,
+        }
+    }
+
+    /// Returns the rule that colors the texture of the given
+    /// side of this block.
+    ///
+    /// Tints let a single grayscale-ish tile take on many
+    /// colors, instead of needing a tile for each of them.
+    pub fn tint(self, side: Side) -> Tint {
         use Block::*;
         use Side::*;
 
         match (self, side) {
-            // Air has no texture, and the renderer is broken if it's asking for one.
-            (Air, _) => unreachable!(),
-            (TestBlock, _) => 16,
-            (Stone, _) => 0,
-            (Soil, _) => 1,
-            (Grass, Top) => 2,
-            (Grass, Bottom) => 1,
-            (Grass, _) => 3,
+            (Grass, Top) => Tint::Biome,
+            (Soil, _) | (Grass, Bottom) => Tint::Variation(0.1),
+            (Torch, _) => Tint::Constant([1., 0.92, 0.78]),
+            _ => Tint::None,
         }
     }
 
-    /// Returns ``true`` if the block is transparent.
+    /// Returns the numeric ID of the block.
+    ///
+    /// IDs are used to store blocks compactly, for example
+    /// in saved sectors, so they must never change.
+    pub fn id(self) -> u8 {
+        use Block::*;
+
+        match self {
+            Air => 0,
+            TestBlock => 1,
+            Stone => 2,
+            Soil => 3,
+            Grass => 4,
+            Torch => 5,
+            Bricks => 6,
+            Slab => 7,
+        }
+    }
+
+    /// Returns the block with the given numeric ID, or
+    /// ``None`` if no block has that ID.
+    pub fn from_id(id: u8) -> Option<Block> {
+        use Block::*;
+
+        match id {
+            0 => Some(Air),
+            1 => Some(TestBlock),
+            2 => Some(Stone),
+            3 => Some(Soil),
+            4 => Some(Grass),
+            5 => Some(Torch),
+            6 => Some(Bricks),
+            7 => Some(Slab),
+            _ => None,
+        }
+    }
+
+    /// Returns the box that the block fills, relative to the
+    /// lowest corner of its voxel, or ``None`` if it does not
+    /// take up any space, like air.
+    ///
+    /// The outline of a targeted block follows this shape.
+    /// Blocks that are not cubes are drawn as their shape.
+    pub fn shape(self) -> Option<Aabb> {
+        use Block::*;
+
+        match self {
+            Air => None,
+            Slab => Some(Aabb::new((0., 0., 0.), (1., 0.5, 1.))),
+            Torch => Some(Aabb::new(
+                (7. / 16., 0., 7. / 16.),
+                (9. / 16., 10. / 16., 9. / 16.),
+            )),
+            _ => Some(Aabb::new((0., 0., 0.), (1., 1., 1.))),
+        }
+    }
+
+    /// Returns the box that other boxes collide with,
+    /// relative to the lowest corner of its voxel, or
+    /// ``None`` if the block can be walked through.
+    ///
+    /// Decorations like torches have a shape, but are
+    /// not solid.
+    pub fn collision_shape(self) -> Option<Aabb> {
+        use Block::*;
+
+        match self {
+            Torch => None,
+            _ => self.shape(),
+        }
+    }
+
+    /// Returns ``true`` if the block fills its whole voxel.
+    ///
+    /// Only cubes hide the faces of the blocks next to them,
+    /// and keep light out.
+    pub fn is_cube(self) -> bool {
+        use Block::*;
+
+        !matches!(self, Air | Slab | Torch)
+    }
+
+    /// Returns ``true`` if the block is transparent, which
+    /// every block that is not a cube is.
     pub fn is_transparent(self) -> bool {
+        !self.is_cube()
+    }
+
+    /// Returns the level of block light that the block emits,
+    /// between zero (none) and fifteen.
+    pub fn light_emission(self) -> u8 {
         use Block::*;
 
         match self {
-            Air => true,
-            _ => false,
+            Torch => 14,
+            _ => 0,
         }
     }
 }
 
-impl Default for Block {
-    fn default() -> Block {
-        Block::Air
+/// The texture tiles of every side of every block.
+///
+/// Blocks refer to their textures by name, which only the
+/// texture atlas can turn into tile IDs. The resolved IDs
+/// are stored here, so that meshing never looks up names.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BlockTextures {
+    // The main tile of each side, followed by its variants.
+    ids: [[[BlockTextureID; MAX_VARIANTS]; 6]; BLOCK_COUNT],
+
+    // The number of tiles of each side, the main tile
+    // included.
+    counts: [[u8; 6]; BLOCK_COUNT],
+
+    // The strip of connected textures of each block.
+    connected: [Option<[BlockTextureID; CONNECTED_TILES]>; BLOCK_COUNT],
+}
+
+impl BlockTextures {
+    /// Look up the tiles of every side of every block with
+    /// ``tile_id``, which returns the ID of the tile with the
+    /// given name.
+    ///
+    /// An error is returned if a block refers to a tile that
+    /// ``tile_id`` does not know, or declares more variants
+    /// than can be stored.
+    pub fn resolve<F>(tile_id: F) -> io::Result<BlockTextures>
+    where
+        F: Fn(&str) -> Option<BlockTextureID>,
+    {
+        let mut ids = [[[0; MAX_VARIANTS]; 6]; BLOCK_COUNT];
+        let mut counts = [[0; 6]; BLOCK_COUNT];
+        let mut connected = [None; BLOCK_COUNT];
+
+        let lookup = |name: &str| {
+            tile_id(name).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("missing texture tile: {}", name),
+                )
+            })
+        };
+
+        for blk in Block::ALL {
+            if let Some(name) = blk.connected_texture() {
+                let mut strip = [0; CONNECTED_TILES];
+                for (i, id) in strip.iter_mut().enumerate() {
+                    *id = lookup(&format!("{}_{}", name, i))?;
+                }
+
+                connected[blk.id() as usize] = Some(strip);
+            }
+
+            for (side_idx, side) in Side::ALL.iter().enumerate() {
+                let name = match blk.texture_name(*side) {
+                    Some(name) => name,
+                    None => continue,
+                };
+
+                let variants = blk.texture_variants(*side);
+                if variants.len() >= MAX_VARIANTS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("too many texture variants: {:?} {:?}", blk, side),
+                    ));
+                }
+
+                let slot = &mut ids[blk.id() as usize][side_idx];
+                for (id, name) in slot.iter_mut().zip(Some(&name).into_iter().chain(variants)) {
+                    *id = lookup(name)?;
+                }
+
+                counts[blk.id() as usize][side_idx] = 1 + variants.len() as u8;
+            }
+        }
+
+        Ok(BlockTextures {
+            ids,
+            counts,
+            connected,
+        })
+    }
+
+    /// Returns the texture ID of the main tile for the given
+    /// side of ``blk``.
+    ///
+    /// Texture IDs start at zero, in the upper left corner of
+    /// the texture atlas.
+    /// They increase from left to right across the atlas.
+    /// At the end of a row, they wrap onto the next "line".
+    pub fn texture_id(&self, blk: Block, side: Side) -> BlockTextureID {
+        self.variants(blk, side)[0]
+    }
+
+    /// Returns the texture IDs of all tiles that the given
+    /// side of ``blk`` may show, starting with the main tile.
+    pub fn variants(&self, blk: Block, side: Side) -> &[BlockTextureID] {
+        // Air has no texture, and the renderer is broken if it's asking for one.
+        debug_assert_ne!(blk, Block::Air);
+
+        // ``Side::ALL`` is in declaration order, so the
+        // discriminant is the index that ``resolve`` used.
+        let (blk, side) = (blk.id() as usize, side as usize);
+        &self.ids[blk][side][..self.counts[blk][side] as usize]
+    }
+
+    /// Returns the texture IDs of the strip of connected
+    /// textures of ``blk``, if it has one.
+    pub fn connected(&self, blk: Block) -> Option<&[BlockTextureID; CONNECTED_TILES]> {
+        self.connected[blk.id() as usize].as_ref()
     }
 }
@@ -0,0 +1,125 @@
+//! Parses the arguments of the command line tools in
+//! ``src/bin``.
+//!
+//! Every tool takes the seed and the region of sectors to
+//! generate as its first seven arguments:
+//!
+//! ```text
+//! <seed> <min_x> <min_y> <min_z> <max_x> <max_y> <max_z>
+//! ```
+//!
+//! The minimum sector index is inclusive and the maximum
+//! is exclusive. They are followed by the arguments of the
+//! tool, and then by options, of which ``--workers <n>``
+//! is shared by all tools.
+//!
+//! Invalid arguments end the process with a message, since
+//! there is nothing the tools could do about them.
+
+use crate::entity::sector::{GenSettings, SectorIndex, SectorRegion};
+use std::{env, process, slice, str::FromStr};
+
+/// The arguments that a tool was started with.
+pub struct Args {
+    usage: &'static str,
+    args: Vec<String>,
+}
+
+impl Args {
+    /// Read the arguments of the process, of which at least
+    /// ``n_args`` must be given. ``usage`` is shown if they
+    /// are invalid.
+    pub fn from_env(usage: &'static str, n_args: usize) -> Args {
+        let args = Args {
+            usage,
+            args: env::args().skip(1).collect(),
+        };
+
+        if args.args.len() < n_args {
+            args.fail_usage();
+        }
+
+        args
+    }
+
+    /// Parse the argument at ``index``.
+    pub fn get<T: FromStr>(&self, index: usize) -> T {
+        self.parse(&self.args[index])
+    }
+
+    /// Create the generator settings from the seed and the
+    /// region.
+    pub fn gen_settings(&self) -> GenSettings {
+        let min = SectorIndex(self.get(1), self.get(2), self.get(3));
+        let max = SectorIndex(self.get(4), self.get(5), self.get(6));
+
+        let mut settings = GenSettings::new(SectorRegion::new(min, max));
+        settings.seed = self.get(0);
+
+        if settings.region.is_empty() {
+            fail("the region contains no sectors");
+        }
+
+        settings
+    }
+
+    /// Iterate over the options, which begin at ``index``.
+    pub fn options(&self, index: usize) -> Options<'_> {
+        Options {
+            args: self,
+            rest: self.args[index.min(self.args.len())..].iter(),
+        }
+    }
+
+    /// End the process, showing how the tool is used.
+    pub fn fail_usage(&self) -> ! {
+        fail(self.usage)
+    }
+
+    fn parse<T: FromStr>(&self, arg: &str) -> T {
+        arg.parse()
+            .unwrap_or_else(|_| fail(&format!("invalid argument: {}\n{}", arg, self.usage)))
+    }
+}
+
+/// The options of a tool, which are returned one by one.
+///
+/// The values of an option are taken with ``value``, right
+/// after the option itself.
+pub struct Options<'a> {
+    args: &'a Args,
+    rest: slice::Iter<'a, String>,
+}
+
+impl<'a> Options<'a> {
+    /// Parse the next value of the current option.
+    pub fn value<T: FromStr>(&mut self) -> T {
+        match self.rest.next() {
+            Some(arg) => self.args.parse(arg),
+            None => self.args.fail_usage(),
+        }
+    }
+
+    /// Handle an option that every tool knows, or end the
+    /// process if ``option`` is not one of them.
+    pub fn shared(&mut self, option: &str, settings: &mut GenSettings) {
+        match option {
+            "--workers" => settings.n_workers = self.value(),
+            _ => self.args.fail_usage(),
+        }
+    }
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.rest.next().map(String::as_str)
+    }
+}
+
+/// End the process with the message ``msg``.
+pub fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1);
+}
@@ -2,5 +2,6 @@
 //! such as the camera.
 
 pub mod camera;
+pub mod collision;
 pub mod player;
 pub mod sector;
@@ -12,7 +12,7 @@ use crate::maths::matrix::{Mat4x4, Rotation, Transform, Translation};
 /// inverse of its transforms, and those inverse
 /// transforms are applied to the world. The result
 /// is an notion of player movement through the world.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Camera {
     translation: Translation,
     rotation: Rotation,
@@ -33,15 +33,6 @@ impl Camera {
     }
 }
 
-impl Default for Camera {
-    fn default() -> Self {
-        Camera {
-            translation: Default::default(),
-            rotation: Default::default(),
-        }
-    }
-}
-
 impl Transform for Camera {
     fn to_matrix(&self) -> Mat4x4 {
         &(-self.rotation).to_matrix() * &(-self.translation).to_matrix()
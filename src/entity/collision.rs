@@ -0,0 +1,257 @@
+//! Moves boxes through the world without letting them pass
+//! into solid blocks.
+//!
+//! A movement is resolved one axis at a time: along each axis,
+//! the box travels until its leading face meets the shape of a
+//! block, and the rest of the movement along that axis is
+//! dropped. The other axes are unaffected, so a box that runs
+//! into a wall at an angle slides along it.
+//!
+//! Blocks that a box already overlaps never stop it, so that
+//! anything stuck inside of terrain can always move out.
+
+use super::sector::WorldCoords;
+use crate::maths::{aabb::Aabb, vector::Vec3f};
+
+/// The highest obstacle, in blocks, that a box standing on
+/// the ground steps onto instead of being stopped by it.
+pub const STEP_HEIGHT: f32 = 0.5;
+
+// Faces that are closer than this are considered to touch.
+// Boxes that only touch neither stop nor overlap each other,
+// so rounding errors never let a box slip into a block or
+// catch on the seam between two blocks.
+const EPSILON: f32 = 1e-4;
+
+// How far below a box the ground may be while it still
+// stands on it.
+const GROUND_DISTANCE: f32 = 0.01;
+
+/// Move ``bounds`` by up to ``delta`` and return the box at
+/// the position where it stops.
+///
+/// ``shape`` returns the shape that collides with the box in
+/// the voxel at the given position, such as the ``shape`` of
+/// its block, or ``None`` if nothing there can stop the box.
+/// The shape is given relative to the lowest corner of the
+/// voxel, and must not reach beyond it.
+///
+/// The vertical movement is made first. If the box then
+/// stands on the ground, it climbs obstacles of up to
+/// ``STEP_HEIGHT`` that are in the way of the horizontal
+/// movement.
+pub fn move_box<F>(bounds: Aabb, delta: Vec3f, mut shape: F) -> Aabb
+where
+    F: FnMut(WorldCoords) -> Option<Aabb>,
+{
+    let bounds = shift(bounds, 1, sweep(bounds, 1, delta.y, &mut shape));
+    let grounded = sweep(bounds, 1, -GROUND_DISTANCE, &mut shape) > -GROUND_DISTANCE;
+
+    let level = move_horizontally(bounds, delta, &mut shape);
+    if !grounded || reached(bounds, level, delta) {
+        return level;
+    }
+
+    // Lift the box, move it, and put it back down onto
+    // whatever it stepped onto.
+    let lift = sweep(bounds, 1, STEP_HEIGHT, &mut shape);
+    let raised = move_horizontally(shift(bounds, 1, lift), delta, &mut shape);
+    let stepped = shift(raised, 1, sweep(raised, 1, -lift, &mut shape));
+
+    if horizontal_distance(bounds, stepped) > horizontal_distance(bounds, level) + EPSILON {
+        stepped
+    } else {
+        level
+    }
+}
+
+// Move a box along X and then Z.
+fn move_horizontally<F>(bounds: Aabb, delta: Vec3f, shape: &mut F) -> Aabb
+where
+    F: FnMut(WorldCoords) -> Option<Aabb>,
+{
+    let bounds = shift(bounds, 0, sweep(bounds, 0, delta.x, shape));
+    shift(bounds, 2, sweep(bounds, 2, delta.z, shape))
+}
+
+// Return how far a box can move by up to ``distance`` along
+// ``axis`` before it runs into the shape in a voxel.
+fn sweep<F>(bounds: Aabb, axis: usize, distance: f32, shape: &mut F) -> f32
+where
+    F: FnMut(WorldCoords) -> Option<Aabb>,
+{
+    if distance == 0. {
+        return 0.;
+    }
+
+    let (min, max) = (coords(bounds.min), coords(bounds.max));
+
+    // The voxels whose shapes the box could touch on its way.
+    // Shapes never reach beyond their voxels.
+    let mut range = [(0, 0); 3];
+    for (i, r) in range.iter_mut().enumerate() {
+        let (lo, hi) = if i != axis {
+            (min[i], max[i])
+        } else if distance > 0. {
+            (max[i] - EPSILON, max[i] + distance)
+        } else {
+            (min[i] + distance, min[i] + EPSILON)
+        };
+
+        *r = (lo.floor() as i32, hi.ceil() as i32);
+    }
+
+    let mut allowed = distance;
+
+    for x in range[0].0..range[0].1 {
+        for y in range[1].0..range[1].1 {
+            for z in range[2].0..range[2].1 {
+                let solid = match shape(WorldCoords(x, y, z)) {
+                    Some(solid) => solid.translate(Vec3f::new(x as f32, y as f32, z as f32)),
+                    None => continue,
+                };
+                let (shape_min, shape_max) = (coords(solid.min), coords(solid.max));
+
+                // Only shapes that overlap the box across the
+                // axis of movement are in its way.
+                let across = (0..3)
+                    .filter(|i| *i != axis)
+                    .all(|i| shape_min[i] < max[i] - EPSILON && shape_max[i] > min[i] + EPSILON);
+                if !across {
+                    continue;
+                }
+
+                if distance > 0. && shape_min[axis] >= max[axis] - EPSILON {
+                    allowed = allowed.min((shape_min[axis] - max[axis]).max(0.));
+                } else if distance < 0. && shape_max[axis] <= min[axis] + EPSILON {
+                    allowed = allowed.max((shape_max[axis] - min[axis]).min(0.));
+                }
+            }
+        }
+    }
+
+    allowed
+}
+
+// Returns ``true`` if a box that moved from ``from`` to ``to``
+// went the whole horizontal part of ``delta``.
+fn reached(from: Aabb, to: Aabb, delta: Vec3f) -> bool {
+    let moved = to.min - from.min;
+    (moved.x - delta.x).abs() < EPSILON && (moved.z - delta.z).abs() < EPSILON
+}
+
+// Return the squared horizontal distance between two boxes.
+fn horizontal_distance(from: Aabb, to: Aabb) -> f32 {
+    let moved = to.min - from.min;
+    moved.x * moved.x + moved.z * moved.z
+}
+
+// Return a box moved by ``distance`` along ``axis``.
+fn shift(bounds: Aabb, axis: usize, distance: f32) -> Aabb {
+    let mut offset = [0.; 3];
+    offset[axis] = distance;
+
+    bounds.translate(Vec3f::new(offset[0], offset[1], offset[2]))
+}
+
+// Return the components of a vector as an array, so that
+// they can be picked by axis.
+fn coords(v: Vec3f) -> [f32; 3] {
+    [v.x, v.y, v.z]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    // A box the size of the player, standing on the ground at
+    // ``(x, y, z)`` with its lowest corner.
+    fn body(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::new((x, y, z), (x + 0.6, y + 1.8, z + 0.6))
+    }
+
+    // A floor of stone below ``y = 0``, with ``blocks`` on it.
+    fn world(blocks: &[(WorldCoords, Block)]) -> impl FnMut(WorldCoords) -> Option<Aabb> + '_ {
+        move |pos| {
+            let blk = match blocks.iter().find(|(p, _)| *p == pos) {
+                Some((_, blk)) => *blk,
+                None if pos.1 < 0 => Block::Stone,
+                None => Block::Air,
+            };
+
+            blk.collision_shape()
+        }
+    }
+
+    fn assert_near(a: Vec3f, b: Vec3f) {
+        let d = a - b;
+        assert!(
+            d.x.abs() < 1e-3 && d.y.abs() < 1e-3 && d.z.abs() < 1e-3,
+            "{:?} is not {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn boxes_slide_along_walls_and_stop_in_corners() {
+        let wall: Vec<_> = (-2..4)
+            .map(|z| (WorldCoords(2, 0, z), Block::Stone))
+            .chain((-2..2).map(|x| (WorldCoords(x, 0, 3), Block::Stone)))
+            .collect();
+
+        // Running into the wall at an angle keeps the motion
+        // along it.
+        let moved = move_box(body(1., 0., 0.), Vec3f::new(1., 0., 1.), world(&wall));
+        assert_near(moved.min, Vec3f::new(1.4, 0., 1.));
+
+        // In the corner, neither axis can move on.
+        let moved = move_box(body(1., 0., 1.), Vec3f::new(1., 0., 2.), world(&wall));
+        assert_near(moved.min, Vec3f::new(1.4, 0., 2.4));
+    }
+
+    #[test]
+    fn boxes_cross_seams_between_blocks() {
+        // The floor is made of many blocks, and a wall of
+        // separate blocks runs right next to the box.
+        let wall: Vec<_> = (-2..8)
+            .map(|x| (WorldCoords(x, 0, 1), Block::Stone))
+            .collect();
+        let start = body(0., 0., 0.4);
+
+        let moved = move_box(start, Vec3f::new(5.5, 0., 0.), world(&wall));
+        assert_near(moved.min, Vec3f::new(5.5, 0., 0.4));
+
+        let moved = move_box(moved, Vec3f::new(-5.5, -0.5, 0.), world(&wall));
+        assert_near(moved.min, start.min);
+    }
+
+    #[test]
+    fn boxes_step_up_onto_slabs() {
+        let slab = [(WorldCoords(1, 0, 0), Block::Slab)];
+
+        let moved = move_box(body(0.2, 0., 0.2), Vec3f::new(0.5, 0., 0.), world(&slab));
+        assert_near(moved.min, Vec3f::new(0.7, 0.5, 0.2));
+
+        // Stepping needs ground to stand on.
+        let moved = move_box(body(0.2, 0.1, 0.2), Vec3f::new(0.5, 0., 0.), world(&slab));
+        assert_near(moved.min, Vec3f::new(0.4, 0.1, 0.2));
+    }
+
+    #[test]
+    fn boxes_do_not_step_onto_full_blocks() {
+        let block = [(WorldCoords(1, 0, 0), Block::Stone)];
+
+        let moved = move_box(body(0.2, 0., 0.2), Vec3f::new(0.5, 0., 0.), world(&block));
+        assert_near(moved.min, Vec3f::new(0.4, 0., 0.2));
+    }
+
+    #[test]
+    fn boxes_walk_through_torches() {
+        let torch = [(WorldCoords(1, 0, 0), Block::Torch)];
+
+        let moved = move_box(body(0.2, 0., 0.2), Vec3f::new(2., 0., 0.), world(&torch));
+        assert_near(moved.min, Vec3f::new(2.2, 0., 0.2));
+    }
+}
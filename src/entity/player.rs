@@ -1,13 +1,28 @@
 //! Provides a representation of game players
 //! in the world.
 
-use super::camera::CameraTarget;
-use crate::maths::{
-    matrix::{Rotation, Translation},
-    vector::{Vec2f, Vec3f},
+use super::{camera::CameraTarget, collision, sector::WorldCoords};
+use crate::{
+    block::Block,
+    maths::{
+        aabb::Aabb,
+        matrix::{Rotation, Translation},
+        vector::{Vec2f, Vec3f},
+    },
 };
 use std::f32::consts::{FRAC_PI_2, PI};
 
+/// The width of the player along the X and Z axes,
+/// in blocks.
+pub const PLAYER_WIDTH: f32 = 0.6;
+
+/// The height of the player, in blocks.
+pub const PLAYER_HEIGHT: f32 = 1.8;
+
+/// How far the player's eyes are above its feet,
+/// in blocks.
+pub const EYE_HEIGHT: f32 = 1.62;
+
 /// Represents a single player's position
 /// and attributes.
 pub struct Player {
@@ -40,27 +55,98 @@ impl Player {
         Self::with_pos((0., 0., 0.))
     }
 
-    /// Move the player by the given delta.
-    pub fn slide(&mut self, delta: impl Into<Vec3f>) {
-        self.translation.offset += delta.into();
+    /// Return the position of the player's feet in
+    /// the world.
+    pub fn position(&self) -> Vec3f {
+        self.translation.offset
+    }
+
+    /// Return the position of the player's eyes, from
+    /// which the camera looks. They are ``EYE_HEIGHT``
+    /// above its feet.
+    pub fn eye_position(&self) -> Vec3f {
+        self.translation.offset + Vec3f::new(0., EYE_HEIGHT, 0.)
+    }
+
+    /// Return the unit vector in the direction that the
+    /// player looks in.
+    pub fn look_direction(&self) -> Vec3f {
+        let (rx, ry) = self.rx_ry();
+
+        // Unturned, the player looks along negative Z. The
+        // pitch tilts that up, and the yaw turns it left.
+        Vec3f::new(-ry.sin() * rx.cos(), rx.sin(), -ry.cos() * rx.cos())
+    }
+
+    /// Return the box that the player occupies, and that
+    /// collides with blocks.
+    ///
+    /// The box is ``PLAYER_WIDTH`` wide and ``PLAYER_HEIGHT``
+    /// tall, with the player's feet in the middle of its
+    /// bottom.
+    pub fn bounds(&self) -> Aabb {
+        let feet = self.position();
+        let half_width = PLAYER_WIDTH / 2.;
+
+        Aabb::new(
+            (feet.x - half_width, feet.y, feet.z - half_width),
+            (
+                feet.x + half_width,
+                feet.y + PLAYER_HEIGHT,
+                feet.z + half_width,
+            ),
+        )
+    }
+
+    /// Returns ``true`` if the block at ``pos`` would
+    /// overlap the box that the player occupies.
+    ///
+    /// Boxes that only touch do not overlap.
+    pub fn overlaps_block(&self, pos: WorldCoords) -> bool {
+        let Aabb { min, max } = self.bounds();
+        let WorldCoords(x, y, z) = pos;
+        let overlaps = |lo: f32, hi: f32, block: i32| lo < (block + 1) as f32 && hi > block as f32;
+
+        overlaps(min.x, max.x, x) && overlaps(min.y, max.y, y) && overlaps(min.z, max.z, z)
+    }
+
+    /// Move the player by the given delta, as far as the
+    /// blocks in the way let it. See ``collision::move_box``.
+    ///
+    /// ``block`` returns the block at the given position,
+    /// or ``None`` if it is not known.
+    pub fn slide<F>(&mut self, delta: impl Into<Vec3f>, mut block: F)
+    where
+        F: FnMut(WorldCoords) -> Option<Block>,
+    {
+        let bounds = self.bounds();
+        let moved = collision::move_box(bounds, delta.into(), |pos| block(pos)?.collision_shape());
+
+        self.translation.offset += moved.min - bounds.min;
     }
 
-    /// Move the player in the *relative* X direction by the given delta.
-    pub fn move_x(&mut self, delta: f32) {
+    /// Move the player in the *relative* X direction by the given delta,
+    /// as far as the blocks in the way let it. See ``slide``.
+    pub fn move_x<F>(&mut self, delta: f32, block: F)
+    where
+        F: FnMut(WorldCoords) -> Option<Block>,
+    {
         let (_, ry) = self.rx_ry();
         let rot = -ry;
 
-        self.translation.offset.x += delta * rot.cos();
-        self.translation.offset.z += delta * rot.sin();
+        self.slide((delta * rot.cos(), 0., delta * rot.sin()), block);
     }
 
-    /// Move the player in the *relative* Z direction by the given delta.
-    pub fn move_z(&mut self, delta: f32) {
+    /// Move the player in the *relative* Z direction by the given delta,
+    /// as far as the blocks in the way let it. See ``slide``.
+    pub fn move_z<F>(&mut self, delta: f32, block: F)
+    where
+        F: FnMut(WorldCoords) -> Option<Block>,
+    {
         let (_, ry) = self.rx_ry();
         let rot = FRAC_PI_2 - ry;
 
-        self.translation.offset.x += delta * rot.cos();
-        self.translation.offset.z += delta * rot.sin();
+        self.slide((delta * rot.cos(), 0., delta * rot.sin()), block);
     }
 
     /// Rotate the player by the given delta.
@@ -69,11 +155,7 @@ impl Player {
     pub fn spin(&mut self, delta: impl Into<Vec2f>) {
         self.rotation.tilt += delta.into();
 
-        if self.rotation.tilt.x < -FRAC_PI_2 {
-            self.rotation.tilt.x = -FRAC_PI_2;
-        } else if self.rotation.tilt.x > FRAC_PI_2 {
-            self.rotation.tilt.x = FRAC_PI_2;
-        }
+        self.rotation.tilt.x = self.rotation.tilt.x.clamp(-FRAC_PI_2, FRAC_PI_2);
 
         if self.rotation.tilt.y < 0. {
             self.rotation.tilt.y += 2. * PI;
@@ -91,7 +173,7 @@ impl Player {
 
 impl CameraTarget for Player {
     fn cam_translation(&self) -> Translation {
-        self.translation
+        Translation::new(self.eye_position())
     }
 
     fn cam_rotation(&self) -> Rotation {
@@ -8,22 +8,58 @@
 //! is associated with one *Vertex Array Object (VAO)* in
 //! OpenGL.
 
+mod culling;
 mod data;
+mod export;
 mod generation;
+mod heightmap;
+mod light;
 mod meshgen;
+mod pipeline;
+mod raycast;
+mod save;
+mod visibility;
 
-use crate::maths::{
-    matrix::{Mat4x4, Transform, Translation},
-    vector::Vec3f,
+use crate::{
+    block::Block,
+    maths::{
+        matrix::{Mat4x4, Transform, Translation},
+        vector::Vec3f,
+    },
+    render::{MeshId, Renderer},
+    side::Side,
 };
-use data::{SectorData, SECTOR_DIM_EXCL};
-use generation::GenController;
-use luminance::{
-    context::GraphicsContext,
-    tess::{Mode, Tess, TessBuilder},
+pub use culling::CullStats;
+pub use data::SectorData;
+use data::{SectorCoords, SECTOR_DIM, SECTOR_DIM_EXCL, SECTOR_PAD};
+pub use export::{write_glb, write_mtl, write_obj, MATERIAL_NAME};
+pub use generation::{
+    GenController, GenSettings, Message, DEFAULT_SEED, DEFAULT_VERTICAL_RANGE, HORIZONTAL_RANGE,
+    N_WORKERS,
 };
-use png::OutputInfo;
-use std::collections::hash_map::{self, HashMap};
+pub use heightmap::{ColumnIndex, Heightmap};
+use light::LightAccess;
+pub use light::{LightChannel, LightData, MAX_LIGHT};
+pub use meshgen::{
+    gen_terrain, gen_terrain_greedy, gen_terrain_lod, lod_for_distance, pack_vertex, unpack_vertex,
+    MeshNeighborhood, MeshSource, PaddedSector, PreGeometry, TerrainTextures, TextureLayout,
+    VertexFormat, Vertices, LOD_LEVELS,
+};
+pub use raycast::{raycast, RayHit};
+pub use save::{read_sector, sector_file_name, write_sector};
+use std::{
+    collections::{
+        hash_map::{self, HashMap},
+        HashSet, VecDeque,
+    },
+    mem,
+    ops::Range,
+};
+pub use visibility::{visible_sectors, FaceConnections, VisibleSet};
+
+/// The number of sectors whose coarser levels of detail
+/// ``SectorManager::remesh_sectors`` meshes again per call.
+const LOD_REMESH_BUDGET: usize = 2;
 
 /// Represents the global position of a ``Sector``.
 /// Each integer increment represents one sector.
@@ -31,33 +67,250 @@ use std::collections::hash_map::{self, HashMap};
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct SectorIndex(pub i32, pub i32, pub i32);
 
+impl SectorIndex {
+    /// Return the world position of the center
+    /// of the sector.
+    pub fn center(self) -> Vec3f {
+        let SectorIndex(x, y, z) = self;
+        let dim = SECTOR_DIM_EXCL as f32;
+
+        Vec3f::new(x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5) * dim
+    }
+
+    /// Return the world positions of the lower left back
+    /// and the upper right front corner of the sector.
+    pub fn bounds(self) -> (Vec3f, Vec3f) {
+        let SectorIndex(x, y, z) = self;
+        let dim = SECTOR_DIM_EXCL as f32;
+        let min = Vec3f::new(x as f32, y as f32, z as f32) * dim;
+
+        (min, min + Vec3f::new(dim, dim, dim))
+    }
+
+    /// Return the transformation that places the mesh of
+    /// the sector in the world.
+    ///
+    /// Meshes are built in padded sector coordinates, so
+    /// they are shifted by the padding, and the first voxel
+    /// inside the sector lies exactly on the sector's world
+    /// origin.
+    pub fn translation(self) -> Mat4x4 {
+        let SectorIndex(x, y, z) = self;
+        let pos = Vec3f::new(x as f32, y as f32, z as f32) * SECTOR_DIM_EXCL as f32
+            - Vec3f::new(1., 1., 1.) * SECTOR_PAD as f32;
+
+        Translation::new(pos).to_matrix()
+    }
+
+    /// Return the index of the sector adjacent to this
+    /// one on the given ``side``.
+    pub fn neighbor(self, side: Side) -> SectorIndex {
+        let SectorIndex(x, y, z) = self;
+
+        match side {
+            Side::Front => SectorIndex(x, y, z + 1),
+            Side::Back => SectorIndex(x, y, z - 1),
+            Side::RightSide => SectorIndex(x + 1, y, z),
+            Side::LeftSide => SectorIndex(x - 1, y, z),
+            Side::Top => SectorIndex(x, y + 1, z),
+            Side::Bottom => SectorIndex(x, y - 1, z),
+        }
+    }
+
+    /// Iterate over this sector and the 26 sectors around it.
+    fn around(self) -> impl Iterator<Item = SectorIndex> {
+        let SectorIndex(x, y, z) = self;
+
+        (x - 1..x + 2).flat_map(move |nx| {
+            (y - 1..y + 2).flat_map(move |ny| (z - 1..z + 2).map(move |nz| SectorIndex(nx, ny, nz)))
+        })
+    }
+}
+
+/// A box-shaped region of sectors.
+///
+/// The region includes every ``SectorIndex`` whose
+/// components are greater than or equal to those
+/// of ``min`` and less than those of ``max``.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SectorRegion {
+    pub min: SectorIndex,
+    pub max: SectorIndex,
+}
+
+impl SectorRegion {
+    /// Create a new ``SectorRegion`` from its inclusive
+    /// lower and exclusive upper bounds.
+    pub fn new(min: SectorIndex, max: SectorIndex) -> SectorRegion {
+        SectorRegion { min, max }
+    }
+
+    /// Returns ``true`` if the sector at ``index`` lies
+    /// within the region.
+    pub fn contains(&self, index: SectorIndex) -> bool {
+        let SectorIndex(x, y, z) = index;
+
+        x >= self.min.0
+            && x < self.max.0
+            && y >= self.min.1
+            && y < self.max.1
+            && z >= self.min.2
+            && z < self.max.2
+    }
+
+    /// Return the number of sectors in the region.
+    pub fn len(&self) -> usize {
+        let span = |min: i32, max: i32| (max - min).max(0) as usize;
+
+        span(self.min.0, self.max.0) * span(self.min.1, self.max.1) * span(self.min.2, self.max.2)
+    }
+
+    /// Returns ``true`` if the region contains no sectors.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over the sectors in the region.
+    ///
+    /// Vertically, sectors are visited from the top
+    /// down, so that the surface of the world is
+    /// reached before the underground.
+    pub fn iter(&self) -> impl Iterator<Item = SectorIndex> {
+        let SectorRegion { min, max } = *self;
+
+        (min.0..max.0).flat_map(move |x| {
+            (min.1..max.1)
+                .rev()
+                .flat_map(move |y| (min.2..max.2).map(move |z| SectorIndex(x, y, z)))
+        })
+    }
+}
+
+/// Represents the global position of a single voxel.
+/// Each integer increment represents one block.
+///
+/// The block at ``WorldCoords(0, 0, 0)`` occupies the
+/// unit cube between the world origin and ``(1, 1, 1)``.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct WorldCoords(pub i32, pub i32, pub i32);
+
+impl WorldCoords {
+    /// Return the position of the block that contains the
+    /// point ``pos``.
+    pub fn containing(pos: Vec3f) -> WorldCoords {
+        WorldCoords(
+            pos.x.floor() as i32,
+            pos.y.floor() as i32,
+            pos.z.floor() as i32,
+        )
+    }
+
+    /// Return the index of the ``Sector`` that owns
+    /// this block.
+    pub fn sector_index(self) -> SectorIndex {
+        let WorldCoords(x, y, z) = self;
+        let dim = SECTOR_DIM_EXCL as i32;
+
+        SectorIndex(x.div_euclid(dim), y.div_euclid(dim), z.div_euclid(dim))
+    }
+
+    /// Return the position of this block relative to the
+    /// sector that owns it.
+    pub fn sector_coords(self) -> SectorCoords {
+        let WorldCoords(x, y, z) = self;
+        let dim = SECTOR_DIM_EXCL as i32;
+        let local = |c: i32| c.rem_euclid(dim) as usize;
+
+        SectorCoords(local(x), local(y), local(z))
+    }
+
+    /// Return the world position of the block at the given
+    /// coordinates in the sector located at ``index``.
+    pub fn from_sector(index: SectorIndex, coords: SectorCoords) -> WorldCoords {
+        let SectorIndex(sx, sy, sz) = index;
+        let SectorCoords(x, y, z) = coords;
+        let dim = SECTOR_DIM_EXCL as i32;
+        let global = |s: i32, c: usize| s * dim + c as i32;
+
+        WorldCoords(global(sx, x), global(sy, y), global(sz, z))
+    }
+
+    /// Return the world position of the block at the given
+    /// padded coordinates of the sector located at ``index``.
+    ///
+    /// Coordinates in the padding range map to the blocks
+    /// of the neighboring sectors.
+    pub fn from_padded(index: SectorIndex, coords: SectorCoords) -> WorldCoords {
+        let SectorIndex(sx, sy, sz) = index;
+        let SectorCoords(x, y, z) = coords;
+        let dim = SECTOR_DIM_EXCL as i32;
+        let global = |s: i32, c: usize| s * dim + c as i32 - SECTOR_PAD as i32;
+
+        WorldCoords(global(sx, x), global(sy, y), global(sz, z))
+    }
+
+    /// Return the position of the block adjacent to this
+    /// one on the given ``side``.
+    pub fn neighbor(self, side: Side) -> WorldCoords {
+        let WorldCoords(x, y, z) = self;
+
+        match side {
+            Side::Front => WorldCoords(x, y, z + 1),
+            Side::Back => WorldCoords(x, y, z - 1),
+            Side::RightSide => WorldCoords(x + 1, y, z),
+            Side::LeftSide => WorldCoords(x - 1, y, z),
+            Side::Top => WorldCoords(x, y + 1, z),
+            Side::Bottom => WorldCoords(x, y - 1, z),
+        }
+    }
+}
+
 /// A single sector or "chunk" of the world.
 ///
 /// A ``Sector`` contains an internal array
 /// of the blocks within its bounds.
 ///
 /// It also keeps track of its position in
-/// world coordinates.
+/// world coordinates, of the light levels
+/// of its blocks, and of which of its faces
+/// can be seen through from one another.
 ///
-/// ``Sector``s optionally hold a ``Tess``
-/// that represents their mesh in graphics
-/// memory. The mesh is not generated by
-/// default on construction.
-/// To trigger mesh generation, call the
-/// ``gen_geometry`` function.
+/// ``Sector``s optionally hold the ``MeshId``
+/// of their mesh in a ``Renderer``, along with
+/// those of coarser meshes for each further
+/// level of detail. The meshes are generated
+/// and uploaded by the ``SectorManager``.
 pub struct Sector {
     translation: Mat4x4,
     data: SectorData,
-    geometry: Option<Tess>,
+    light: LightData,
+    connections: FaceConnections,
+    geometry: Option<MeshId>,
+    lod_geometry: Vec<Option<MeshId>>,
 }
 
 impl Sector {
-    /// Create a sector with the provided voxel data.
-    pub fn new(world_pos: SectorIndex, sector_data: SectorData, geometry: Option<Tess>) -> Sector {
+    /// Create a sector with the provided voxel data,
+    /// light levels and face connections.
+    ///
+    /// ``lod_geometry`` holds the meshes of the coarser
+    /// levels of detail, starting at level one. It may
+    /// be empty.
+    pub fn new(
+        world_pos: SectorIndex,
+        sector_data: SectorData,
+        light: LightData,
+        connections: FaceConnections,
+        geometry: Option<MeshId>,
+        lod_geometry: Vec<Option<MeshId>>,
+    ) -> Sector {
         Sector {
-            translation: Self::calc_mat(world_pos),
+            translation: world_pos.translation(),
             data: sector_data,
+            light,
+            connections,
             geometry,
+            lod_geometry,
         }
     }
 
@@ -72,8 +325,52 @@ impl Sector {
     /// ``Sector``s may be fully generated without any
     /// geometry, because some sectors consist of only
     /// air blocks or blocks with no visual representation.
-    pub fn geometry(&self) -> Option<&Tess> {
-        self.geometry.as_ref()
+    pub fn geometry(&self) -> Option<MeshId> {
+        self.geometry
+    }
+
+    /// Return the ``Sector``'s geometry at the given
+    /// level of detail, if it has any.
+    ///
+    /// If the level was not meshed, the closest
+    /// coarser level that was is used instead, or
+    /// the full resolution geometry if there is none.
+    pub fn lod_geometry(&self, level: usize) -> Option<MeshId> {
+        if level == 0 || self.lod_geometry.is_empty() {
+            return self.geometry();
+        }
+
+        let level = level.min(self.lod_geometry.len());
+        self.lod_geometry[level - 1]
+    }
+
+    /// Returns which faces of the ``Sector`` can be seen
+    /// through from one another.
+    pub fn connections(&self) -> FaceConnections {
+        self.connections
+    }
+
+    /// Replace the full resolution mesh of this instance,
+    /// and free the old one in ``renderer``.
+    fn replace_geometry(&mut self, renderer: &mut impl Renderer, geometry: Option<MeshId>) {
+        if let Some(mesh) = mem::replace(&mut self.geometry, geometry) {
+            renderer.drop_mesh(mesh);
+        }
+    }
+
+    /// Replace the meshes of the coarser levels of detail of
+    /// this instance, and free the old ones in ``renderer``.
+    fn replace_lod_geometry(
+        &mut self,
+        renderer: &mut impl Renderer,
+        lod_geometry: Vec<Option<MeshId>>,
+    ) {
+        for mesh in mem::replace(&mut self.lod_geometry, lod_geometry)
+            .into_iter()
+            .flatten()
+        {
+            renderer.drop_mesh(mesh);
+        }
     }
 
     /// Returns the ``SectorData`` for this instance.
@@ -81,59 +378,498 @@ impl Sector {
         &self.data
     }
 
-    fn calc_mat(pos: SectorIndex) -> Mat4x4 {
-        let SectorIndex(sx, sy, sz) = pos;
-        let pos = Vec3f::new(sx as f32, sy as f32, sz as f32) * SECTOR_DIM_EXCL as f32;
+    /// Returns the ``SectorData`` for this instance mutably.
+    fn data_mut(&mut self) -> &mut SectorData {
+        &mut self.data
+    }
 
-        Translation::new(pos).to_matrix()
+    /// Returns the light levels of this instance.
+    fn light(&self) -> &LightData {
+        &self.light
+    }
+
+    /// Returns the light levels of this instance mutably.
+    fn light_mut(&mut self) -> &mut LightData {
+        &mut self.light
     }
 }
 
 /// Stores all loaded ``Sector``s in the world.
+///
+/// The manager also maintains a ``Heightmap`` for
+/// each column of sectors that has been loaded, and
+/// remeshes sectors after their blocks are changed.
 pub struct SectorManager {
     sectors: HashMap<SectorIndex, Sector>,
+    heightmaps: HashMap<ColumnIndex, Heightmap>,
+    region: SectorRegion,
     generator: GenController,
+    textures: TerrainTextures,
+    format: VertexFormat,
+
+    // The sectors whose meshes are out of date.
+    stale: HashSet<SectorIndex>,
+
+    // The sectors whose coarser levels of detail are out of
+    // date, in the order they were remeshed in.
+    stale_lod: VecDeque<SectorIndex>,
+
+    // The sectors whose blocks or light, or those of the
+    // voxels around them, changed since they were generated.
+    edited: HashSet<SectorIndex>,
+
+    // The sector that the generator was last told to
+    // generate the surroundings of first.
+    focus: Option<SectorIndex>,
 }
 
 impl SectorManager {
     /// Create a new ``SectorManager`` with no stored
     /// ``Sectors``.
-    pub fn new(tex_info: &OutputInfo) -> SectorManager {
+    ///
+    /// Sectors are generated over the
+    /// ``DEFAULT_VERTICAL_RANGE``, and meshed with
+    /// the given ``TerrainTextures`` into vertices
+    /// of the given ``VertexFormat``.
+    pub fn new(textures: TerrainTextures, format: VertexFormat) -> SectorManager {
+        Self::with_vertical_range(textures, format, DEFAULT_VERTICAL_RANGE)
+    }
+
+    /// Create a new ``SectorManager`` with no stored
+    /// ``Sectors`` that generates the world for every
+    /// vertical sector index in ``vertical_range``.
+    pub fn with_vertical_range(
+        textures: TerrainTextures,
+        format: VertexFormat,
+        vertical_range: Range<i32>,
+    ) -> SectorManager {
+        let region = SectorRegion::new(
+            SectorIndex(HORIZONTAL_RANGE.start, vertical_range.start, HORIZONTAL_RANGE.start),
+            SectorIndex(HORIZONTAL_RANGE.end, vertical_range.end, HORIZONTAL_RANGE.end),
+        );
+
+        Self::with_region(textures, format, region)
+    }
+
+    /// Create a new ``SectorManager`` with no stored
+    /// ``Sectors`` that generates the sectors of ``region``.
+    pub fn with_region(
+        textures: TerrainTextures,
+        format: VertexFormat,
+        region: SectorRegion,
+    ) -> SectorManager {
+        let mut settings = GenSettings::new(region);
+        settings.format = format;
+
         SectorManager {
             sectors: HashMap::new(),
-            generator: GenController::launch(tex_info),
+            heightmaps: HashMap::new(),
+            region,
+            generator: GenController::launch(textures, settings),
+            textures,
+            format,
+            stale: HashSet::new(),
+            stale_lod: VecDeque::new(),
+            edited: HashSet::new(),
+            focus: None,
+        }
+    }
+
+    /// Generate the sectors closest to ``pos`` first, such
+    /// as those around the player.
+    pub fn focus(&mut self, pos: Vec3f) {
+        let index = WorldCoords::containing(pos).sector_index();
+
+        if self.focus != Some(index) {
+            self.focus = Some(index);
+            self.generator.focus(index);
         }
     }
 
     /// Finish generating a sector whose terrain and geometry
     /// has been generated in the worldgen thread, if the
     /// sector is ready.
-    pub fn finalize_sectors(&mut self, ctx: &mut impl GraphicsContext) {
-        match self.generator.receiver().try_recv() {
-            Ok(message) => {
-                let tess = message.pre_geometry.map(|pre_geo| {                    
-                    TessBuilder::new(ctx)
-                        .add_vertices(pre_geo.vertices)
-                        .set_indices(pre_geo.indices)
-                        .set_mode(Mode::Triangle)
-                        .build()
-                        .unwrap()
-                });
-
-                let new_sector = Sector::new(message.world_pos, message.sector_data, tess);
-
-                self.sectors.insert(message.world_pos, new_sector);
+    ///
+    /// The meshes of the sector are uploaded to ``renderer``.
+    ///
+    /// The generator does not know about the blocks changed
+    /// by ``set_block``. A sector that arrives next to such a
+    /// change is lit again, and it and its neighbors, whose
+    /// meshes were made without it, are remeshed by the next
+    /// call to ``remesh_sectors``.
+    pub fn finalize_sectors(&mut self, renderer: &mut impl Renderer) {
+        if let Ok(message) = self.generator.receiver().try_recv() {
+            self.finalize(message, renderer);
+        }
+    }
+
+    /// Store the sector of a ``Message`` from the generator,
+    /// and upload its meshes to ``renderer``.
+    fn finalize(&mut self, message: Message, renderer: &mut impl Renderer) {
+        let mesh = message
+            .pre_geometry
+            .map(|pre_geo| renderer.upload_mesh(pre_geo));
+        let lod_meshes = message
+            .lod_geometry
+            .into_iter()
+            .map(|pre_geo| pre_geo.map(|pre_geo| renderer.upload_mesh(pre_geo)))
+            .collect();
+
+        let new_sector = Sector::new(
+            message.world_pos,
+            message.sector_data,
+            message.light,
+            message.connections,
+            mesh,
+            lod_meshes,
+        );
+
+        self.sectors.insert(message.world_pos, new_sector);
+
+        self.heightmaps
+            .entry(message.world_pos.into())
+            .or_default()
+            .merge(&message.heightmap);
+
+        let index = message.world_pos;
+        if index.around().any(|n| self.edited.contains(&n)) {
+            self.relight(index);
+        }
+    }
+
+    /// Return the block at the given world position, or
+    /// ``None`` if the sector containing it is not loaded.
+    pub fn block(&self, pos: WorldCoords) -> Option<Block> {
+        self.sectors
+            .get(&pos.sector_index())
+            .map(|sector| *sector.data().block(pos.sector_coords()))
+    }
+
+    /// Return the light level of one channel at the given
+    /// world position, or ``None`` if the sector containing
+    /// it is not loaded.
+    pub fn light(&self, pos: WorldCoords, channel: LightChannel) -> Option<u8> {
+        self.sectors
+            .get(&pos.sector_index())
+            .map(|sector| sector.light().get(pos.sector_coords(), channel))
+    }
+
+    /// Replace the block at the given world position.
+    ///
+    /// The column's ``Heightmap`` and the light levels around
+    /// the block are updated too. Light may change in sectors
+    /// up to ``MAX_LIGHT`` blocks away.
+    ///
+    /// The meshes of the sectors that change, including the
+    /// neighbors that the block lies next to, are not updated
+    /// until ``remesh_sectors`` is called.
+    ///
+    /// Returns ``false`` if the sector containing the block
+    /// is not loaded, in which case nothing is changed.
+    pub fn set_block(&mut self, pos: WorldCoords, blk: Block) -> bool {
+        let owner = pos.sector_index();
+
+        match self.sectors.get_mut(&owner) {
+            Some(sector) => *sector.data_mut().block_mut(pos.sector_coords()) = blk,
+            None => return false,
+        }
+
+        let mut changed = HashSet::new();
+        mark_stale(&self.sectors, &mut changed, pos);
+
+        light::update_block(&mut WorldLight(&mut self.sectors, &mut changed), pos, blk);
+
+        self.stale.extend(&changed);
+        self.edited.extend(changed);
+
+        let WorldCoords(wx, wy, wz) = pos;
+        let SectorCoords(lx, _, lz) = pos.sector_coords();
+        let sectors = &self.sectors;
+
+        self.heightmaps
+            .entry(owner.into())
+            .or_default()
+            .update(lx, lz, wy, blk, |y| highest_below(sectors, wx, y, wz));
+
+        true
+    }
+
+    /// Mesh every sector whose blocks or light levels were
+    /// changed by ``set_block`` again, and replace its meshes
+    /// in ``renderer``.
+    ///
+    /// The sectors are meshed from their loaded neighbors,
+    /// and which of their faces connect is found again too.
+    ///
+    /// Only the full resolution meshes are replaced right
+    /// away. The coarser levels of detail are only seen from
+    /// afar, so they are remeshed for ``LOD_REMESH_BUDGET``
+    /// sectors per call, in the order the sectors changed.
+    pub fn remesh_sectors(&mut self, renderer: &mut impl Renderer) {
+        for index in mem::take(&mut self.stale) {
+            let source = match self.mesh_neighborhood(index) {
+                Some(source) => source,
+                None => continue,
+            };
+
+            let geometry = gen_terrain_greedy(&self.textures, &source, self.format)
+                .map(|pre_geo| renderer.upload_mesh(pre_geo));
+
+            let sector = self.sectors.get_mut(&index).unwrap();
+            sector.connections = FaceConnections::from_sector(sector.data());
+            sector.replace_geometry(renderer, geometry);
+
+            if !self.stale_lod.contains(&index) {
+                self.stale_lod.push_back(index);
             }
-            Err(_) => {}
+        }
+
+        for _ in 0..LOD_REMESH_BUDGET {
+            let index = match self.stale_lod.pop_front() {
+                Some(index) => index,
+                None => break,
+            };
+
+            let source = match self.mesh_neighborhood(index) {
+                Some(source) => source,
+                None => continue,
+            };
+
+            let lod_geometry = (1..LOD_LEVELS)
+                .map(|level| {
+                    gen_terrain_lod(&self.textures, &source, level, self.format)
+                        .map(|pre_geo| renderer.upload_mesh(pre_geo))
+                })
+                .collect();
+
+            let sector = self.sectors.get_mut(&index).unwrap();
+            sector.replace_lod_geometry(renderer, lod_geometry);
         }
     }
 
+    /// Return the world height of the highest non-air block
+    /// in the block column at the given world X and Z position.
+    ///
+    /// ``None`` is returned if there are no solid blocks in the
+    /// column, or if none of its sectors are loaded.
+    pub fn height_at(&self, x: i32, z: i32) -> Option<i32> {
+        let pos = WorldCoords(x, 0, z);
+        let SectorCoords(lx, _, lz) = pos.sector_coords();
+
+        self.heightmaps
+            .get(&pos.sector_index().into())
+            .and_then(|heightmap| heightmap.height(lx, lz))
+    }
+
+    /// Return a ``MeshNeighborhood`` that meshes the sector at
+    /// ``index`` from its own voxels and those of the loaded
+    /// sectors around it, or ``None`` if the sector is not
+    /// loaded.
+    pub fn mesh_neighborhood(&self, index: SectorIndex) -> Option<MeshNeighborhood<'_>> {
+        let sector = self.sectors.get(&index)?;
+        let SectorIndex(x, y, z) = index;
+
+        Some(MeshNeighborhood::new(
+            index,
+            sector.data(),
+            sector.light(),
+            |[dx, dy, dz]| {
+                self.sectors
+                    .get(&SectorIndex(x + dx, y + dy, z + dz))
+                    .map(|neighbor| (neighbor.data(), neighbor.light()))
+            },
+        ))
+    }
+
+    /// Light the sector at ``index`` again from the loaded
+    /// sectors, join its light with that of its neighbors,
+    /// and mark it and its neighbors stale.
+    fn relight(&mut self, index: SectorIndex) {
+        // Mirror the generator, which lets the sky through
+        // a column if nothing lies above the neighborhood.
+        let sky_limit = (index.1 + 2) * SECTOR_DIM_EXCL as i32;
+        let light = light::light_sector(
+            index,
+            |pos| self.block(pos),
+            |x, z| self.height_at(x, z).unwrap_or(i32::MIN) < sky_limit,
+        );
+        *self.sectors.get_mut(&index).unwrap().light_mut() = light;
+
+        // Light crosses the boundary of the sector in both
+        // directions, so it is spread from the voxels on both
+        // sides of it.
+        let boundary: Vec<_> = (0..SECTOR_DIM * SECTOR_DIM * SECTOR_DIM)
+            .map(|i| {
+                SectorCoords(
+                    i % SECTOR_DIM,
+                    i / SECTOR_DIM % SECTOR_DIM,
+                    i / (SECTOR_DIM * SECTOR_DIM),
+                )
+            })
+            .filter(|SectorCoords(x, y, z)| {
+                [x, y, z]
+                    .iter()
+                    .any(|c| **c <= SECTOR_PAD || **c >= SECTOR_DIM - 1 - SECTOR_PAD)
+            })
+            .map(|coords| WorldCoords::from_padded(index, coords))
+            .collect();
+        light::spread_from(
+            &mut WorldLight(&mut self.sectors, &mut self.stale),
+            &boundary,
+        );
+
+        for neighbor in index.around() {
+            if self.sectors.contains_key(&neighbor) {
+                self.stale.insert(neighbor);
+            }
+        }
+    }
+
+    /// Find the sectors that might be visible from a camera
+    /// at ``camera``. See ``visible_sectors``.
+    ///
+    /// Sectors that are still being generated count as
+    /// open space.
+    pub fn visible_sectors(&self, camera: Vec3f) -> VisibleSet {
+        let start = WorldCoords::containing(camera).sector_index();
+
+        visibility::visible_sectors(self.region, start, |index| {
+            self.sectors
+                .get(&index)
+                .map_or(FaceConnections::all(), Sector::connections)
+        })
+    }
+
+    /// Cast a ray through the loaded sectors and return the
+    /// first block that it hits. See ``raycast``.
+    ///
+    /// The ray stops without a hit when it reaches a sector
+    /// that is not loaded.
+    pub fn raycast(&self, origin: Vec3f, direction: Vec3f, max_distance: f32) -> Option<RayHit> {
+        // The sector of the last block is kept, so that it is
+        // only looked up again when the ray leaves it.
+        let mut current: Option<(SectorIndex, Option<&Sector>)> = None;
+
+        raycast::raycast(origin, direction, max_distance, |pos| {
+            let index = pos.sector_index();
+
+            let sector = match current {
+                Some((last, sector)) if last == index => sector,
+                _ => {
+                    let sector = self.sectors.get(&index);
+                    current = Some((index, sector));
+                    sector
+                }
+            };
+
+            sector.map(|sector| *sector.data().block(pos.sector_coords()))
+        })
+    }
+
+    /// Return the ``Heightmap`` of a sector column, if any
+    /// of its sectors have been loaded.
+    pub fn heightmap(&self, column: ColumnIndex) -> Option<&Heightmap> {
+        self.heightmaps.get(&column)
+    }
+
     /// Iterate over the sectors in the sector manager.
     pub fn iter(&self) -> SectorManagerIter<'_> {
         self.into_iter()
     }
 }
 
+/// Return the padded coordinates of the block at ``pos``
+/// relative to the sector at ``index``, if the block lies
+/// within that sector or its padding.
+fn padded_coords(index: SectorIndex, pos: WorldCoords) -> Option<SectorCoords> {
+    let SectorIndex(sx, sy, sz) = index;
+    let WorldCoords(wx, wy, wz) = pos;
+    let dim = SECTOR_DIM_EXCL as i32;
+    let local = |s: i32, w: i32| {
+        let c = w - s * dim + SECTOR_PAD as i32;
+
+        if c >= 0 && c < SECTOR_DIM as i32 {
+            Some(c as usize)
+        } else {
+            None
+        }
+    };
+
+    Some(SectorCoords(local(sx, wx)?, local(sy, wy)?, local(sz, wz)?))
+}
+
+/// Add every loaded sector whose mesh depends on the voxel
+/// at ``pos`` to ``stale``.
+///
+/// These are the sector that owns the voxel, and those of
+/// its neighbors whose padding the voxel lies in.
+fn mark_stale(
+    sectors: &HashMap<SectorIndex, Sector>,
+    stale: &mut HashSet<SectorIndex>,
+    pos: WorldCoords,
+) {
+    // A block near a sector boundary lies in the padding of
+    // up to seven neighbors, so every sector around the owner
+    // is checked.
+    for index in pos.sector_index().around() {
+        if padded_coords(index, pos).is_some() && sectors.contains_key(&index) {
+            stale.insert(index);
+        }
+    }
+}
+
+/// Gives the lighting algorithms access to the loaded sectors.
+///
+/// Every sector whose mesh depends on a voxel whose light
+/// changes is added to the set of stale sectors.
+struct WorldLight<'a>(
+    &'a mut HashMap<SectorIndex, Sector>,
+    &'a mut HashSet<SectorIndex>,
+);
+
+impl<'a> LightAccess for WorldLight<'a> {
+    fn block(&self, pos: WorldCoords) -> Option<Block> {
+        self.0
+            .get(&pos.sector_index())
+            .map(|sector| *sector.data().block(pos.sector_coords()))
+    }
+
+    fn light(&self, pos: WorldCoords, channel: LightChannel) -> u8 {
+        self.0
+            .get(&pos.sector_index())
+            .map_or(0, |sector| sector.light().get(pos.sector_coords(), channel))
+    }
+
+    fn set_light(&mut self, pos: WorldCoords, channel: LightChannel, level: u8) {
+        if let Some(sector) = self.0.get_mut(&pos.sector_index()) {
+            sector.light_mut().set(pos.sector_coords(), channel, level);
+            mark_stale(self.0, self.1, pos);
+        }
+    }
+}
+
+/// Scan the loaded sectors downward from just below world
+/// height ``y`` and return the height of the first non-air
+/// block in the block column at ``x`` and ``z``.
+fn highest_below(sectors: &HashMap<SectorIndex, Sector>, x: i32, y: i32, z: i32) -> Option<i32> {
+    let column = ColumnIndex::from(WorldCoords(x, y, z).sector_index());
+
+    // No solid block can lie below the lowest loaded sector.
+    let lowest = sectors
+        .keys()
+        .filter(|index| ColumnIndex::from(**index) == column)
+        .map(|index| index.1 * SECTOR_DIM_EXCL as i32)
+        .min()?;
+
+    (lowest..y).rev().find(|wy| {
+        let pos = WorldCoords(x, *wy, z);
+
+        sectors
+            .get(&pos.sector_index())
+            .is_some_and(|sector| *sector.data().block(pos.sector_coords()) != Block::Air)
+    })
+}
+
 /// An ``Iterator`` over the ``Sectors`` in a
 /// ``SectorManager``.
 pub struct SectorManagerIter<'a> {
@@ -158,3 +894,165 @@ impl<'a> IntoIterator for &'a SectorManager {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::RecordingRenderer;
+
+    // A manager of ``region``, whose sectors are all
+    // generated but not yet stored.
+    fn manager(region: SectorRegion) -> (SectorManager, HashMap<SectorIndex, Message>) {
+        let textures = TerrainTextures::for_tests(TextureLayout::Array);
+
+        let manager = SectorManager::with_region(textures, VertexFormat::Full, region);
+        let messages = manager
+            .generator
+            .receiver()
+            .iter()
+            .map(|message| (message.world_pos, message))
+            .collect();
+
+        (manager, messages)
+    }
+
+    fn vertex_count(renderer: &RecordingRenderer, mesh: Option<MeshId>) -> usize {
+        mesh.and_then(|mesh| renderer.mesh(mesh))
+            .map_or(0, |pre_geo| pre_geo.vertices.len())
+    }
+
+    #[test]
+    fn sectors_next_to_edits_are_relit_and_remeshed() {
+        let region = SectorRegion::new(SectorIndex(0, -1, 0), SectorIndex(2, 1, 1));
+        let (mut manager, mut messages) = manager(region);
+        let mut renderer = RecordingRenderer::new();
+        let (ground, sky) = (SectorIndex(0, -1, 0), SectorIndex(0, 0, 0));
+        let (next_ground, next_sky) = (SectorIndex(1, -1, 0), SectorIndex(1, 0, 0));
+
+        manager.finalize(messages.remove(&ground).unwrap(), &mut renderer);
+        manager.finalize(messages.remove(&sky).unwrap(), &mut renderer);
+
+        // Dig into the grass and place a torch on the surface,
+        // both right next to the sectors that are not loaded.
+        assert!(manager.set_block(WorldCoords(15, -1, 5), Block::Air));
+        assert!(manager.set_block(WorldCoords(15, 0, 14), Block::Torch));
+        manager.remesh_sectors(&mut renderer);
+        assert!(manager.stale.is_empty());
+
+        // Pretend that the generator saw a torch at the other
+        // end of the boundary that has been removed since.
+        let torch = Block::Torch.light_emission();
+        let mut sky_message = messages.remove(&next_sky).unwrap();
+        sky_message
+            .light
+            .set(SectorCoords(0, 0, 0), LightChannel::Block, torch - 1);
+
+        let generated = messages[&next_ground].pre_geometry.as_ref().unwrap();
+        let generated_vertices = generated.vertices.len();
+        manager.finalize(messages.remove(&next_ground).unwrap(), &mut renderer);
+        manager.finalize(sky_message, &mut renderer);
+
+        for index in &[ground, sky, next_ground, next_sky] {
+            assert!(manager.stale.contains(index));
+        }
+
+        // The torch lights the sector it was placed next to,
+        // and the light of the removed one is gone.
+        assert_eq!(
+            manager.light(WorldCoords(16, 0, 14), LightChannel::Block),
+            Some(torch - 1)
+        );
+        assert_eq!(
+            manager.light(WorldCoords(16, 0, 0), LightChannel::Block),
+            Some(0)
+        );
+
+        // The wall of the hole in the neighbor gets a face.
+        manager.remesh_sectors(&mut renderer);
+        let geometry = manager.sectors[&next_ground].geometry();
+        assert!(vertex_count(&renderer, geometry) > generated_vertices);
+    }
+
+    // The full resolution and the first coarser mesh of
+    // every sector, in a fixed order.
+    fn meshes(manager: &SectorManager) -> Vec<(Option<MeshId>, Option<MeshId>)> {
+        let mut sectors: Vec<_> = manager.iter().collect();
+        sectors.sort_by_key(|(SectorIndex(x, y, z), _)| (*x, *y, *z));
+
+        sectors
+            .iter()
+            .map(|(_, sector)| (sector.geometry(), sector.lod_geometry(1)))
+            .collect()
+    }
+
+    #[test]
+    fn coarser_levels_are_remeshed_within_the_budget() {
+        let region = SectorRegion::new(SectorIndex(0, -1, 0), SectorIndex(2, 0, 2));
+        let (mut manager, messages) = manager(region);
+        let mut renderer = RecordingRenderer::new();
+
+        for (_, message) in messages {
+            manager.finalize(message, &mut renderer);
+        }
+
+        // The grass at the corner of the four sectors lies in
+        // all of them.
+        let before = meshes(&manager);
+        assert!(manager.set_block(WorldCoords(15, -1, 15), Block::Air));
+        manager.remesh_sectors(&mut renderer);
+        let after = meshes(&manager);
+
+        let changed_lods = |after: &[(Option<MeshId>, Option<MeshId>)]| {
+            before.iter().zip(after).filter(|(a, b)| a.1 != b.1).count()
+        };
+
+        assert!(before.iter().zip(&after).all(|(a, b)| a.0 != b.0));
+        assert_eq!(changed_lods(&after), LOD_REMESH_BUDGET);
+        assert_eq!(manager.stale_lod.len(), 4 - LOD_REMESH_BUDGET);
+
+        manager.remesh_sectors(&mut renderer);
+        assert!(manager.stale_lod.is_empty());
+        assert_eq!(changed_lods(&meshes(&manager)), 4);
+    }
+
+    #[test]
+    fn heights_follow_edits_across_sectors() {
+        let region = SectorRegion::new(SectorIndex(0, -2, 0), SectorIndex(1, 1, 1));
+        let (mut manager, messages) = manager(region);
+        let mut renderer = RecordingRenderer::new();
+
+        for (_, message) in messages {
+            manager.finalize(message, &mut renderer);
+        }
+
+        // The height of every column, found block by block.
+        let dim = SECTOR_DIM_EXCL as i32;
+        let scan = |manager: &SectorManager, x: i32, z: i32| {
+            (-2 * dim..dim)
+                .rev()
+                .find(|y| manager.block(WorldCoords(x, *y, z)) != Some(Block::Air))
+        };
+
+        for (x, z) in &[(0, 0), (7, 3), (15, 15)] {
+            assert_eq!(manager.height_at(*x, *z), scan(&manager, *x, *z));
+        }
+
+        // Place a block at the top of the world and remove it.
+        let (x, z) = (7, 3);
+        let ground = manager.height_at(x, z).unwrap();
+        assert!(ground < dim - 2);
+        assert!(manager.set_block(WorldCoords(x, dim - 1, z), Block::Stone));
+        assert_eq!(manager.height_at(x, z), Some(dim - 1));
+        assert!(manager.set_block(WorldCoords(x, dim - 1, z), Block::Air));
+        assert_eq!(manager.height_at(x, z), Some(ground));
+
+        // Dig the column away from the top, down through the
+        // sectors below the ground.
+        for y in (-2 * dim..=ground).rev() {
+            assert!(manager.set_block(WorldCoords(x, y, z), Block::Air));
+            assert_eq!(manager.height_at(x, z), scan(&manager, x, z), "{}", y);
+        }
+
+        assert_eq!(manager.height_at(x, z), None);
+    }
+}
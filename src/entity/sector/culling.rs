@@ -0,0 +1,142 @@
+//! Decides which sectors need to be drawn in a frame.
+//!
+//! Sectors that lie entirely outside of the view frustum, or
+//! that cannot be seen through the sectors between them and
+//! the camera, are skipped before their draw calls are issued.
+//! The tests only look at the bounds and face connections of
+//! each sector, so they work without a graphics context.
+
+use super::{SectorIndex, VisibleSet, LOD_LEVELS};
+use crate::maths::frustum::Frustum;
+
+// How far the skirts of the coarsest level of detail reach
+// below their sector, in blocks: one cell of that level.
+const SKIRT_DEPTH: f32 = (1 << (LOD_LEVELS - 1)) as f32;
+
+/// Counts the sectors that were drawn and culled during
+/// one frame.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CullStats {
+    /// The number of sectors that passed the tests.
+    pub drawn: usize,
+
+    /// The number of sectors that were skipped because
+    /// they lie outside of the frustum.
+    pub culled: usize,
+
+    /// The number of sectors inside of the frustum that
+    /// were skipped because they are hidden.
+    pub occluded: usize,
+}
+
+impl CullStats {
+    /// Create a new ``CullStats`` without any sectors.
+    pub fn new() -> CullStats {
+        CullStats::default()
+    }
+
+    /// Returns ``true`` if the sector at ``index`` might be
+    /// visible through ``frustum`` and is in ``visible``,
+    /// and counts it as drawn, culled or occluded.
+    ///
+    /// The sector's box is grown downwards by the skirts of
+    /// its coarser meshes, which hang out of the sector.
+    pub fn check(&mut self, frustum: &Frustum, visible: &VisibleSet, index: SectorIndex) -> bool {
+        let (mut min, max) = index.bounds();
+        min.y -= SKIRT_DEPTH;
+
+        if !frustum.intersects_box(min, max) {
+            self.culled += 1;
+            false
+        } else if !visible.contains(index) {
+            self.occluded += 1;
+            false
+        } else {
+            self.drawn += 1;
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entity::sector::{visible_sectors, FaceConnections, SectorRegion},
+        maths::matrix::{Projection, Transform, Translation},
+    };
+    use std::f32::consts::FRAC_PI_2;
+
+    // A camera at ``eye`` that looks along negative Z, and
+    // sees a quarter turn in each direction.
+    fn looking_ahead(eye: (f32, f32, f32)) -> Frustum {
+        let projection = Projection::new(FRAC_PI_2, 1., 0.1, 1000.).to_matrix();
+        let view = (-Translation::new(eye)).to_matrix();
+
+        Frustum::from_matrix(&(&projection * &view))
+    }
+
+    #[test]
+    fn sectors_are_counted_as_drawn_culled_or_occluded() {
+        let frustum = looking_ahead((8., 8., 8.));
+
+        // A wall of solid sectors two sectors ahead of the
+        // camera hides everything behind it.
+        let region = SectorRegion::new(SectorIndex(-2, -2, -5), SectorIndex(3, 3, 3));
+        let visible = visible_sectors(region, SectorIndex(0, 0, 0), |index| {
+            if index.2 == -2 {
+                FaceConnections::none()
+            } else {
+                FaceConnections::all()
+            }
+        });
+
+        let mut stats = CullStats::new();
+        let checks = [
+            (SectorIndex(0, 0, 0), true),
+            (SectorIndex(0, 0, -1), true),
+            (SectorIndex(0, 0, -2), true),
+            (SectorIndex(0, 0, -3), false),
+            (SectorIndex(0, 0, 2), false),
+            (SectorIndex(-2, 0, 0), false),
+            (SectorIndex(5, 0, -2), false),
+        ];
+
+        for (index, drawn) in &checks {
+            assert_eq!(
+                stats.check(&frustum, &visible, *index),
+                *drawn,
+                "{:?}",
+                index
+            );
+        }
+
+        assert_eq!(
+            stats,
+            CullStats {
+                drawn: 3,
+                culled: 3,
+                occluded: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn skirts_keep_sectors_above_the_view_from_being_culled() {
+        let everything = VisibleSet::everything();
+
+        // The sector lies above the top plane, but a skirt
+        // that hangs out of it reaches into view.
+        let frustum = looking_ahead((0., -4., 0.));
+        let (min, max) = SectorIndex(0, 1, -1).bounds();
+        assert!(!frustum.intersects_box(min, max));
+
+        let mut stats = CullStats::new();
+        assert!(stats.check(&frustum, &everything, SectorIndex(0, 1, -1)));
+
+        // Once the skirts are out of view too, it is culled.
+        let frustum = looking_ahead((0., -4. - SKIRT_DEPTH, 0.));
+        assert!(!stats.check(&frustum, &everything, SectorIndex(0, 1, -1)));
+        assert_eq!(stats.culled, 1);
+    }
+}
@@ -8,8 +8,8 @@ use core::slice;
 /// excluding padding.
 pub const SECTOR_DIM_EXCL: usize = 16;
 
-/// The number of voxels that are shared with the neighboring
-/// sector within one sector.
+/// The number of voxels of the neighboring sectors that the
+/// meshers look at beyond each side of a sector.
 pub const SECTOR_PAD: usize = 1;
 
 /// The number of voxels along one edge of a sector, including
@@ -17,27 +17,35 @@ pub const SECTOR_PAD: usize = 1;
 pub const SECTOR_DIM: usize = SECTOR_PAD + SECTOR_DIM_EXCL + SECTOR_PAD;
 
 /// The total number of voxels in one cubic sector.
-pub const SECTOR_LEN: usize = SECTOR_DIM * SECTOR_DIM * SECTOR_DIM;
+///
+/// Sectors only store their own voxels, without padding.
+pub const SECTOR_LEN: usize = SECTOR_DIM_EXCL * SECTOR_DIM_EXCL * SECTOR_DIM_EXCL;
 
-/// The smallest component allowed in a sector space coordinate.
+/// The smallest component allowed in a padded sector coordinate.
 pub const SECTOR_MIN: usize = 0;
 
-/// The largest component allowed in a sector space coordinate.
+/// The largest component allowed in a padded sector coordinate.
 pub const SECTOR_MAX: usize = SECTOR_DIM - 1;
 
 /// Represents a position relative to the back lower left of a sector.
 ///
-/// Each triplet of integers maps to one voxel.
-#[derive(Clone, Copy, Debug)]
+/// Each triplet of integers maps to one voxel. The voxels
+/// that a sector stores range from zero to ``SECTOR_DIM_EXCL``
+/// (exclusive) along each axis.
+///
+/// The meshers use *padded* coordinates instead, which are
+/// shifted by ``SECTOR_PAD``, so that they can reach into
+/// the neighboring sectors. See ``MeshSource``.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct SectorCoords(pub usize, pub usize, pub usize);
 
 impl SectorCoords {
-    /// Return the coordinates for the neighboring block
-    /// specified by ``neighbor``, if they exist.
+    /// Return the padded coordinates for the neighboring
+    /// block specified by ``neighbor``, if they exist.
     ///
-    /// If ``self`` is already on a sector boundary and the
-    /// indicated direction points to a block outside the
-    /// valid sector range, ``None`` is returned.
+    /// If ``self`` is already on the boundary of the padding
+    /// and the indicated direction points to a block outside
+    /// the valid padded range, ``None`` is returned.
     pub fn neighbor(self, neighbor: Side) -> Option<SectorCoords> {
         let SectorCoords(x, y, z) = self;
 
@@ -89,28 +97,57 @@ impl SectorCoords {
 }
 
 /// Holds the voxel data for a sector.
+///
+/// Sectors that consist of a single type of block,
+/// such as those in the sky or deep underground,
+/// are stored compactly as that one block. The full
+/// voxel array is only allocated once a uniform
+/// sector is modified.
+#[derive(Clone)]
 pub struct SectorData {
-    blocks: [Block; SECTOR_LEN],
+    uniform: Block,
+    blocks: Option<Box<[Block; SECTOR_LEN]>>,
 }
 
 impl SectorData {
     /// Create a new ``SectorData`` filled with the default block.
     pub fn new() -> SectorData {
+        Self::uniform(Block::default())
+    }
+
+    /// Create a new ``SectorData`` filled entirely with ``blk``.
+    pub fn uniform(blk: Block) -> SectorData {
         SectorData {
-            blocks: [Block::default(); SECTOR_LEN],
+            uniform: blk,
+            blocks: None,
+        }
+    }
+
+    /// Return the block that fills the entire sector, if
+    /// the sector is stored uniformly.
+    ///
+    /// ``None`` does not guarantee that the sector contains
+    /// more than one type of block, only that it is not
+    /// stored compactly.
+    pub fn uniform_block(&self) -> Option<Block> {
+        match self.blocks {
+            Some(_) => None,
+            None => Some(self.uniform),
         }
     }
 
     /// Return a reference to the block located at the given position.
     pub fn block(&self, sector_coords: SectorCoords) -> &Block {
-        let idx = Self::index(sector_coords);
-        &self.blocks[idx]
+        match self.blocks {
+            Some(ref blocks) => &blocks[Self::index(sector_coords)],
+            None => &self.uniform,
+        }
     }
 
     /// Return a mutable reference to the block located at the given position.
     pub fn block_mut(&mut self, sector_coords: SectorCoords) -> &mut Block {
         let idx = Self::index(sector_coords);
-        &mut self.blocks[idx]
+        &mut self.expand()[idx]
     }
 
     /// Iterate over the entries of the ``SectorData``.
@@ -119,15 +156,27 @@ impl SectorData {
     }
 
     /// Iterate mutably over the entries of the ``SectorData``.
+    ///
+    /// A uniform ``SectorData`` allocates its full voxel
+    /// array when iterated mutably.
     pub fn iter_mut(&mut self) -> SectorIterMut<'_> {
         self.into_iter()
     }
 
+    /// Return the full voxel array, allocating it from the
+    /// uniform block first if necessary.
+    fn expand(&mut self) -> &mut [Block; SECTOR_LEN] {
+        let uniform = self.uniform;
+
+        self.blocks
+            .get_or_insert_with(|| Box::new([uniform; SECTOR_LEN]))
+    }
+
     /// Determine the array index of a particular voxel coordinate.
     fn index(sector_coords: SectorCoords) -> usize {
         let SectorCoords(x, y, z) = sector_coords;
 
-        x + y * SECTOR_DIM + z * SECTOR_DIM * SECTOR_DIM
+        x + y * SECTOR_DIM_EXCL + z * SECTOR_DIM_EXCL * SECTOR_DIM_EXCL
     }
 
     // Determine the sector coordinates that correspond to a
@@ -135,11 +184,11 @@ impl SectorData {
     fn coords(idx: usize) -> SectorCoords {
         let mut remaining = idx;
 
-        let z = remaining / (SECTOR_DIM * SECTOR_DIM);
-        remaining -= z * SECTOR_DIM * SECTOR_DIM;
+        let z = remaining / (SECTOR_DIM_EXCL * SECTOR_DIM_EXCL);
+        remaining -= z * SECTOR_DIM_EXCL * SECTOR_DIM_EXCL;
 
-        let y = remaining / SECTOR_DIM;
-        remaining -= y * SECTOR_DIM;
+        let y = remaining / SECTOR_DIM_EXCL;
+        remaining -= y * SECTOR_DIM_EXCL;
 
         let x = remaining;
 
@@ -147,12 +196,22 @@ impl SectorData {
     }
 }
 
+impl Default for SectorData {
+    fn default() -> SectorData {
+        SectorData::new()
+    }
+}
+
 /// The type of the ``Item`` that ``SectorIter`` yields.
 pub type DataEntry<'a> = (SectorCoords, &'a Block);
 
 /// Iterates over the ``Block``s in a ``SectorData`` instance.
+///
+/// For uniform sectors, the single stored block is
+/// yielded for every position.
 pub struct SectorIter<'a> {
-    inner: slice::Iter<'a, Block>,
+    inner: Option<slice::Iter<'a, Block>>,
+    uniform: &'a Block,
     current: usize,
 }
 
@@ -160,7 +219,13 @@ impl<'a> Iterator for SectorIter<'a> {
     type Item = DataEntry<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(item) = self.inner.next() {
+        let item = match self.inner {
+            Some(ref mut inner) => inner.next(),
+            None if self.current < SECTOR_LEN => Some(self.uniform),
+            None => None,
+        };
+
+        if let Some(item) = item {
             let coords = SectorData::coords(self.current);
             self.current += 1;
 
@@ -177,7 +242,8 @@ impl<'a> IntoIterator for &'a SectorData {
 
     fn into_iter(self) -> Self::IntoIter {
         SectorIter {
-            inner: self.blocks.iter(),
+            inner: self.blocks.as_ref().map(|blocks| blocks.iter()),
+            uniform: &self.uniform,
             current: 0,
         }
     }
@@ -213,7 +279,7 @@ impl<'a> IntoIterator for &'a mut SectorData {
 
     fn into_iter(self) -> Self::IntoIter {
         SectorIterMut {
-            inner: self.blocks.iter_mut(),
+            inner: self.expand().iter_mut(),
             current: 0,
         }
     }
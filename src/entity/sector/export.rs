@@ -0,0 +1,593 @@
+//! Exports sector meshes to files that other 3D tools can read.
+//!
+//! Two formats are supported: Wavefront OBJ, with a material
+//! library that references the terrain atlas, and binary glTF,
+//! which embeds the atlas.
+//!
+//! Sector meshes cannot be exported as they are. Their texture
+//! coordinates count whole tiles and are wrapped into the atlas
+//! by the fragment shader, which other tools know nothing about.
+//! Every quad is therefore split into one quad per repetition of
+//! its tile, each with ordinary atlas coordinates, and faces
+//! that cover only part of a tile, like those of torches and
+//! slabs, show the same part of it as in the game. The lighting,
+//! ambient occlusion and tint that the shader would apply are
+//! baked into vertex colors, where the format supports them.
+//!
+//! The meshes must have been generated with the atlas
+//! ``TextureLayout`` and the full ``VertexFormat``. Packed
+//! meshes are rejected with an ``InvalidInput`` error.
+
+use super::{
+    light::MAX_LIGHT,
+    meshgen::{PreGeometry, Vertices},
+    SectorIndex, SECTOR_DIM_EXCL, SECTOR_PAD,
+};
+use crate::{
+    render::{AO_MIN, LIGHT_FALLOFF},
+    vertexattrib::VoxelVertex,
+};
+use std::io::{self, Write};
+
+/// The name of the single material in exported files.
+pub const MATERIAL_NAME: &str = "terrain";
+
+// Magic numbers of the binary glTF container.
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+// OpenGL constants used by glTF samplers and buffer views.
+const GL_NEAREST: u32 = 9728;
+const GL_CLAMP_TO_EDGE: u32 = 33071;
+const GL_ARRAY_BUFFER: u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const GL_FLOAT: u32 = 5126;
+const GL_UNSIGNED_INT: u32 = 5125;
+
+// Texture coordinates are made of sixteenths of a tile, so
+// coordinates that are closer than half of that are equal.
+const UV_EPSILON: f32 = 1. / 32.;
+
+/// Write the given sector meshes as a Wavefront OBJ file.
+///
+/// Each mesh is translated to its place in the world and becomes
+/// its own object, named after its ``SectorIndex``. ``tile_size``
+/// is the size of one atlas tile, as returned by
+/// ``TextureAtlas::tile_extent``.
+/// The faces use the material ``MATERIAL_NAME`` from the library
+/// ``mtl_name``, which ``write_mtl`` creates.
+pub fn write_obj(
+    w: &mut impl Write,
+    meshes: &[(SectorIndex, &PreGeometry)],
+    tile_size: [f32; 2],
+    mtl_name: &str,
+) -> io::Result<()> {
+    writeln!(w, "mtllib {}", mtl_name)?;
+
+    // OBJ indices are one-based and count across objects.
+    let mut base = 1;
+
+    for (index, pre_geo) in meshes {
+        let mesh = ExportMesh::new(*index, pre_geo, tile_size)?;
+        let SectorIndex(x, y, z) = *index;
+
+        writeln!(w, "o sector_{}_{}_{}", x, y, z)?;
+        writeln!(w, "usemtl {}", MATERIAL_NAME)?;
+
+        for [x, y, z] in &mesh.positions {
+            writeln!(w, "v {} {} {}", x, y, z)?;
+        }
+
+        // OBJ places the origin of the texture at its
+        // lower left corner.
+        for [u, v] in &mesh.uvs {
+            writeln!(w, "vt {} {}", u, 1. - v)?;
+        }
+
+        for [x, y, z] in &mesh.normals {
+            writeln!(w, "vn {} {} {}", x, y, z)?;
+        }
+
+        for triangle in mesh.indices.chunks(3) {
+            write!(w, "f")?;
+
+            for i in triangle {
+                let i = base + i;
+                write!(w, " {}/{}/{}", i, i, i)?;
+            }
+
+            writeln!(w)?;
+        }
+
+        base += mesh.positions.len() as u32;
+    }
+
+    Ok(())
+}
+
+/// Write the material library that belongs to an OBJ
+/// file from ``write_obj``.
+///
+/// ``texture_path`` is the path of the terrain atlas,
+/// relative to the library.
+pub fn write_mtl(w: &mut impl Write, texture_path: &str) -> io::Result<()> {
+    writeln!(w, "newmtl {}", MATERIAL_NAME)?;
+    writeln!(w, "Ka 1 1 1")?;
+    writeln!(w, "Kd 1 1 1")?;
+    writeln!(w, "Ks 0 0 0")?;
+    writeln!(w, "d 1")?;
+    writeln!(w, "illum 1")?;
+    writeln!(w, "map_Kd {}", texture_path)
+}
+
+/// Write the given sector meshes as a binary glTF file.
+///
+/// All meshes are translated to their place in the world and
+/// merged into a single mesh. ``tile_size`` is the size of one
+/// atlas tile, as returned by ``TextureAtlas::tile_extent``, and ``atlas_png``
+/// holds the contents of the terrain atlas file, which is
+/// embedded into the glTF file. Light, ambient occlusion and
+/// tints are stored as vertex colors.
+pub fn write_glb(
+    w: &mut impl Write,
+    meshes: &[(SectorIndex, &PreGeometry)],
+    tile_size: [f32; 2],
+    atlas_png: &[u8],
+) -> io::Result<()> {
+    let mut mesh = ExportMesh::default();
+
+    for (index, pre_geo) in meshes {
+        mesh.append(ExportMesh::new(*index, pre_geo, tile_size)?);
+    }
+
+    // Lay out the binary chunk: one view per attribute,
+    // then the indices and the image.
+    let floats = |values: &[[f32; 3]]| words(values.iter().flatten().map(|c| c.to_bits()));
+
+    let mut bin = Vec::new();
+    let views = [
+        push_view(&mut bin, &floats(&mesh.positions)),
+        push_view(&mut bin, &floats(&mesh.normals)),
+        push_view(
+            &mut bin,
+            &words(mesh.uvs.iter().flatten().map(|c| c.to_bits())),
+        ),
+        push_view(&mut bin, &floats(&mesh.colors)),
+        push_view(&mut bin, &words(mesh.indices.iter().cloned())),
+        push_view(&mut bin, atlas_png),
+    ];
+
+    let (min, max) = mesh.bounds();
+    let n_vertices = mesh.positions.len();
+
+    let json = format!(
+        r#"{{
+  "asset": {{ "version": "2.0", "generator": "sandbox" }},
+  "scene": 0,
+  "scenes": [{{ "nodes": [0] }}],
+  "nodes": [{{ "mesh": 0, "name": "world" }}],
+  "meshes": [{{
+    "primitives": [{{
+      "attributes": {{ "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2, "COLOR_0": 3 }},
+      "indices": 4,
+      "material": 0
+    }}]
+  }}],
+  "materials": [{{
+    "name": "{material}",
+    "pbrMetallicRoughness": {{
+      "baseColorTexture": {{ "index": 0 }},
+      "metallicFactor": 0,
+      "roughnessFactor": 1
+    }}
+  }}],
+  "textures": [{{ "sampler": 0, "source": 0 }}],
+  "samplers": [{{
+    "magFilter": {nearest}, "minFilter": {nearest},
+    "wrapS": {clamp}, "wrapT": {clamp}
+  }}],
+  "images": [{{ "bufferView": 5, "mimeType": "image/png" }}],
+  "accessors": [
+    {positions},
+    {normals},
+    {uvs},
+    {colors},
+    {indices}
+  ],
+  "bufferViews": [
+    {v0},
+    {v1},
+    {v2},
+    {v3},
+    {v4},
+    {v5}
+  ],
+  "buffers": [{{ "byteLength": {bin_len} }}]
+}}"#,
+        material = MATERIAL_NAME,
+        nearest = GL_NEAREST,
+        clamp = GL_CLAMP_TO_EDGE,
+        positions = accessor_json(0, GL_FLOAT, n_vertices, "VEC3", Some((min, max))),
+        normals = accessor_json(1, GL_FLOAT, n_vertices, "VEC3", None),
+        uvs = accessor_json(2, GL_FLOAT, n_vertices, "VEC2", None),
+        colors = accessor_json(3, GL_FLOAT, n_vertices, "VEC3", None),
+        indices = accessor_json(4, GL_UNSIGNED_INT, mesh.indices.len(), "SCALAR", None),
+        v0 = view_json(views[0], Some(GL_ARRAY_BUFFER)),
+        v1 = view_json(views[1], Some(GL_ARRAY_BUFFER)),
+        v2 = view_json(views[2], Some(GL_ARRAY_BUFFER)),
+        v3 = view_json(views[3], Some(GL_ARRAY_BUFFER)),
+        v4 = view_json(views[4], Some(GL_ELEMENT_ARRAY_BUFFER)),
+        v5 = view_json(views[5], None),
+        bin_len = bin.len(),
+    );
+
+    // Both chunks must be padded to four bytes: the JSON
+    // chunk with spaces, and the binary chunk with zeros.
+    let mut json = json.into_bytes();
+    pad(&mut json, b' ');
+    pad(&mut bin, 0);
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+
+    w.write_all(&GLB_MAGIC.to_le_bytes())?;
+    w.write_all(&GLB_VERSION.to_le_bytes())?;
+    w.write_all(&(total as u32).to_le_bytes())?;
+
+    w.write_all(&(json.len() as u32).to_le_bytes())?;
+    w.write_all(&GLB_CHUNK_JSON.to_le_bytes())?;
+    w.write_all(&json)?;
+
+    w.write_all(&(bin.len() as u32).to_le_bytes())?;
+    w.write_all(&GLB_CHUNK_BIN.to_le_bytes())?;
+    w.write_all(&bin)
+}
+
+// A triangle mesh in world space, with ordinary atlas
+// coordinates and baked vertex colors.
+#[derive(Default)]
+struct ExportMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    colors: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    // Convert the mesh of the sector at ``index``.
+    //
+    // The vertices of a ``PreGeometry`` come in groups of four,
+    // one group per quad, and the indices in groups of six.
+    fn new(
+        index: SectorIndex,
+        pre_geo: &PreGeometry,
+        tile_size: [f32; 2],
+    ) -> io::Result<ExportMesh> {
+        let vertices = match &pre_geo.vertices {
+            Vertices::Full(vertices) => vertices,
+            Vertices::Packed(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "packed meshes cannot be exported",
+                ))
+            }
+        };
+
+        let mut mesh = ExportMesh::default();
+        let offset = sector_offset(index);
+
+        for (quad, indices) in vertices.chunks(4).zip(pre_geo.indices.chunks(6)) {
+            mesh.push_quad(quad, indices, offset, tile_size);
+        }
+
+        Ok(mesh)
+    }
+
+    // Add one quad, split into one quad per repetition of its tile.
+    fn push_quad(
+        &mut self,
+        quad: &[VoxelVertex],
+        indices: &[u32],
+        offset: [f32; 3],
+        tile_size: [f32; 2],
+    ) {
+        let first = indices[0] - indices[0] % 4;
+        let pattern: Vec<usize> = indices.iter().map(|i| (i - first) as usize).collect();
+
+        let uv = |v: &VoxelVertex| *v.uv;
+        let u_min = quad.iter().map(|v| uv(v)[0]).fold(f32::INFINITY, f32::min);
+        let u_max = quad
+            .iter()
+            .map(|v| uv(v)[0])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let v_min = quad.iter().map(|v| uv(v)[1]).fold(f32::INFINITY, f32::min);
+        let v_max = quad
+            .iter()
+            .map(|v| uv(v)[1])
+            .fold(f32::NEG_INFINITY, f32::max);
+
+        // Find the corners at the ends of the U and V edges,
+        // from which every position on the quad follows.
+        let corner = |u: f32, v: f32| {
+            quad.iter()
+                .find(|vertex| uv(vertex) == [u, v])
+                .map(|vertex| *vertex.pos)
+                .unwrap()
+        };
+        let origin = corner(u_min, v_min);
+        let along_u = sub(corner(u_max, v_min), origin);
+        let along_v = sub(corner(u_min, v_max), origin);
+
+        let columns = tile_cells(u_min, u_max);
+        let rows = tile_cells(v_min, v_max);
+
+        for (u_tile, u_start, u_end) in &columns {
+            for (v_tile, v_start, v_end) in &rows {
+                let base = self.positions.len() as u32;
+
+                for vertex in quad {
+                    // Move each corner onto the corner of the cell
+                    // that lies in the same direction.
+                    let [u, v] = uv(vertex);
+                    let u = if u > u_min { *u_end } else { *u_start };
+                    let v = if v > v_min { *v_end } else { *v_start };
+                    let s = (u - u_min) / (u_max - u_min);
+                    let t = (v - v_min) / (v_max - v_min);
+
+                    // A cell that is smaller than a tile shows
+                    // the same part of it that the shader would.
+                    let tile = *vertex.tile;
+                    let in_tile = [u - u_tile, v - v_tile];
+                    let world = |c: usize| origin[c] + along_u[c] * s + along_v[c] * t + offset[c];
+
+                    self.positions.push([world(0), world(1), world(2)]);
+                    self.normals.push(*vertex.normal);
+                    self.uvs.push([
+                        tile[0] + in_tile[0] * tile_size[0],
+                        tile[1] + in_tile[1] * tile_size[1],
+                    ]);
+                    self.colors.push(bake_color(vertex));
+                }
+
+                self.indices
+                    .extend(pattern.iter().map(|k| base + *k as u32));
+            }
+        }
+    }
+
+    // Add the vertices and triangles of another mesh.
+    fn append(&mut self, other: ExportMesh) {
+        let base = self.positions.len() as u32;
+
+        self.positions.extend(other.positions);
+        self.normals.extend(other.normals);
+        self.uvs.extend(other.uvs);
+        self.colors.extend(other.colors);
+        self.indices.extend(other.indices.iter().map(|i| base + i));
+    }
+
+    // Return the smallest and largest position components.
+    fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+
+        for pos in &self.positions {
+            for ((min, max), c) in min.iter_mut().zip(max.iter_mut()).zip(pos) {
+                *min = min.min(*c);
+                *max = max.max(*c);
+            }
+        }
+
+        (min, max)
+    }
+}
+
+// Split the texture coordinates from ``min`` to ``max`` at
+// the edges of the tiles, into the ranges that each lie on a
+// single repetition of the tile. Each range is returned with
+// the coordinate at which its tile begins. The last range ends
+// early on faces that do not cover whole tiles.
+fn tile_cells(min: f32, max: f32) -> Vec<(f32, f32, f32)> {
+    let mut cells = Vec::new();
+    let mut start = min;
+
+    while max - start > UV_EPSILON {
+        let tile = (start + UV_EPSILON).floor();
+        let end = if max - (tile + 1.) < UV_EPSILON {
+            max
+        } else {
+            tile + 1.
+        };
+
+        cells.push((tile, start, end));
+        start = end;
+    }
+
+    cells
+}
+
+// Return the world position of a sector's voxel space origin.
+// This is the translation of the ``Sector``.
+fn sector_offset(index: SectorIndex) -> [f32; 3] {
+    let SectorIndex(x, y, z) = index;
+    let offset = |s: i32| (s * SECTOR_DIM_EXCL as i32 - SECTOR_PAD as i32) as f32;
+
+    [offset(x), offset(y), offset(z)]
+}
+
+// Compute the color that the fragment shader would
+// multiply the texture with, apart from sun shading.
+fn bake_color(vertex: &VoxelVertex) -> [f32; 3] {
+    let occlusion = AO_MIN + (1. - AO_MIN) * *vertex.ao / 3.;
+    let [sky, block] = *vertex.light;
+    let max = MAX_LIGHT as f32;
+    let brightness = LIGHT_FALLOFF.powf(max - sky.max(block) * max);
+    let grey = occlusion * brightness;
+    let [r, g, b] = *vertex.tint;
+
+    [r * grey, g * grey, b * grey]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+// Convert 32-bit words into little-endian bytes.
+fn words(words: impl Iterator<Item = u32>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    for word in words {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+
+    bytes
+}
+
+// Append bytes to the binary chunk, aligned to four bytes,
+// and return the offset and length of the new view.
+fn push_view(bin: &mut Vec<u8>, bytes: &[u8]) -> (usize, usize) {
+    pad(bin, 0);
+
+    let offset = bin.len();
+    bin.extend_from_slice(bytes);
+
+    (offset, bytes.len())
+}
+
+fn accessor_json(
+    view: usize,
+    component_type: u32,
+    count: usize,
+    kind: &str,
+    bounds: Option<([f32; 3], [f32; 3])>,
+) -> String {
+    let bounds = match bounds {
+        Some((min, max)) => format!(
+            r#", "min": [{}, {}, {}], "max": [{}, {}, {}]"#,
+            min[0], min[1], min[2], max[0], max[1], max[2]
+        ),
+        None => String::new(),
+    };
+
+    format!(
+        r#"{{ "bufferView": {}, "componentType": {}, "count": {}, "type": "{}"{} }}"#,
+        view, component_type, count, kind, bounds
+    )
+}
+
+fn view_json(view: (usize, usize), target: Option<u32>) -> String {
+    let (offset, len) = view;
+
+    match target {
+        Some(target) => format!(
+            r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": {}, "target": {} }}"#,
+            offset, len, target
+        ),
+        None => format!(
+            r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": {} }}"#,
+            offset, len
+        ),
+    }
+}
+
+fn pad(bytes: &mut Vec<u8>, with: u8) {
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(with);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::data::SectorCoords, *};
+    use crate::{
+        block::Block,
+        entity::sector::{gen_terrain, PaddedSector, TerrainTextures, TextureLayout, VertexFormat},
+    };
+
+    // The atlas holds 16 by 16 tiles of 16 pixels.
+    const ATLAS: TextureLayout = TextureLayout::Atlas {
+        width: 256,
+        height: 256,
+        tile_size: 16,
+        padding: 0,
+    };
+    const TILE_SIZE: [f32; 2] = [1. / 16.; 2];
+
+    // Return the area of an axis-aligned rectangle, given
+    // its corners.
+    fn area<'a>(corners: impl Iterator<Item = &'a [f32]> + Clone) -> f32 {
+        let mut extents: Vec<f32> = (0..3)
+            .map(|c| {
+                let values = corners.clone().map(|p| p.get(c).cloned().unwrap_or(0.));
+                let min = values.clone().fold(f32::INFINITY, f32::min);
+                let max = values.fold(f32::NEG_INFINITY, f32::max);
+                max - min
+            })
+            .collect();
+
+        extents.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        extents[0] * extents[1]
+    }
+
+    #[test]
+    fn faces_show_their_part_of_the_tile() {
+        let index = SectorIndex(1, 0, -2);
+        let torch = SectorCoords(SECTOR_PAD + 3, SECTOR_PAD, SECTOR_PAD + 3);
+        let slab = SectorCoords(SECTOR_PAD + 9, SECTOR_PAD, SECTOR_PAD + 5);
+        let stone = SectorCoords(SECTOR_PAD + 12, SECTOR_PAD + 2, SECTOR_PAD + 12);
+        let source = PaddedSector::new(
+            index,
+            |coords| match coords {
+                c if c == torch => Block::Torch,
+                c if c == slab => Block::Slab,
+                c if c == stone => Block::Stone,
+                _ => Block::Air,
+            },
+            |_, _| MAX_LIGHT,
+        );
+
+        let textures = TerrainTextures::for_tests(ATLAS);
+        let pre_geo = gen_terrain(&textures, &source, VertexFormat::Full).unwrap();
+        let vertices = match &pre_geo.vertices {
+            Vertices::Full(vertices) => vertices,
+            Vertices::Packed(_) => unreachable!(),
+        };
+        let mesh = ExportMesh::new(index, &pre_geo, TILE_SIZE).unwrap();
+
+        // No face is lost, and none of them grows.
+        let expected: f32 = vertices
+            .chunks(4)
+            .map(|quad| area(quad.iter().map(|v| &v.pos[..])))
+            .sum();
+        let exported: f32 = mesh
+            .positions
+            .chunks(4)
+            .map(|quad| area(quad.iter().map(|p| &p[..])))
+            .sum();
+        assert!((expected - exported).abs() < 1e-4);
+
+        for (positions, uvs) in mesh.positions.chunks(4).zip(mesh.uvs.chunks(4)) {
+            // The texture is neither stretched nor squashed...
+            let uv_area = area(uvs.iter().map(|uv| &uv[..])) / (TILE_SIZE[0] * TILE_SIZE[1]);
+            let pos_area = area(positions.iter().map(|p| &p[..]));
+            assert!(pos_area > 0.);
+            assert!(
+                (uv_area - pos_area).abs() < 1e-4,
+                "{} {}",
+                uv_area,
+                pos_area
+            );
+
+            // ... and stays on a single tile.
+            for c in 0..2 {
+                let lo = uvs.iter().map(|uv| uv[c]).fold(f32::INFINITY, f32::min);
+                let hi = uvs.iter().map(|uv| uv[c]).fold(f32::NEG_INFINITY, f32::max);
+                let tile = |uv: f32| (uv / TILE_SIZE[c]).floor();
+
+                assert_eq!(tile(lo + 1e-4), tile(hi - 1e-4));
+            }
+        }
+    }
+}
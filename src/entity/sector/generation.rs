@@ -1,21 +1,127 @@
 //! Provides the game's multithreaded world generator.
+//!
+//! Generation is split into the stages listed by ``GenStage``.
+//! A scheduler thread owns the sectors that are being generated
+//! and hands individual steps to a pool of worker threads as
+//! soon as the ``Scheduler`` allows them to run.
+//!
+//! Workers only read the data of neighboring sectors, so
+//! the scheduler shares it with them instead of copying it.
+//! Sectors are meshed from their own voxels and those of
+//! their neighbors, so the scheduler keeps the data of each
+//! sector until all of its neighbors have been meshed too.
 
 use super::{
-    data::{SectorCoords, SectorData, SECTOR_MAX},
-    meshgen::{self, PreGeometry},
-    SectorIndex,
+    data::{SectorData, SECTOR_DIM_EXCL},
+    heightmap::{ColumnIndex, Heightmap},
+    light::{self, LightData, MAX_LIGHT},
+    meshgen::{self, MeshNeighborhood, PreGeometry, TerrainTextures, VertexFormat},
+    pipeline::{GenStage, Scheduler, Step},
+    visibility::FaceConnections,
+    SectorIndex, SectorRegion, WorldCoords,
 };
-use crate::block::Block;
-use png::OutputInfo;
+use crate::{block::Block, util::hash};
 use std::{
+    collections::HashMap,
     mem,
-    sync::mpsc::{self, Receiver, Sender},
+    ops::Range,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
     thread::{self, JoinHandle},
 };
 
-// The number of worker threads that should run
-// in the background, in addition to the main thread.
-const N_WORKERS: usize = 1;
+/// The number of worker threads that run in the
+/// background by default, in addition to the main
+/// thread and the scheduler thread.
+pub const N_WORKERS: usize = 1;
+
+/// The world seed that is used unless another
+/// seed is requested.
+pub const DEFAULT_SEED: u32 = 0;
+
+/// The range of vertical sector indices that are
+/// generated unless another range is requested.
+pub const DEFAULT_VERTICAL_RANGE: Range<i32> = -4..4;
+
+/// The range of horizontal sector indices that are
+/// generated along both the X and Z axes.
+pub const HORIZONTAL_RANGE: Range<i32> = -20..21;
+
+// The world height of the grass layer in the superflat world.
+const SURFACE_HEIGHT: i32 = -1;
+
+// The world height of the highest stone block in the superflat world.
+const STONE_HEIGHT: i32 = -5;
+
+// The world height of the highest block that may be carved out
+// by a cave. This leaves a roof of stone below the soil layer.
+const CAVE_CEILING: i32 = STONE_HEIGHT - 2;
+
+// Caves are placed on a grid of cubic cells with this edge length.
+const CAVE_CELL: i32 = 16;
+
+// The largest radius a cave can have.
+const CAVE_MAX_RADIUS: i32 = 6;
+
+// The seed that distinguishes cave hashes from other hashes.
+const CAVE_SEED: u32 = 0xCA7E;
+
+// The seed that decides which caves are lit by a torch.
+const TORCH_SEED: u32 = 0x7012;
+
+// Ruined brick walls stand on the surface, on a grid of
+// square columns with this edge length.
+const RUIN_CELL: i32 = 32;
+
+// The height of the tallest ruined wall.
+const RUIN_MAX_HEIGHT: i32 = 4;
+
+// The seed that decides where ruins stand, and how
+// much of them is left.
+const RUIN_SEED: u32 = 0xB41C;
+
+/// Describes which sectors are generated and how.
+#[derive(Clone, Copy, Debug)]
+pub struct GenSettings {
+    /// The sectors that are generated.
+    pub region: SectorRegion,
+
+    /// The seed that determines the generated world.
+    pub seed: u32,
+
+    /// The number of worker threads.
+    pub n_workers: usize,
+
+    /// Whether sectors are meshed after the final stage.
+    /// If ``false``, no ``Message`` has pre-geometry.
+    pub mesh: bool,
+
+    /// Whether the coarser levels of detail are meshed
+    /// as well. Has no effect unless ``mesh`` is set.
+    pub lods: bool,
+
+    /// The format of the vertices of every mesh.
+    pub format: VertexFormat,
+}
+
+impl GenSettings {
+    /// Create new ``GenSettings`` for the given region
+    /// that use the default seed and number of workers,
+    /// and mesh every sector at every level of detail
+    /// with full vertices.
+    pub fn new(region: SectorRegion) -> GenSettings {
+        GenSettings {
+            region,
+            seed: DEFAULT_SEED,
+            n_workers: N_WORKERS,
+            mesh: true,
+            lods: true,
+            format: VertexFormat::Full,
+        }
+    }
+}
 
 /// Manages generation workers.
 ///
@@ -24,6 +130,7 @@ const N_WORKERS: usize = 1;
 /// request sector generation or final cleanup.
 pub struct GenController {
     rx: Option<Receiver<Message>>,
+    control: Option<Sender<Event>>,
     handles: Vec<JoinHandle<()>>,
 }
 
@@ -31,18 +138,24 @@ impl GenController {
     /// Create a new world generation controller
     /// and start worker threads.
     ///
-    /// This method will create ``n_workers``
-    /// background threads.
+    /// This method will create as many background
+    /// threads as ``settings`` requests, plus one
+    /// thread that schedules their work.
     ///
     /// Since this manager delegates to workers
     /// that generate textured geometry, it needs
-    /// access to texture metadata.
-    pub fn launch(tex_info: &OutputInfo) -> GenController {
+    /// to know the ``TerrainTextures`` of the meshes.
+    ///
+    /// Once every sector has been sent, the channel
+    /// returned by ``receiver`` is disconnected.
+    pub fn launch(textures: TerrainTextures, settings: GenSettings) -> GenController {
         let (tx, rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
 
         GenController {
             rx: Some(rx),
-            handles: Self::spawn_threads(tx, tex_info, N_WORKERS),
+            control: Some(event_tx.clone()),
+            handles: Self::spawn_threads(tx, event_tx, event_rx, textures, settings),
         }
     }
 
@@ -53,18 +166,56 @@ impl GenController {
         self.rx.as_ref().unwrap()
     }
 
-    fn spawn_threads(tx: Sender<Message>, tex_info: &OutputInfo, n: usize) -> Vec<JoinHandle<()>> {
+    /// Generate the sectors closest to the sector at
+    /// ``focus`` first, from now on.
+    ///
+    /// The order only changes between steps, so sectors
+    /// that are already being worked on are finished first.
+    pub fn focus(&self, focus: SectorIndex) {
+        self.send(Control::Focus(focus));
+    }
+
+    /// Stop generating the sector at ``index``.
+    ///
+    /// No ``Message`` is sent for the sector, unless one
+    /// has been sent already, and its neighbors are
+    /// generated as if it lay outside of the region.
+    pub fn cancel(&self, index: SectorIndex) {
+        self.send(Control::Cancel(index));
+    }
+
+    // Pass a request on to the scheduler thread. Requests
+    // that arrive after generation has finished are of no
+    // consequence, so they are dropped.
+    fn send(&self, control: Control) {
+        let _ = self.control.as_ref().unwrap().send(Event::Control(control));
+    }
+
+    fn spawn_threads(
+        tx: Sender<Message>,
+        event_tx: Sender<Event>,
+        event_rx: Receiver<Event>,
+        textures: TerrainTextures,
+        settings: GenSettings,
+    ) -> Vec<JoinHandle<()>> {
         let mut handles = Vec::new();
 
-        for _ in 0..n {
-            let tx = tx.clone();
-            let tex_info = copy_tex_info(tex_info);
+        let (job_tx, job_rx) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        for _ in 0..settings.n_workers.max(1) {
+            let job_rx = Arc::clone(&job_rx);
+            let event_tx = event_tx.clone();
 
             handles.push(thread::spawn(move || {
-                worker_thread(tx, tex_info);
+                worker_thread(job_rx, event_tx, textures, settings);
             }));
         }
 
+        handles.push(thread::spawn(move || {
+            scheduler_thread(tx, event_rx, job_tx, settings.region);
+        }));
+
         handles
     }
 }
@@ -72,6 +223,7 @@ impl GenController {
 impl Drop for GenController {
     fn drop(&mut self) {
         mem::drop(self.rx.take());
+        mem::drop(self.control.take());
 
         for i in self.handles.drain(..) {
             i.join().unwrap();
@@ -82,73 +234,791 @@ impl Drop for GenController {
 /// Stores the data created by the worker threads.
 ///
 /// Includes the world position of the partially
-/// constructed sector, its terrain data, and
-/// — optionally — its pre-geometry.
+/// constructed sector, its terrain data, its light
+/// levels, the heightmap of its voxels, the connections
+/// between its faces, and — optionally — its
+/// pre-geometry.
+///
+/// ``lod_geometry`` holds the pre-geometry of the
+/// coarser levels of detail, starting at level one.
+/// It is empty unless they were requested.
 pub struct Message {
     pub world_pos: SectorIndex,
     pub sector_data: SectorData,
+    pub light: LightData,
+    pub heightmap: Heightmap,
+    pub connections: FaceConnections,
     pub pre_geometry: Option<PreGeometry>,
+    pub lod_geometry: Vec<Option<PreGeometry>>,
+}
+
+// A request from the main thread to the scheduler thread.
+enum Control {
+    Focus(SectorIndex),
+    Cancel(SectorIndex),
+}
+
+// Something that the scheduler thread waits for. Requests
+// share a channel with the outcomes of jobs, so that they
+// are handled while the workers are busy.
+enum Event {
+    Control(Control),
+    Outcome(Outcome),
+}
+
+// A step of work for a worker thread, along with
+// the data of the sector it applies to.
+struct Job {
+    world_pos: SectorIndex,
+    step: Step,
+    sector_data: Arc<SectorData>,
+
+    // The surroundings of the sector, for the light stage.
+    neighborhood: Option<Neighborhood>,
+
+    // The sector and its neighbors with their light
+    // levels, for meshing.
+    surroundings: Option<Surroundings>,
+}
+
+// The result of a ``Job``.
+enum Outcome {
+    // A stage was completed, and the sector data
+    // is returned to the scheduler, along with its
+    // heightmap and, after the light stage, its
+    // light levels.
+    Advanced {
+        world_pos: SectorIndex,
+        sector_data: Arc<SectorData>,
+        heightmap: Box<Heightmap>,
+        light: Option<LightData>,
+    },
+
+    // The sector was meshed, and is ready to
+    // be sent to the main thread.
+    Finished(Box<Message>),
+}
+
+// The sectors around a sector that is being meshed, and
+// the sector itself, along with their light levels.
+struct Surroundings(HashMap<SectorIndex, (Arc<SectorData>, Arc<LightData>)>);
+
+impl Surroundings {
+    // Gather the sectors around ``world_pos`` that are known.
+    fn gather(
+        world_pos: SectorIndex,
+        sectors: &HashMap<SectorIndex, Arc<SectorData>>,
+        lights: &HashMap<SectorIndex, Arc<LightData>>,
+    ) -> Surroundings {
+        let mut surroundings = HashMap::new();
+
+        for index in world_pos.around() {
+            if let (Some(data), Some(light)) = (sectors.get(&index), lights.get(&index)) {
+                surroundings.insert(index, (Arc::clone(data), Arc::clone(light)));
+            }
+        }
+
+        Surroundings(surroundings)
+    }
+
+    // Return a ``MeshNeighborhood`` around ``world_pos``,
+    // which must be one of the gathered sectors.
+    fn neighborhood(&self, world_pos: SectorIndex) -> MeshNeighborhood<'_> {
+        let SectorIndex(x, y, z) = world_pos;
+        let (data, light) = &self.0[&world_pos];
+
+        MeshNeighborhood::new(world_pos, data, light, |[dx, dy, dz]| {
+            self.0
+                .get(&SectorIndex(x + dx, y + dy, z + dz))
+                .map(|(data, light)| (&**data, &**light))
+        })
+    }
+}
+
+// The blocks around a sector that is being lit.
+struct Neighborhood {
+    // The sector and its neighbors.
+    sectors: HashMap<SectorIndex, Arc<SectorData>>,
+
+    // The highest blocks above the neighbors, by sector column.
+    ceilings: HashMap<ColumnIndex, Heightmap>,
+}
+
+impl Neighborhood {
+    // Gather the surroundings of the sector at ``world_pos``.
+    fn gather(
+        world_pos: SectorIndex,
+        region: SectorRegion,
+        sectors: &HashMap<SectorIndex, Arc<SectorData>>,
+        heightmaps: &HashMap<SectorIndex, Heightmap>,
+    ) -> Neighborhood {
+        let SectorIndex(x, y, z) = world_pos;
+        let mut neighborhood = Neighborhood {
+            sectors: HashMap::new(),
+            ceilings: HashMap::new(),
+        };
+
+        for nx in x - 1..x + 2 {
+            for nz in z - 1..z + 2 {
+                for ny in y - 1..y + 2 {
+                    let index = SectorIndex(nx, ny, nz);
+
+                    if let Some(data) = sectors.get(&index) {
+                        neighborhood.sectors.insert(index, Arc::clone(data));
+                    }
+                }
+
+                let mut ceiling = Heightmap::new();
+
+                for ny in y + 2..region.max.1 {
+                    if let Some(heightmap) = heightmaps.get(&SectorIndex(nx, ny, nz)) {
+                        ceiling.merge(heightmap);
+                    }
+                }
+
+                neighborhood.ceilings.insert(ColumnIndex(nx, nz), ceiling);
+            }
+        }
+
+        neighborhood
+    }
+
+    // Return the block at ``pos``, or ``None`` if it lies
+    // outside of the generated region.
+    fn block(&self, pos: WorldCoords) -> Option<Block> {
+        self.sectors
+            .get(&pos.sector_index())
+            .map(|data| *data.block(pos.sector_coords()))
+    }
+
+    // Returns ``true`` if no block above the neighborhood
+    // lies in the block column at ``x`` and ``z``.
+    fn open_sky(&self, x: i32, z: i32) -> bool {
+        let pos = WorldCoords(x, 0, z);
+        let coords = pos.sector_coords();
+
+        match self.ceilings.get(&pos.sector_index().into()) {
+            Some(ceiling) => ceiling.height(coords.0, coords.2).is_none(),
+            None => true,
+        }
+    }
+
+    // Returns ``true`` if the whole neighborhood is filled
+    // with air and open to the sky.
+    fn is_open(&self) -> bool {
+        self.sectors
+            .values()
+            .all(|data| data.uniform_block() == Some(Block::Air))
+            && self.ceilings.values().all(Heightmap::is_empty)
+    }
 }
 
-fn worker_thread(tx: Sender<Message>, tex_info: OutputInfo) {
-    for x in -10..11 {
-        for y in -1..0 {
-            for z in -10..11 {
-                let world_pos = SectorIndex(x, y, z);
-                let sector_data = superflat_sector(world_pos);
+fn scheduler_thread(
+    tx: Sender<Message>,
+    event_rx: Receiver<Event>,
+    job_tx: Sender<Job>,
+    region: SectorRegion,
+) {
+    let mut scheduler = Scheduler::new(region);
+    let mut sectors = HashMap::new();
+
+    // The heightmaps are kept after a sector is sent
+    // off, since the sectors below it need them to
+    // find out whether they are open to the sky.
+    let mut heightmaps = HashMap::new();
+    let mut lights: HashMap<SectorIndex, Arc<LightData>> = HashMap::new();
+
+    while !scheduler.is_finished() {
+        // Hand out every step that is allowed to run.
+        while let Some((world_pos, step)) = scheduler.next_ready() {
+            let (sector_data, neighborhood, surroundings) = match step {
+                Step::Stage(stage) if stage.needs_column() => (
+                    Arc::clone(&sectors[&world_pos]),
+                    Some(Neighborhood::gather(
+                        world_pos,
+                        region,
+                        &sectors,
+                        &heightmaps,
+                    )),
+                    None,
+                ),
+                Step::Stage(_) => (sectors.remove(&world_pos).unwrap_or_default(), None, None),
+                Step::Mesh => (
+                    Arc::clone(&sectors[&world_pos]),
+                    None,
+                    Some(Surroundings::gather(world_pos, &sectors, &lights)),
+                ),
+            };
+
+            let job = Job {
+                world_pos,
+                step,
+                sector_data,
+                neighborhood,
+                surroundings,
+            };
+
+            if job_tx.send(job).is_err() {
+                return;
+            }
+        }
+
+        // Wait for a request, or for a worker to complete
+        // a step.
+        match event_rx.recv() {
+            Ok(Event::Control(Control::Focus(focus))) => scheduler.set_focus(focus),
+            Ok(Event::Control(Control::Cancel(index))) => {
+                scheduler.cancel(index);
+                sectors.remove(&index);
+                lights.remove(&index);
+            }
+            Ok(Event::Outcome(Outcome::Advanced {
+                world_pos,
+                sector_data,
+                heightmap,
+                light,
+            })) => {
+                if !scheduler.complete(world_pos) {
+                    continue;
+                }
+
+                sectors.insert(world_pos, sector_data);
+                heightmaps.insert(world_pos, *heightmap);
 
-                let pre_geometry = meshgen::gen_terrain(&tex_info, &sector_data);
+                if let Some(light) = light {
+                    lights.insert(world_pos, Arc::new(light));
+                }
+            }
+            Ok(Event::Outcome(Outcome::Finished(message))) => {
+                if !scheduler.complete(message.world_pos) {
+                    continue;
+                }
 
-                let message = Message {
+                // Forget the sectors that no neighbor needs
+                // for its mesh anymore.
+                for index in message.world_pos.around() {
+                    if index.around().all(|n| scheduler.is_done(n)) {
+                        sectors.remove(&index);
+                        lights.remove(&index);
+                    }
+                }
+
+                if tx.send(*message).is_err() {
+                    println!("quitting!");
+                    return;
+                }
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+fn worker_thread(
+    jobs: Arc<Mutex<Receiver<Job>>>,
+    tx: Sender<Event>,
+    textures: TerrainTextures,
+    settings: GenSettings,
+) {
+    loop {
+        // The lock is released as soon as a job is received.
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        let Job {
+            world_pos,
+            step,
+            mut sector_data,
+            neighborhood,
+            surroundings,
+        } = job;
+
+        let outcome = match step {
+            Step::Stage(stage) => {
+                let light = match neighborhood {
+                    Some(ref neighborhood) => Some(light_stage(world_pos, neighborhood)),
+                    None => {
+                        run_stage(stage, world_pos, settings.seed, &mut sector_data);
+                        None
+                    }
+                };
+
+                Outcome::Advanced {
                     world_pos,
+                    heightmap: Box::new(Heightmap::from_sector(world_pos, &sector_data)),
                     sector_data,
-                    pre_geometry,
+                    light,
+                }
+            }
+            Step::Mesh => {
+                // The scheduler keeps the data for the meshes
+                // of the neighbors, so the main thread gets a
+                // copy of its own.
+                let surroundings = surroundings.unwrap();
+                let source = surroundings.neighborhood(world_pos);
+                let light = (*surroundings.0[&world_pos].1).clone();
+                let heightmap = Heightmap::from_sector(world_pos, &sector_data);
+                let connections = FaceConnections::from_sector(&sector_data);
+                let pre_geometry = if settings.mesh {
+                    meshgen::gen_terrain_greedy(&textures, &source, settings.format)
+                } else {
+                    None
+                };
+                let lod_geometry = if settings.mesh && settings.lods {
+                    (1..meshgen::LOD_LEVELS)
+                        .map(|level| {
+                            meshgen::gen_terrain_lod(&textures, &source, level, settings.format)
+                        })
+                        .collect()
+                } else {
+                    Vec::new()
                 };
 
-                match tx.send(message) {
-                    Ok(_) => {}
-                    Err(_) => {
-                        println!("quitting!");
-                        return;
-                    }
-                }
+                Outcome::Finished(Box::new(Message {
+                    world_pos,
+                    sector_data: (*sector_data).clone(),
+                    light,
+                    heightmap,
+                    connections,
+                    pre_geometry,
+                    lod_geometry,
+                }))
             }
+        };
+
+        if tx.send(Event::Outcome(outcome)).is_err() {
+            return;
         }
     }
 }
 
+// Run a stage that modifies the sector's blocks.
+fn run_stage(stage: GenStage, world_pos: SectorIndex, seed: u32, data: &mut Arc<SectorData>) {
+    match stage {
+        GenStage::Terrain => *data = Arc::new(superflat_sector(world_pos)),
+        GenStage::Carve => carve_caves(world_pos, seed, data),
+        GenStage::Decorate => decorate(world_pos, seed, data),
+
+        // Lighting leaves the blocks untouched.
+        GenStage::Light => {}
+    }
+}
+
+fn light_stage(world_pos: SectorIndex, neighborhood: &Neighborhood) -> LightData {
+    // Solid sectors are dark throughout, and open
+    // sectors are fully lit by the sky.
+    match neighborhood
+        .sectors
+        .get(&world_pos)
+        .and_then(|data| data.uniform_block())
+    {
+        Some(blk) if !blk.is_transparent() && blk.light_emission() == 0 => return LightData::new(),
+        _ => {}
+    }
+
+    if neighborhood.is_open() {
+        return LightData::uniform(MAX_LIGHT, 0);
+    }
+
+    light::light_sector(
+        world_pos,
+        |pos| neighborhood.block(pos),
+        |x, z| neighborhood.open_sky(x, z),
+    )
+}
+
+// Return the lowest and highest world heights of the
+// voxels in the sector.
+fn vertical_bounds(world_pos: SectorIndex) -> (i32, i32) {
+    let dim = SECTOR_DIM_EXCL as i32;
+
+    (world_pos.1 * dim, world_pos.1 * dim + dim - 1)
+}
+
 fn superflat_sector(world_pos: SectorIndex) -> SectorData {
+    // Sectors whose voxels lie entirely in the sky or in
+    // the stone layer are uniform and need not be generated
+    // voxel by voxel.
+    let (lowest, highest) = vertical_bounds(world_pos);
+
+    if lowest > SURFACE_HEIGHT {
+        return SectorData::uniform(Block::Air);
+    } else if highest <= STONE_HEIGHT {
+        return SectorData::uniform(Block::Stone);
+    }
+
     let mut data = SectorData::new();
 
-    if world_pos.1 != -1 {
-        return data;
+    for (coords, blk) in data.iter_mut() {
+        *blk = superflat_block(WorldCoords::from_sector(world_pos, coords));
     }
 
-    for (SectorCoords(x, y, z), blk) in data.iter_mut() {
-        *blk = if y < SECTOR_MAX - 1 {
-            Block::Soil
-        } else if y == SECTOR_MAX - 1 {
-            if x % 4 == 0 && z % 4 == 0 {
-                Block::TestBlock
-            } else {
-                Block::Grass
+    data
+}
+
+fn superflat_block(pos: WorldCoords) -> Block {
+    let WorldCoords(_, y, _) = pos;
+
+    if y <= STONE_HEIGHT {
+        Block::Stone
+    } else if y < SURFACE_HEIGHT {
+        Block::Soil
+    } else if y == SURFACE_HEIGHT {
+        Block::Grass
+    } else {
+        Block::Air
+    }
+}
+
+// A spherical cave, given by its center and radius.
+struct Cave(WorldCoords, i32);
+
+impl Cave {
+    // Return the cave in the given cell, if the cell has one.
+    fn in_cell(cx: i32, cy: i32, cz: i32, seed: u32) -> Option<Cave> {
+        let h = hash::hash3(cx, cy, cz, seed ^ CAVE_SEED);
+
+        // Only one in three cells contains a cave.
+        if !h.is_multiple_of(3) {
+            return None;
+        }
+
+        let offset = |shift: u32| ((h >> shift) % CAVE_CELL as u32) as i32;
+        let center = WorldCoords(
+            cx * CAVE_CELL + offset(4),
+            cy * CAVE_CELL + offset(12),
+            cz * CAVE_CELL + offset(20),
+        );
+        let radius = 3 + ((h >> 28) % (CAVE_MAX_RADIUS as u32 - 2)) as i32;
+
+        Some(Cave(center, radius))
+    }
+
+    fn contains(&self, pos: WorldCoords) -> bool {
+        let Cave(WorldCoords(cx, cy, cz), radius) = *self;
+        let WorldCoords(x, y, z) = pos;
+        let (dx, dy, dz) = (x - cx, y - cy, z - cz);
+
+        dx * dx + dy * dy + dz * dz <= radius * radius
+    }
+}
+
+// Collect every cave that could reach into the sector.
+fn caves_near(world_pos: SectorIndex, seed: u32) -> Vec<Cave> {
+    let dim = SECTOR_DIM_EXCL as i32;
+    let cells = |s: i32| {
+        let min = (s * dim - CAVE_MAX_RADIUS - 1).div_euclid(CAVE_CELL);
+        let max = (s * dim + dim + CAVE_MAX_RADIUS).div_euclid(CAVE_CELL);
+
+        min..max + 1
+    };
+
+    let SectorIndex(sx, sy, sz) = world_pos;
+    let mut caves = Vec::new();
+
+    for cx in cells(sx) {
+        for cy in cells(sy) {
+            for cz in cells(sz) {
+                caves.extend(Cave::in_cell(cx, cy, cz, seed));
             }
+        }
+    }
+
+    caves
+}
+
+fn carve_caves(world_pos: SectorIndex, seed: u32, data: &mut Arc<SectorData>) {
+    let (lowest, _) = vertical_bounds(world_pos);
+
+    if lowest > CAVE_CEILING {
+        return;
+    }
+
+    let caves = caves_near(world_pos, seed);
+
+    // Leave sectors without any caves untouched,
+    // so uniform sectors stay uniform.
+    if caves.is_empty() {
+        return;
+    }
+
+    for (coords, blk) in Arc::make_mut(data).iter_mut() {
+        let pos = WorldCoords::from_sector(world_pos, coords);
+
+        if pos.1 <= CAVE_CEILING && caves.iter().any(|cave| cave.contains(pos)) {
+            *blk = Block::Air;
+        }
+    }
+}
+
+fn decorate(world_pos: SectorIndex, seed: u32, data: &mut Arc<SectorData>) {
+    light_caves(world_pos, seed, data);
+    build_ruins(world_pos, seed, data);
+
+    // Only sectors that contain the surface have anything to decorate.
+    if data.uniform_block().is_some() {
+        return;
+    }
+
+    // Mark a regular grid on the grass with test blocks.
+    for (coords, blk) in Arc::make_mut(data).iter_mut() {
+        let WorldCoords(x, _, z) = WorldCoords::from_sector(world_pos, coords);
+
+        if *blk == Block::Grass && x.rem_euclid(4) == 0 && z.rem_euclid(4) == 0 {
+            *blk = Block::TestBlock;
+        }
+    }
+}
+
+// Stand a torch on the floor of every other cave.
+fn light_caves(world_pos: SectorIndex, seed: u32, data: &mut Arc<SectorData>) {
+    let (lowest, _) = vertical_bounds(world_pos);
+
+    if lowest > CAVE_CEILING {
+        return;
+    }
+
+    for Cave(WorldCoords(x, y, z), radius) in caves_near(world_pos, seed) {
+        // The torch stands on the cave's lowest point,
+        // just above its floor.
+        let torch = WorldCoords(x, y - radius, z);
+        let floor = WorldCoords(x, torch.1 - 1, z);
+
+        if torch.1 > CAVE_CEILING
+            || torch.sector_index() != world_pos
+            || hash::hash3(x, y, z, seed ^ TORCH_SEED) & 1 != 0
+        {
+            continue;
+        }
+
+        // Another cave may have carved the floor away, and
+        // it may lie in the sector below, so it is looked up
+        // in the world rather than in this sector.
+        if carved_block(floor, seed).is_transparent() {
+            continue;
+        }
+
+        *Arc::make_mut(data).block_mut(torch.sector_coords()) = Block::Torch;
+    }
+}
+
+// A ruined wall of bricks that is one block thick, given by
+// the lowest corner of the blocks it may fill and its length
+// along X or Z.
+struct Ruin {
+    origin: WorldCoords,
+    length: i32,
+    along_x: bool,
+}
+
+impl Ruin {
+    // Return the ruin in the given cell, if the cell has one.
+    fn in_cell(cx: i32, cz: i32, seed: u32) -> Option<Ruin> {
+        let h = hash::hash3(cx, 0, cz, seed ^ RUIN_SEED);
+
+        // Only one in four cells contains a ruin.
+        if !h.is_multiple_of(4) {
+            return None;
+        }
+
+        // The wall lies entirely inside of its cell.
+        let length = 4 + ((h >> 2) % 9) as i32;
+        let offset = |shift: u32, room: i32| ((h >> shift) % room as u32) as i32;
+        let along_x = (h >> 6) & 1 == 0;
+        let (dx, dz) = if along_x {
+            (offset(8, RUIN_CELL - length), offset(16, RUIN_CELL))
         } else {
-            Block::Air
+            (offset(8, RUIN_CELL), offset(16, RUIN_CELL - length))
         };
+
+        Some(Ruin {
+            origin: WorldCoords(cx * RUIN_CELL + dx, SURFACE_HEIGHT + 1, cz * RUIN_CELL + dz),
+            length,
+            along_x,
+        })
     }
 
-    data
+    // Iterate over the bricks that are left of the wall.
+    // Each column has crumbled down to a random height.
+    fn bricks(&self, seed: u32) -> impl Iterator<Item = WorldCoords> {
+        let WorldCoords(x, y, z) = self.origin;
+        let along_x = self.along_x;
+
+        (0..self.length).flat_map(move |i| {
+            let (x, z) = if along_x { (x + i, z) } else { (x, z + i) };
+            let height = 1 + (hash::hash3(x, y, z, seed ^ RUIN_SEED) % RUIN_MAX_HEIGHT as u32);
+
+            (0..height as i32).map(move |dy| WorldCoords(x, y + dy, z))
+        })
+    }
 }
 
-// The ``png`` crate does not include a ``Clone`` implementation
-// for ``OutputInfo``, but it's fairly easy to reconstruct one.
-fn copy_tex_info(tex_info: &OutputInfo) -> OutputInfo {
-    OutputInfo {
-        width: tex_info.width,
-        height: tex_info.height,
-        color_type: tex_info.color_type,
-        bit_depth: tex_info.bit_depth,
-        line_size: tex_info.line_size,
+// Set the bricks of every ruin that reaches into the sector.
+fn build_ruins(world_pos: SectorIndex, seed: u32, data: &mut Arc<SectorData>) {
+    let (lowest, highest) = vertical_bounds(world_pos);
+
+    if highest <= SURFACE_HEIGHT || lowest > SURFACE_HEIGHT + RUIN_MAX_HEIGHT {
+        return;
+    }
+
+    // Ruins never leave their cell, so only the cells that
+    // overlap the sector can reach into it.
+    let dim = SECTOR_DIM_EXCL as i32;
+    let cells =
+        |s: i32| (s * dim).div_euclid(RUIN_CELL)..=(s * dim + dim - 1).div_euclid(RUIN_CELL);
+    let SectorIndex(sx, _, sz) = world_pos;
+
+    for cx in cells(sx) {
+        for cz in cells(sz) {
+            let ruin = match Ruin::in_cell(cx, cz, seed) {
+                Some(ruin) => ruin,
+                None => continue,
+            };
+
+            for pos in ruin.bricks(seed) {
+                if pos.sector_index() == world_pos {
+                    *Arc::make_mut(data).block_mut(pos.sector_coords()) = Block::Bricks;
+                }
+            }
+        }
+    }
+}
+
+// Return the block at ``pos`` once the caves are carved,
+// before any decoration.
+fn carved_block(pos: WorldCoords, seed: u32) -> Block {
+    let carved = pos.1 <= CAVE_CEILING
+        && caves_near(pos.sector_index(), seed)
+            .iter()
+            .any(|cave| cave.contains(pos));
+
+    if carved {
+        Block::Air
+    } else {
+        superflat_block(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc::RecvTimeoutError, time::Duration};
+
+    // Run every stage that changes blocks on the sector.
+    fn generate(world_pos: SectorIndex, seed: u32) -> SectorData {
+        let mut data = Arc::new(SectorData::uniform(Block::Air));
+
+        for stage in &[GenStage::Terrain, GenStage::Carve, GenStage::Decorate] {
+            run_stage(*stage, world_pos, seed, &mut data);
+        }
+
+        (*data).clone()
+    }
+
+    #[test]
+    fn torches_stand_on_solid_floors() {
+        let mut torches = 0;
+
+        for seed in 0..4 {
+            for x in -2..2 {
+                for y in -3..0 {
+                    for z in -2..2 {
+                        let world_pos = SectorIndex(x, y, z);
+
+                        for (coords, blk) in generate(world_pos, seed).iter() {
+                            if *blk != Block::Torch {
+                                continue;
+                            }
+
+                            let WorldCoords(x, y, z) = WorldCoords::from_sector(world_pos, coords);
+                            let floor = WorldCoords(x, y - 1, z);
+                            let below =
+                                *generate(floor.sector_index(), seed).block(floor.sector_coords());
+
+                            assert!(below.is_cube(), "{:?} stands on {:?}", floor, below);
+                            torches += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        assert!(torches > 0);
+    }
+
+    #[test]
+    fn ruins_stand_on_the_surface() {
+        let seed = 3;
+        let mut bricks = 0;
+
+        for x in -4..4 {
+            for z in -4..4 {
+                let world_pos = SectorIndex(x, 0, z);
+
+                for (coords, blk) in generate(world_pos, seed).iter() {
+                    if *blk != Block::Bricks {
+                        continue;
+                    }
+
+                    // Every brick stands on the ground or on
+                    // another brick, even across sectors.
+                    let WorldCoords(x, y, z) = WorldCoords::from_sector(world_pos, coords);
+                    let below = WorldCoords(x, y - 1, z);
+                    let support =
+                        *generate(below.sector_index(), seed).block(below.sector_coords());
+
+                    assert!(y > SURFACE_HEIGHT && y <= SURFACE_HEIGHT + RUIN_MAX_HEIGHT);
+                    assert!(support.is_cube(), "{:?} stands on {:?}", below, support);
+                    bricks += 1;
+                }
+            }
+        }
+
+        assert!(bricks > 0);
+    }
+
+    #[test]
+    fn carved_blocks_match_the_generated_sectors() {
+        let seed = 7;
+
+        for y in -3..0 {
+            let world_pos = SectorIndex(0, y, 0);
+
+            for (coords, blk) in generate(world_pos, seed).iter() {
+                let expected = carved_block(WorldCoords::from_sector(world_pos, coords), seed);
+
+                // Decoration is the only stage that runs after carving.
+                match blk {
+                    Block::Torch => assert_eq!(expected, Block::Air),
+                    Block::TestBlock => assert_eq!(expected, Block::Grass),
+                    _ => assert_eq!(*blk, expected),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn requests_are_handled_while_workers_are_busy() {
+        let index = SectorIndex(0, 0, 0);
+        let region = SectorRegion::new(index, SectorIndex(1, 1, 1));
+        let (tx, rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+        let (job_tx, job_rx) = mpsc::channel();
+
+        let scheduler = thread::spawn(move || scheduler_thread(tx, event_rx, job_tx, region));
+
+        // Take the first job like a worker, but never complete
+        // it, so that only the request reaches the scheduler.
+        let job = job_rx.recv().unwrap();
+        assert_eq!(job.world_pos, index);
+        event_tx
+            .send(Event::Control(Control::Cancel(index)))
+            .unwrap();
+
+        // The scheduler finishes without sending the sector.
+        let received = rx.recv_timeout(Duration::from_secs(10));
+        assert_eq!(received.err(), Some(RecvTimeoutError::Disconnected));
+        scheduler.join().unwrap();
     }
 }
@@ -0,0 +1,255 @@
+//! Implements per-column heightmaps for the world.
+//!
+//! A heightmap records, for every block column in a
+//! column of sectors, the world height of the highest
+//! block that is not air. This information is needed
+//! frequently (for spawning, lighting, and maps) and
+//! would otherwise require a scan of every sector in
+//! the column.
+
+use super::{
+    data::{SectorCoords, SectorData, SECTOR_DIM_EXCL},
+    SectorIndex,
+};
+use crate::block::Block;
+
+/// The number of block columns in a heightmap.
+const HEIGHTMAP_LEN: usize = SECTOR_DIM_EXCL * SECTOR_DIM_EXCL;
+
+/// Represents the position of a vertical column of sectors.
+///
+/// Each integer increment represents one sector along
+/// the X or Z axis.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ColumnIndex(pub i32, pub i32);
+
+impl From<SectorIndex> for ColumnIndex {
+    fn from(index: SectorIndex) -> ColumnIndex {
+        let SectorIndex(x, _, z) = index;
+        ColumnIndex(x, z)
+    }
+}
+
+/// Stores the height of the highest non-air block
+/// in each block column of a sector column.
+///
+/// Heights are stored as world Y coordinates.
+/// A column that has no solid blocks in any loaded
+/// sector has no height.
+#[derive(Clone, Debug)]
+pub struct Heightmap {
+    heights: [Option<i32>; HEIGHTMAP_LEN],
+}
+
+impl Heightmap {
+    /// Create a new ``Heightmap`` in which no column
+    /// has a height.
+    pub fn new() -> Heightmap {
+        Heightmap {
+            heights: [None; HEIGHTMAP_LEN],
+        }
+    }
+
+    /// Create the ``Heightmap`` of a single sector.
+    pub fn from_sector(index: SectorIndex, voxels: &SectorData) -> Heightmap {
+        let mut heightmap = Heightmap::new();
+        let base_y = index.1 * SECTOR_DIM_EXCL as i32;
+
+        if voxels.uniform_block() == Some(Block::Air) {
+            return heightmap;
+        }
+
+        for x in 0..SECTOR_DIM_EXCL {
+            for z in 0..SECTOR_DIM_EXCL {
+                // Scan downward, stopping at the first solid block.
+                for y in (0..SECTOR_DIM_EXCL).rev() {
+                    if *voxels.block(SectorCoords(x, y, z)) != Block::Air {
+                        heightmap.heights[Self::index(x, z)] = Some(base_y + y as i32);
+                        break;
+                    }
+                }
+            }
+        }
+
+        heightmap
+    }
+
+    /// Return the world height of the highest non-air
+    /// block in the column at the given local coordinates.
+    ///
+    /// ``x`` and ``z`` must be less than ``SECTOR_DIM_EXCL``.
+    pub fn height(&self, x: usize, z: usize) -> Option<i32> {
+        self.heights[Self::index(x, z)]
+    }
+
+    /// Returns ``true`` if no column has a height.
+    pub fn is_empty(&self) -> bool {
+        self.heights.iter().all(Option::is_none)
+    }
+
+    /// Assign the height of the column at the given
+    /// local coordinates.
+    pub fn set_height(&mut self, x: usize, z: usize, height: Option<i32>) {
+        self.heights[Self::index(x, z)] = height;
+    }
+
+    /// Combine another heightmap of the same sector column
+    /// into this one, keeping the greater height of each
+    /// block column.
+    pub fn merge(&mut self, other: &Heightmap) {
+        for (mine, theirs) in self.heights.iter_mut().zip(other.heights.iter()) {
+            *mine = match (*mine, *theirs) {
+                (Some(a), Some(b)) => Some(a.max(b)),
+                (a, b) => a.or(b),
+            };
+        }
+    }
+
+    /// Update the heightmap after the block at world height
+    /// ``y`` in the given column was changed to ``blk``.
+    ///
+    /// Placing a block can only raise a column, so that case
+    /// is handled immediately. Removing the topmost block,
+    /// however, requires the next solid block below it to be
+    /// found. The ``below`` closure is called in that case
+    /// with the height of the removed block, and must return
+    /// the height of the highest non-air block beneath it.
+    pub fn update<F>(&mut self, x: usize, z: usize, y: i32, blk: Block, below: F)
+    where
+        F: FnOnce(i32) -> Option<i32>,
+    {
+        let current = self.height(x, z);
+
+        if blk != Block::Air {
+            if current.is_none_or(|h| y > h) {
+                self.set_height(x, z, Some(y));
+            }
+        } else if current == Some(y) {
+            self.set_height(x, z, below(y));
+        }
+    }
+
+    /// Determine the array index of a local column.
+    fn index(x: usize, z: usize) -> usize {
+        x + z * SECTOR_DIM_EXCL
+    }
+}
+
+impl Default for Heightmap {
+    fn default() -> Heightmap {
+        Heightmap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOP: usize = SECTOR_DIM_EXCL - 1;
+
+    // A sector with a single column of stone at ``(x, z)``
+    // from the bottom of the sector up to ``y``.
+    fn pillar(x: usize, z: usize, y: usize) -> SectorData {
+        let mut voxels = SectorData::uniform(Block::Air);
+        for py in 0..=y {
+            *voxels.block_mut(SectorCoords(x, py, z)) = Block::Stone;
+        }
+
+        voxels
+    }
+
+    fn never_below(_: i32) -> Option<i32> {
+        panic!("the column was searched")
+    }
+
+    #[test]
+    fn sectors_have_the_height_of_their_highest_blocks() {
+        let base = 2 * SECTOR_DIM_EXCL as i32;
+
+        let air = Heightmap::from_sector(SectorIndex(0, 2, 0), &SectorData::uniform(Block::Air));
+        assert!(air.is_empty());
+
+        let stone =
+            Heightmap::from_sector(SectorIndex(0, 2, 0), &SectorData::uniform(Block::Stone));
+        assert_eq!(stone.height(0, 0), Some(base + TOP as i32));
+        assert_eq!(stone.height(TOP, TOP), Some(base + TOP as i32));
+
+        // A torch floating over a pillar does not hide it from
+        // the columns around it.
+        let mut voxels = pillar(3, 5, 4);
+        *voxels.block_mut(SectorCoords(3, 9, 5)) = Block::Torch;
+
+        let heightmap = Heightmap::from_sector(SectorIndex(0, -2, 0), &voxels);
+        assert_eq!(
+            heightmap.height(3, 5),
+            Some(-2 * SECTOR_DIM_EXCL as i32 + 9)
+        );
+        assert_eq!(heightmap.height(5, 3), None);
+        assert_eq!(heightmap.height(4, 5), None);
+    }
+
+    #[test]
+    fn placing_blocks_only_raises_columns() {
+        let mut heightmap = Heightmap::new();
+
+        heightmap.update(1, 2, 10, Block::Stone, never_below);
+        assert_eq!(heightmap.height(1, 2), Some(10));
+
+        heightmap.update(1, 2, 4, Block::Stone, never_below);
+        assert_eq!(heightmap.height(1, 2), Some(10));
+
+        heightmap.update(1, 2, 12, Block::Torch, never_below);
+        assert_eq!(heightmap.height(1, 2), Some(12));
+        assert_eq!(heightmap.height(2, 1), None);
+    }
+
+    #[test]
+    fn removing_the_topmost_block_searches_below() {
+        let mut heightmap = Heightmap::new();
+        heightmap.set_height(1, 2, Some(10));
+
+        // Blocks below the top and air above it change nothing.
+        heightmap.update(1, 2, 4, Block::Air, never_below);
+        heightmap.update(1, 2, 11, Block::Air, never_below);
+        assert_eq!(heightmap.height(1, 2), Some(10));
+
+        let mut searched = None;
+        heightmap.update(1, 2, 10, Block::Air, |y| {
+            searched = Some(y);
+            Some(7)
+        });
+        assert_eq!(searched, Some(10));
+        assert_eq!(heightmap.height(1, 2), Some(7));
+
+        // Removing the last block leaves the column empty.
+        heightmap.update(1, 2, 7, Block::Air, |_| None);
+        assert_eq!(heightmap.height(1, 2), None);
+        assert!(heightmap.is_empty());
+    }
+
+    #[test]
+    fn stacked_sectors_merge_in_any_order() {
+        let dim = SECTOR_DIM_EXCL as i32;
+        let sectors = [
+            (SectorIndex(0, -1, 0), SectorData::uniform(Block::Stone)),
+            (SectorIndex(0, 0, 0), pillar(3, 5, 6)),
+            (SectorIndex(0, 1, 0), pillar(5, 3, 2)),
+            (SectorIndex(0, 2, 0), SectorData::uniform(Block::Air)),
+        ];
+        let heightmaps: Vec<_> = sectors
+            .iter()
+            .map(|(index, voxels)| Heightmap::from_sector(*index, voxels))
+            .collect();
+
+        for order in &[[0, 1, 2, 3], [3, 2, 1, 0], [2, 0, 3, 1]] {
+            let mut merged = Heightmap::new();
+            for i in order {
+                merged.merge(&heightmaps[*i]);
+            }
+
+            assert_eq!(merged.height(0, 0), Some(-1));
+            assert_eq!(merged.height(3, 5), Some(6));
+            assert_eq!(merged.height(5, 3), Some(dim + 2));
+        }
+    }
+}
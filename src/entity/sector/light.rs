@@ -0,0 +1,460 @@
+//! Implements the light levels of the world.
+//!
+//! Every voxel has two light levels between zero and
+//! ``MAX_LIGHT``: *sky light*, which enters the world from
+//! above, and *block light*, which is emitted by blocks such
+//! as torches. Light spreads through transparent blocks by
+//! flood fill, losing one level with every step. Sky light
+//! at full strength is the exception: it travels straight
+//! down without fading, so open columns are fully lit all
+//! the way to the ground.
+//!
+//! Light is first computed for each sector as it is generated,
+//! and is then updated incrementally whenever a block changes.
+//! Both use the same breadth-first search, which only needs
+//! access to blocks and light levels by world position through
+//! the ``LightAccess`` trait.
+
+use super::{
+    data::{SectorCoords, SECTOR_DIM_EXCL, SECTOR_LEN},
+    SectorIndex, WorldCoords,
+};
+use crate::{block::Block, side::Side};
+use std::collections::VecDeque;
+
+/// The highest light level, of full daylight.
+pub const MAX_LIGHT: u8 = 15;
+
+/// The two kinds of light that every voxel stores.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LightChannel {
+    /// Light from the sky.
+    Sky,
+
+    /// Light emitted by blocks.
+    Block,
+}
+
+impl LightChannel {
+    /// Both channels, in the order they are packed.
+    pub const ALL: [LightChannel; 2] = [LightChannel::Sky, LightChannel::Block];
+
+    // The position of the channel's level in a packed byte.
+    fn shift(self) -> u8 {
+        match self {
+            LightChannel::Sky => 4,
+            LightChannel::Block => 0,
+        }
+    }
+}
+
+/// Holds the light levels of a sector, laid out just like
+/// its ``SectorData``.
+///
+/// As with the voxels, sectors that are lit evenly, such as
+/// those in the open sky or deep in solid rock, are stored
+/// as a single pair of levels.
+#[derive(Clone, Debug)]
+pub struct LightData {
+    uniform: u8,
+    levels: Option<Box<[u8; SECTOR_LEN]>>,
+}
+
+impl LightData {
+    /// Create a new ``LightData`` that is completely dark.
+    pub fn new() -> LightData {
+        Self::uniform(0, 0)
+    }
+
+    /// Create a new ``LightData`` with the same sky and
+    /// block light level everywhere.
+    pub fn uniform(sky: u8, block: u8) -> LightData {
+        LightData {
+            uniform: pack(sky, LightChannel::Sky) | pack(block, LightChannel::Block),
+            levels: None,
+        }
+    }
+
+    /// Return the light level of one channel at the given position.
+    pub fn get(&self, coords: SectorCoords, channel: LightChannel) -> u8 {
+        let packed = match self.levels {
+            Some(ref levels) => levels[Self::index(coords)],
+            None => self.uniform,
+        };
+
+        (packed >> channel.shift()) & MAX_LIGHT
+    }
+
+    /// Assign the light level of one channel at the given position.
+    pub fn set(&mut self, coords: SectorCoords, channel: LightChannel, level: u8) {
+        let uniform = self.uniform;
+        let packed = &mut self
+            .levels
+            .get_or_insert_with(|| Box::new([uniform; SECTOR_LEN]))[Self::index(coords)];
+
+        *packed = (*packed & !pack(MAX_LIGHT, channel)) | pack(level, channel);
+    }
+
+    // Store the levels uniformly if they are all equal.
+    fn compact(&mut self) {
+        let uniform = match self.levels {
+            Some(ref levels) if levels.iter().all(|packed| *packed == levels[0]) => levels[0],
+            _ => return,
+        };
+
+        self.uniform = uniform;
+        self.levels = None;
+    }
+
+    // Determine the array index of a particular voxel coordinate.
+    fn index(coords: SectorCoords) -> usize {
+        let SectorCoords(x, y, z) = coords;
+
+        x + y * SECTOR_DIM_EXCL + z * SECTOR_DIM_EXCL * SECTOR_DIM_EXCL
+    }
+}
+
+impl Default for LightData {
+    fn default() -> LightData {
+        LightData::new()
+    }
+}
+
+/// Provides the lighting algorithms with access to the
+/// blocks and light levels of the world.
+///
+/// Implementations only need to cover a limited area, such
+/// as the loaded sectors. Light never spreads beyond it.
+pub trait LightAccess {
+    /// Return the block at ``pos``, or ``None`` if ``pos``
+    /// lies outside of the accessible area.
+    fn block(&self, pos: WorldCoords) -> Option<Block>;
+
+    /// Return the light level of one channel at ``pos``.
+    /// Positions outside of the accessible area are dark.
+    fn light(&self, pos: WorldCoords, channel: LightChannel) -> u8;
+
+    /// Assign the light level of one channel at ``pos``.
+    fn set_light(&mut self, pos: WorldCoords, channel: LightChannel, level: u8);
+}
+
+/// Update the light around ``pos`` after the block there
+/// has been replaced with ``blk``.
+///
+/// ``world`` must already contain the new block. The light
+/// that the old block let through or emitted is removed, and
+/// the light of the surrounding voxels is spread again.
+pub fn update_block(world: &mut impl LightAccess, pos: WorldCoords, blk: Block) {
+    for &channel in &LightChannel::ALL {
+        let mut queue = remove(world, pos, channel);
+
+        if channel == LightChannel::Block && blk.light_emission() > 0 {
+            world.set_light(pos, channel, blk.light_emission());
+            queue.push_back(pos);
+        }
+
+        if blk.is_transparent() {
+            queue.extend(Side::ALL.iter().map(|side| pos.neighbor(*side)));
+
+            // Beyond the top of the accessible area, there is
+            // nothing but sky.
+            if channel == LightChannel::Sky && world.block(pos.neighbor(Side::Top)).is_none() {
+                world.set_light(pos, channel, MAX_LIGHT);
+                queue.push_back(pos);
+            }
+        }
+
+        propagate(world, channel, queue);
+    }
+}
+
+/// Spread the light of the voxels at ``sources`` wherever it
+/// is brighter than the light that is already there.
+///
+/// This joins the light of a sector that was lit on its own
+/// with the light of the sectors around it.
+pub fn spread_from(world: &mut impl LightAccess, sources: &[WorldCoords]) {
+    for &channel in &LightChannel::ALL {
+        propagate(world, channel, sources.iter().copied().collect());
+    }
+}
+
+/// Compute the light levels of the sector at ``index``.
+///
+/// ``block`` returns the block at any world position, and is
+/// consulted for every voxel within ``MAX_LIGHT`` blocks of the
+/// sector, since light from that far away can still reach it.
+/// It returns ``None`` where the world has not been generated.
+/// No light spreads through such places, but the sky shines
+/// down through them.
+///
+/// ``open_sky`` returns ``true`` if nothing blocks the sky
+/// above the block column at the given world X and Z position,
+/// starting from the top of the sector above this one.
+pub fn light_sector<B, S>(index: SectorIndex, block: B, open_sky: S) -> LightData
+where
+    B: Fn(WorldCoords) -> Option<Block>,
+    S: Fn(i32, i32) -> bool,
+{
+    let dim = SECTOR_DIM_EXCL as i32;
+    let margin = MAX_LIGHT as i32;
+    let SectorIndex(sx, sy, sz) = index;
+
+    let origin = WorldCoords(sx * dim - margin, sy * dim - margin, sz * dim - margin);
+    let mut grid = LightGrid::new(origin, &block);
+
+    let WorldCoords(ox, _, oz) = origin;
+    let top = origin.1 + GRID_DIM as i32 - 1;
+
+    // Sky light enters each open column at the top of the grid,
+    // or at the first generated block below it.
+    let sky_limit = (sy + 2) * dim;
+    let transparent = |blk: Option<Block>| blk.is_none_or(Block::is_transparent);
+    let mut sky = VecDeque::new();
+
+    for x in ox..ox + GRID_DIM as i32 {
+        for z in oz..oz + GRID_DIM as i32 {
+            if !open_sky(x, z)
+                || !(top..sky_limit).all(|y| transparent(block(WorldCoords(x, y, z))))
+            {
+                continue;
+            }
+
+            let entry = (origin.1..top + 1)
+                .rev()
+                .map(|y| WorldCoords(x, y, z))
+                .find(|pos| grid.block(*pos).is_some());
+
+            match entry {
+                Some(pos) if transparent(grid.block(pos)) => {
+                    grid.set_light(pos, LightChannel::Sky, MAX_LIGHT);
+                    sky.push_back(pos);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Block light starts at every emitting block.
+    let mut emitted = VecDeque::new();
+
+    for (i, blk) in grid.blocks.iter().enumerate() {
+        if let Some(blk) = blk {
+            if blk.light_emission() > 0 {
+                emitted.push_back((grid.pos(i), blk.light_emission()));
+            }
+        }
+    }
+
+    for &(pos, level) in &emitted {
+        grid.set_light(pos, LightChannel::Block, level);
+    }
+
+    propagate(&mut grid, LightChannel::Sky, sky);
+    propagate(
+        &mut grid,
+        LightChannel::Block,
+        emitted.into_iter().map(|(pos, _)| pos).collect(),
+    );
+
+    let mut light = LightData::new();
+
+    for x in 0..SECTOR_DIM_EXCL {
+        for y in 0..SECTOR_DIM_EXCL {
+            for z in 0..SECTOR_DIM_EXCL {
+                let coords = SectorCoords(x, y, z);
+                let pos = WorldCoords::from_sector(index, coords);
+
+                for &channel in &LightChannel::ALL {
+                    let level = grid.light(pos, channel);
+
+                    if level > 0 {
+                        light.set(coords, channel, level);
+                    }
+                }
+            }
+        }
+    }
+
+    light.compact();
+    light
+}
+
+// Return the level that spreads from a voxel with the given
+// level to its neighbor on ``side``.
+fn spread(channel: LightChannel, side: Side, level: u8) -> u8 {
+    if channel == LightChannel::Sky && side == Side::Bottom && level == MAX_LIGHT {
+        MAX_LIGHT
+    } else {
+        level.saturating_sub(1)
+    }
+}
+
+// Spread light outward from every position in ``queue``
+// into the transparent blocks around it.
+fn propagate(
+    world: &mut impl LightAccess,
+    channel: LightChannel,
+    mut queue: VecDeque<WorldCoords>,
+) {
+    while let Some(pos) = queue.pop_front() {
+        let level = world.light(pos, channel);
+
+        if level == 0 {
+            continue;
+        }
+
+        for &side in &Side::ALL {
+            let next = pos.neighbor(side);
+
+            match world.block(next) {
+                Some(blk) if blk.is_transparent() => {}
+                _ => continue,
+            }
+
+            let level = spread(channel, side, level);
+
+            if level > world.light(next, channel) {
+                world.set_light(next, channel, level);
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+// Darken ``pos`` and every voxel that received its light
+// through ``pos``.
+//
+// Returns the lit voxels at the edge of the darkened area.
+// Their light must be propagated again to fill the area
+// back in from other sources.
+fn remove(
+    world: &mut impl LightAccess,
+    pos: WorldCoords,
+    channel: LightChannel,
+) -> VecDeque<WorldCoords> {
+    let mut edge = VecDeque::new();
+    let mut queue = VecDeque::new();
+
+    queue.push_back((pos, world.light(pos, channel)));
+    world.set_light(pos, channel, 0);
+
+    while let Some((pos, level)) = queue.pop_front() {
+        for &side in &Side::ALL {
+            let next = pos.neighbor(side);
+            let next_level = world.light(next, channel);
+
+            if next_level == 0 {
+                continue;
+            }
+
+            // A neighbor that is no brighter than the light that
+            // ``pos`` spread to it may have been lit by ``pos``.
+            if next_level > spread(channel, side, level) {
+                edge.push_back(next);
+                continue;
+            }
+
+            world.set_light(next, channel, 0);
+            queue.push_back((next, next_level));
+
+            // Emitting blocks keep their own light.
+            if channel == LightChannel::Block {
+                let emission = world.block(next).map_or(0, Block::light_emission);
+
+                if emission > 0 {
+                    world.set_light(next, channel, emission);
+                    edge.push_back(next);
+                }
+            }
+        }
+    }
+
+    edge
+}
+
+// Place a level in its channel's position within a byte.
+fn pack(level: u8, channel: LightChannel) -> u8 {
+    (level & MAX_LIGHT) << channel.shift()
+}
+
+// The number of voxels along one edge of a ``LightGrid``.
+const GRID_DIM: usize = SECTOR_DIM_EXCL + 2 * MAX_LIGHT as usize;
+
+// The blocks and light levels around a single sector,
+// while its light is computed.
+struct LightGrid {
+    origin: WorldCoords,
+    blocks: Vec<Option<Block>>,
+    levels: Vec<u8>,
+}
+
+impl LightGrid {
+    // Create a dark grid with its lower left back corner at
+    // ``origin``, filled with the blocks that ``block`` returns.
+    fn new(origin: WorldCoords, block: impl Fn(WorldCoords) -> Option<Block>) -> LightGrid {
+        let mut grid = LightGrid {
+            origin,
+            blocks: Vec::with_capacity(GRID_DIM * GRID_DIM * GRID_DIM),
+            levels: vec![0; GRID_DIM * GRID_DIM * GRID_DIM],
+        };
+
+        for i in 0..grid.levels.len() {
+            let pos = grid.pos(i);
+            grid.blocks.push(block(pos));
+        }
+
+        grid
+    }
+
+    // Return the world position of the voxel at an array index.
+    fn pos(&self, idx: usize) -> WorldCoords {
+        let WorldCoords(ox, oy, oz) = self.origin;
+        let (x, y, z) = (
+            idx % GRID_DIM,
+            idx / GRID_DIM % GRID_DIM,
+            idx / (GRID_DIM * GRID_DIM),
+        );
+
+        WorldCoords(ox + x as i32, oy + y as i32, oz + z as i32)
+    }
+
+    // Return the array index of a world position, if it lies
+    // within the grid.
+    fn index(&self, pos: WorldCoords) -> Option<usize> {
+        let WorldCoords(ox, oy, oz) = self.origin;
+        let WorldCoords(x, y, z) = pos;
+        let local = |c: i32, o: i32| {
+            let c = c - o;
+
+            if c >= 0 && c < GRID_DIM as i32 {
+                Some(c as usize)
+            } else {
+                None
+            }
+        };
+
+        Some(local(x, ox)? + local(y, oy)? * GRID_DIM + local(z, oz)? * GRID_DIM * GRID_DIM)
+    }
+}
+
+impl LightAccess for LightGrid {
+    fn block(&self, pos: WorldCoords) -> Option<Block> {
+        self.index(pos).and_then(|idx| self.blocks[idx])
+    }
+
+    fn light(&self, pos: WorldCoords, channel: LightChannel) -> u8 {
+        match self.index(pos) {
+            Some(idx) => (self.levels[idx] >> channel.shift()) & MAX_LIGHT,
+            None => 0,
+        }
+    }
+
+    fn set_light(&mut self, pos: WorldCoords, channel: LightChannel, level: u8) {
+        if let Some(idx) = self.index(pos) {
+            let packed = &mut self.levels[idx];
+
+            *packed = (*packed & !pack(MAX_LIGHT, channel)) | pack(level, channel);
+        }
+    }
+}
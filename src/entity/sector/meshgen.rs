@@ -7,14 +7,33 @@
 //!
 //! In other words, it makes models for the sectors.
 
-use super::data::{SectorCoords, SectorData, SECTOR_MAX, SECTOR_MIN};
+mod connected;
+mod greedy;
+mod lod;
+mod packed;
+mod source;
+
+pub use greedy::gen_terrain_greedy;
+pub use lod::{gen_terrain_lod, lod_for_distance, LOD_LEVELS};
+pub use packed::{pack_vertex, unpack_vertex};
+pub use source::{MeshNeighborhood, MeshSource, PaddedSector};
+
+use super::{
+    data::{SectorCoords, SECTOR_DIM_EXCL, SECTOR_PAD},
+    light::{LightChannel, MAX_LIGHT},
+    SectorIndex, WorldCoords,
+};
 use crate::{
-    block::Block,
+    biome,
+    block::{self, Block, BlockTextureID, BlockTextures, Orientation, Tint},
     side::Side,
-    vertexattrib::{PosAttrib, UvAttrib, VoxelVertex},
+    util::hash,
+    vertexattrib::{
+        AoAttrib, LayerAttrib, LightAttrib, NormalAttrib, PackedVertex, PosAttrib, TileAttrib,
+        TintAttrib, UvAttrib, VoxelVertex,
+    },
 };
-use png::OutputInfo;
-use std::ops::Add;
+use std::{io, ops::Add};
 
 /// Stores vertex attributes and indices in memory.
 ///
@@ -22,21 +41,149 @@ use std::ops::Add;
 /// until they are uploaded to graphics memory by
 /// constructing a ``Tess``.
 pub struct PreGeometry {
-    pub vertices: Vec<VoxelVertex>,
+    pub vertices: Vertices,
     pub indices: Vec<u32>,
 }
 
+/// The vertices of a mesh, in the ``VertexFormat``
+/// that it was generated with.
+pub enum Vertices {
+    Full(Vec<VoxelVertex>),
+    Packed(Vec<PackedVertex>),
+}
+
+impl Vertices {
+    /// Return the number of vertices.
+    pub fn len(&self) -> usize {
+        match self {
+            Vertices::Full(vertices) => vertices.len(),
+            Vertices::Packed(vertices) => vertices.len(),
+        }
+    }
+
+    /// Returns ``true`` if there are no vertices.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Selects the format of the vertices that the
+/// meshers emit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum VertexFormat {
+    /// Every attribute is stored in floating point,
+    /// as a ``VoxelVertex`` of 68 bytes.
+    Full,
+
+    /// All attributes are packed into a ``PackedVertex``
+    /// of 12 bytes, which ``vs_packed.glsl`` decodes.
+    Packed,
+}
+
+impl VertexFormat {
+    /// Check that meshes in this format can refer to each
+    /// of ``tile_count`` tiles.
+    ///
+    /// An error is returned for the packed format if there
+    /// are more tiles than its 8-bit texture IDs can tell
+    /// apart.
+    pub fn check_tile_count(self, tile_count: u32) -> io::Result<()> {
+        match self {
+            VertexFormat::Packed if tile_count > packed::MAX_TILES => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "packed vertices support at most {} tiles, but there are {}",
+                    packed::MAX_TILES,
+                    tile_count
+                ),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
 // Visual length of the cube sides in
 // OpenGL model units.
 // const EDGE_LEN: f32 = 1.;
 
-// Square edge length of an individual
-// texture on the texture atlas in pixels.
-const TILE_SIZE: u32 = 16;
+// The light of a corner is the average of up to four
+// voxels, so it is kept in fractions of a light level.
+const LIGHT_STEPS: u8 = 4;
+
+// The seed that distinguishes tint variation hashes
+// from other hashes.
+const TINT_SEED: u32 = 0x7147;
+
+// The seed that distinguishes texture variant hashes
+// from other hashes.
+const VARIANT_SEED: u32 = 0x7A12;
+
+/// Selects how the textures of blocks are stored on the
+/// GPU, and thereby which texture coordinates the meshes
+/// carry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextureLayout {
+    /// All tiles share a single texture atlas that is
+    /// ``width`` by ``height`` pixels large. Each tile is
+    /// ``tile_size`` pixels wide and surrounded by a border
+    /// that is ``padding`` pixels wide. Vertices carry the
+    /// origin of their tile, and the fragment shader wraps
+    /// the repeating texture coordinate into it.
+    Atlas {
+        width: u32,
+        height: u32,
+        tile_size: u32,
+        padding: u32,
+    },
+
+    /// Every tile is a layer of a 2D texture array, and the
+    /// layer index is the block's texture ID. Vertices carry
+    /// the layer, and the repeating texture coordinate can
+    /// be sampled directly, so mipmapping works.
+    Array,
+}
+
+/// Describes the textures that sector meshes refer to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TerrainTextures {
+    /// How the tiles are stored on the GPU.
+    pub layout: TextureLayout,
+
+    /// The tile of every side of every block.
+    pub blocks: BlockTextures,
+}
+
+impl TerrainTextures {
+    /// Create a new ``TerrainTextures`` from its parts.
+    pub fn new(layout: TextureLayout, blocks: BlockTextures) -> TerrainTextures {
+        TerrainTextures { layout, blocks }
+    }
+}
 
-// Floating-point representation of the
-// ``TILE_SIZE`` constant.
-const TILE_SIZE_F32: f32 = TILE_SIZE as f32;
+#[cfg(test)]
+impl TerrainTextures {
+    /// Create textures for tests that mesh sectors without
+    /// loading the atlas. Every tile gets an ID derived from
+    /// its name, which fits into the packed vertex format.
+    pub fn for_tests(layout: TextureLayout) -> TerrainTextures {
+        let blocks = BlockTextures::resolve(|name| {
+            Some(
+                name.bytes()
+                    .fold(0u32, |id, b| id.wrapping_mul(31).wrapping_add(b as u32))
+                    % 256,
+            )
+        })
+        .unwrap();
+
+        TerrainTextures::new(layout, blocks)
+    }
+}
+
+// Returns the index of the axis that is neither
+// the U nor the V axis of a face.
+const fn normal_idx(u_idx: usize, v_idx: usize) -> usize {
+    3 - u_idx - v_idx
+}
 
 // Stores all information needed to represent
 // a single face of a cube block.
@@ -96,46 +243,64 @@ const POSITIONS: [[f32; 3]; 8] = [
     [0., 1., 1.],
 ];
 
-/// Generate the mesh for the given ``SectorData``.
+/// Generate the mesh for the sector that ``source``
+/// provides.
 ///
-/// If there are visible voxels in the data, their
+/// If there are visible voxels in the sector, their
 /// vertices are added to the pre-geometry, which
 /// is returned in a ``Some<PreGeometry>``.
 ///
 /// If, on the other hand, there are no visible voxels
-/// in the sector data, ``None`` is returned.
-pub fn gen_terrain(tex_info: &OutputInfo, voxels: &SectorData) -> Option<PreGeometry> {
-    // Initialize empty vectors to hold the vertex
-    // attribute data that will be generated.
-    // Also, keep track of the last index, as the
+/// in the sector, ``None`` is returned.
+///
+/// The light levels of the voxels in front of each face
+/// are baked into its vertices.
+///
+/// ``textures`` decides which tile each face shows, and
+/// whether the vertices address it on an atlas or in a
+/// texture array. ``format`` decides how the vertices
+/// are stored.
+///
+/// Every visible face of every voxel becomes its own
+/// quad. ``gen_terrain_greedy`` produces an equivalent
+/// mesh with far fewer vertices.
+pub fn gen_terrain(
+    textures: &TerrainTextures,
+    source: &impl MeshSource,
+    format: VertexFormat,
+) -> Option<PreGeometry> {
+    // A uniform sector is either empty or completely
+    // enclosed by the same blocks, so it never has any
+    // visible faces.
+    if source.uniform_block().is_some() {
+        return None;
+    }
+
+    // The builder holds the vertex attribute data that
+    // will be generated, as well as the indices, as the
     // voxels are drawn with Indexed Rendering.
-    let mut vertices = Vec::new();
-    let mut indices: Vec<u32> = Vec::new();
-    let mut current_index = 0;
+    let mut builder = MeshBuilder::new(textures.layout, format, source.index());
 
     // For every ``Block``, or voxel, in the sector, we
     // will need to draw between zero and six faces.
-    for (coords, blk) in voxels {
-        // Pull the x, y, z components out of the iterator's
-        // Item for the sake of readability.
+    //
+    // Blocks in the padding range of a sector should only be
+    // rendered by the neighboring sector, so they are skipped.
+    for coords in inner_coords() {
+        // Pull the x, y, z components out of the coordinates
+        // for the sake of readability.
         let SectorCoords(x, y, z) = coords;
+        let blk = source.block(coords);
 
-        // If a block lies in the padding range of a sector
-        // it should only be rendered by the neighboring sector.
-        // Skip it.
-        if x == SECTOR_MIN
-            || x == SECTOR_MAX
-            || y == SECTOR_MIN
-            || y == SECTOR_MAX
-            || z == SECTOR_MIN
-            || z == SECTOR_MAX
-        {
+        // If a block is air, it doesn't have any geometry,
+        // and is skipped.
+        if blk == Block::Air {
             continue;
         }
 
-        // If a block is air, it doesn't have any geometry,
-        // and is skipped.
-        if *blk == Block::Air {
+        // Blocks that are not cubes are drawn as their shape.
+        if !blk.is_cube() {
+            push_shape(&mut builder, textures, source, coords);
             continue;
         }
 
@@ -153,71 +318,537 @@ pub fn gen_terrain(tex_info: &OutputInfo, voxels: &SectorData) -> Option<PreGeom
         // The face attributes are hardcoded in the FACES
         // constant above.
         for f in &FACES {
-            // Check if the neighboring block occludes the face
-            // we are drawing.
-            if let Some(adj_coords) = coords.neighbor(f.side) {
-                // Look up the adjacent block.
-                let adj_block = voxels.block(adj_coords);
-
-                // If it does, skip drawing this face of block.
-                if !adj_block.is_transparent() {
-                    continue;
-                }
+            if !face_visible(source, coords, f.side) {
+                continue;
             }
 
             // If we are here, we are drawing one of the faces
-            // of the cube.
+            // of the cube. It covers exactly one tile.
+            //
+            // For some blocks, the texture depends on which side
+            // of the block is in consideration, so ``quad_texture``
+            // also takes the ``side`` field from our ``Face``.
+            let (texture, orientation) = quad_texture(
+                &textures.blocks,
+                blk,
+                f.side,
+                source.index(),
+                coords,
+                || connected::face_connections(source, blk, coords, f),
+            );
+            let quad = Quad {
+                factors,
+                extent: [1., 1.],
+                texture,
+                orientation,
+                ao: face_ao(source, coords, f),
+                light: face_light(source, coords, f),
+                tint: quad_tint(blk, f.side, source.index(), coords),
+            };
+
+            builder.push_face(f, &quad);
+        }
+    }
+
+    builder.finish()
+}
+
+/// Add the faces of the ``Block::shape`` of the block at
+/// ``coords``, which is not a cube, to ``builder``.
+///
+/// Faces on the boundary of the voxel are hidden, shaded and
+/// lit like the faces of cubes. Faces inside of the voxel are
+/// always visible, and take the light of the voxel itself.
+fn push_shape(
+    builder: &mut MeshBuilder,
+    textures: &TerrainTextures,
+    source: &impl MeshSource,
+    coords: SectorCoords,
+) {
+    let blk = source.block(coords);
+    let shape = match blk.shape() {
+        Some(shape) => shape,
+        None => return,
+    };
+
+    let SectorCoords(x, y, z) = coords;
+    let voxel = [x as f32, y as f32, z as f32];
+    let (min, max) = (
+        [shape.min.x, shape.min.y, shape.min.z],
+        [shape.max.x, shape.max.y, shape.max.z],
+    );
+
+    for f in &FACES {
+        let n_idx = normal_idx(f.u_idx, f.v_idx);
+
+        // The faces on the positive side of an axis lie at
+        // one along it on the unit cube.
+        let positive = POSITIONS[f.positions[0]][n_idx] > 0.;
+        let boundary = if positive {
+            max[n_idx] >= 1.
+        } else {
+            min[n_idx] <= 0.
+        };
+
+        if boundary && !face_visible(source, coords, f.side) {
+            continue;
+        }
+
+        let mut factors = [voxel[0] + min[0], voxel[1] + min[1], voxel[2] + min[2]];
+        if positive {
+            factors[n_idx] = voxel[n_idx] + max[n_idx] - 1.;
+        }
+
+        let (ao, light) = if boundary {
+            (face_ao(source, coords, f), face_light(source, coords, f))
+        } else {
+            let level = |channel| source.light(coords, channel) * LIGHT_STEPS;
+            (
+                [3; 4],
+                [[level(LightChannel::Sky), level(LightChannel::Block)]; 4],
+            )
+        };
+
+        let (texture, orientation) = quad_texture(
+            &textures.blocks,
+            blk,
+            f.side,
+            source.index(),
+            coords,
+            || connected::face_connections(source, blk, coords, f),
+        );
+        let quad = Quad {
+            factors: (factors[0], factors[1], factors[2]),
+            extent: [max[f.u_idx] - min[f.u_idx], max[f.v_idx] - min[f.v_idx]],
+            texture,
+            orientation,
+            ao,
+            light,
+            tint: quad_tint(blk, f.side, source.index(), coords),
+        };
+
+        builder.push_face(f, &quad);
+    }
+}
+
+// Iterate over the coordinates of every voxel inside of
+// the sector, leaving out the padding, in the order in
+// which ``SectorData`` stores them.
+fn inner_coords() -> impl Iterator<Item = SectorCoords> {
+    const DIM: usize = SECTOR_DIM_EXCL;
+
+    (0..DIM * DIM * DIM).map(|i| {
+        SectorCoords(
+            SECTOR_PAD + i % DIM,
+            SECTOR_PAD + i / DIM % DIM,
+            SECTOR_PAD + i / (DIM * DIM),
+        )
+    })
+}
+
+/// Returns ``true`` if the ``side`` face of the block at
+/// ``coords`` is not occluded by its neighbor.
+///
+/// Faces on the boundary of the padded sector are always
+/// considered visible.
+fn face_visible(source: &impl MeshSource, coords: SectorCoords, side: Side) -> bool {
+    match coords.neighbor(side) {
+        Some(adj_coords) => source.block(adj_coords).is_transparent(),
+        None => true,
+    }
+}
+
+/// Calculate the ambient occlusion of the four corners of
+/// the face of the block at ``coords``.
+///
+/// Each corner of a face touches three voxels in front of
+/// the face besides the one the face looks at: one along
+/// each edge of the face that meets the corner (the two
+/// "sides"), and the one diagonally across the corner.
+/// The occlusion value of the corner ranges from zero,
+/// for a fully occluded corner, to three, for a corner
+/// without any neighbors.
+///
+/// If both sides are opaque, the corner is fully occluded
+/// regardless of the diagonal voxel.
+///
+/// The corners are returned in the order of ``face.positions``.
+fn face_ao(source: &impl MeshSource, coords: SectorCoords, face: &Face) -> [u8; 4] {
+    let mut ao = [0; 4];
+
+    // The voxel that the face looks at. Since faces are only
+    // generated for voxels inside the sector, it is always in
+    // range, and so are its neighbors within the face's plane.
+    let front = match coords.neighbor(face.side) {
+        Some(front) => front,
+        None => return [3; 4],
+    };
+
+    let opaque =
+        |du: isize, dv: isize| !source.block(in_plane(front, face, du, dv)).is_transparent() as u8;
+
+    for (corner, pos_idx) in ao.iter_mut().zip(&face.positions) {
+        let (du, dv) = corner_direction(face, *pos_idx);
+
+        let side1 = opaque(du, 0);
+        let side2 = opaque(0, dv);
+        let diagonal = opaque(du, dv);
+
+        *corner = if side1 == 1 && side2 == 1 {
+            0
+        } else {
+            3 - side1 - side2 - diagonal
+        };
+    }
+
+    ao
+}
+
+/// Calculate the sky and block light of the four corners of
+/// the face of the block at ``coords``.
+///
+/// The light of a corner is the average light of the voxel
+/// the face looks at and the transparent voxels among the
+/// three that touch the corner, like in ``face_ao``. Light
+/// does not leak around a corner that both sides close off.
+///
+/// Each level is given in ``LIGHT_STEPS`` steps per light
+/// level, and the corners are returned in the order of
+/// ``face.positions``.
+fn face_light(source: &impl MeshSource, coords: SectorCoords, face: &Face) -> [[u8; 2]; 4] {
+    let mut levels = [[0; 2]; 4];
+
+    let front = match coords.neighbor(face.side) {
+        Some(front) => front,
+        None => return [[MAX_LIGHT * LIGHT_STEPS, 0]; 4],
+    };
+
+    let sample = |du: isize, dv: isize| {
+        let coords = in_plane(front, face, du, dv);
+
+        if source.block(coords).is_transparent() {
+            Some(coords)
+        } else {
+            None
+        }
+    };
+
+    for (corner, pos_idx) in levels.iter_mut().zip(&face.positions) {
+        let (du, dv) = corner_direction(face, *pos_idx);
+
+        let side1 = sample(du, 0);
+        let side2 = sample(0, dv);
+        let diagonal = if side1.is_none() && side2.is_none() {
+            None
+        } else {
+            sample(du, dv)
+        };
+
+        let samples = [Some(front), side1, side2, diagonal];
+        let count = samples.iter().flatten().count() as u32;
+
+        for (level, channel) in corner.iter_mut().zip(&LightChannel::ALL) {
+            let sum = samples
+                .iter()
+                .flatten()
+                .map(|coords| source.light(*coords, *channel) as u32)
+                .sum::<u32>();
+
+            // Round to the nearest step.
+            *level = ((sum * LIGHT_STEPS as u32 + count / 2) / count) as u8;
+        }
+    }
+
+    levels
+}
+
+// Return the directions along the U and V axes of the face
+// in which the corner at ``pos_idx`` lies.
+fn corner_direction(face: &Face, pos_idx: usize) -> (isize, isize) {
+    let rel = POSITIONS[pos_idx];
+    let du = if rel[face.u_idx] > 0. { 1 } else { -1 };
+    let dv = if rel[face.v_idx] > 0. { 1 } else { -1 };
+
+    (du, dv)
+}
+
+// Offset ``coords`` within the plane of the face.
+fn in_plane(coords: SectorCoords, face: &Face, du: isize, dv: isize) -> SectorCoords {
+    let mut components = [coords.0 as isize, coords.1 as isize, coords.2 as isize];
+
+    components[face.u_idx] += du;
+    components[face.v_idx] += dv;
+
+    SectorCoords(
+        components[0] as usize,
+        components[1] as usize,
+        components[2] as usize,
+    )
+}
+
+/// Choose the tile and orientation of the texture on the
+/// ``side`` face of ``blk``, which lies at ``coords`` in the
+/// sector at ``index``.
+///
+/// Blocks with connected textures show the tile for the
+/// neighbor mask that ``connections`` returns. For all other
+/// blocks, the choice is a hash of the world position, so it
+/// is the same every time the sector is meshed.
+fn quad_texture<F>(
+    textures: &BlockTextures,
+    blk: Block,
+    side: Side,
+    index: SectorIndex,
+    coords: SectorCoords,
+    connections: F,
+) -> (BlockTextureID, Orientation)
+where
+    F: FnOnce() -> u8,
+{
+    if let Some(strip) = textures.connected(blk) {
+        let tile = block::connection_tile(connections());
+        return (strip[tile], Orientation::default());
+    }
+
+    let variants = textures.variants(blk, side);
+    let orientations = blk.orientations(side);
+
+    if variants.len() == 1 && orientations.count() == 1 {
+        return (variants[0], Orientation::default());
+    }
+
+    let WorldCoords(x, y, z) = WorldCoords::from_padded(index, coords);
+    let h = hash::hash3(x, y, z, VARIANT_SEED ^ side as u32);
+
+    (
+        variants[(h & 0xFF) as usize % variants.len()],
+        orientations.get(h >> 8),
+    )
+}
+
+/// Determine the tint of the ``side`` face of ``blk``, which
+/// lies at ``coords`` in the sector at ``index``.
+fn quad_tint(blk: Block, side: Side, index: SectorIndex, coords: SectorCoords) -> QuadTint {
+    match blk.tint(side) {
+        Tint::None => QuadTint::Fixed([u8::MAX; 3]),
+        Tint::Constant(color) => QuadTint::Fixed(quantize(color)),
+        Tint::Biome => QuadTint::Biome,
+        Tint::Variation(amount) => {
+            let WorldCoords(x, y, z) = WorldCoords::from_padded(index, coords);
+            let h = hash::hash3(x, y, z, TINT_SEED);
+            let brightness = 1. - amount * (h & 0xFF) as f32 / 255.;
+
+            QuadTint::Fixed(quantize([brightness; 3]))
+        }
+    }
+}
+
+/// The color that the texture of a quad is multiplied by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum QuadTint {
+    /// The same 8-bit color at every corner.
+    Fixed([u8; 3]),
+
+    /// The grass color of the climate at each corner, so
+    /// that neighboring quads always match.
+    Biome,
+}
+
+/// Describes one quad of a sector mesh.
+struct Quad {
+    /// The lower left back corner of the quad.
+    factors: (f32, f32, f32),
+
+    /// The size of the quad along the U and V axes of
+    /// its face, in blocks. A single cube face has an
+    /// extent of ``[1., 1.]``.
+    extent: [f32; 2],
+
+    /// The texture ID of the tile that is repeated
+    /// across the quad.
+    texture: BlockTextureID,
+
+    /// How every repetition of the tile is turned.
+    /// See ``quad_texture``.
+    orientation: Orientation,
+
+    /// The ambient occlusion of each corner, in the order
+    /// of the face's positions. See ``face_ao``.
+    ao: [u8; 4],
+
+    /// The sky and block light of each corner, in the order
+    /// of the face's positions. See ``face_light``.
+    light: [[u8; 2]; 4],
+
+    /// The color of the quad. See ``quad_tint``.
+    tint: QuadTint,
+}
+
+/// Accumulates quads until the mesh is complete.
+struct MeshBuilder {
+    layout: TextureLayout,
+    index: SectorIndex,
+    vertices: Vertices,
+    indices: Vec<u32>,
+    current_index: u32,
+}
+
+impl MeshBuilder {
+    fn new(layout: TextureLayout, format: VertexFormat, index: SectorIndex) -> MeshBuilder {
+        let vertices = match format {
+            VertexFormat::Full => Vertices::Full(Vec::new()),
+            VertexFormat::Packed => Vertices::Packed(Vec::new()),
+        };
+
+        MeshBuilder {
+            layout,
+            index,
+            vertices,
+            indices: Vec::new(),
+            current_index: 0,
+        }
+    }
+
+    /// Add one quad in the plane of ``face``.
+    fn push_face(&mut self, face: &Face, quad: &Quad) {
+        let Quad {
+            factors,
+            extent,
+            texture,
+            orientation,
+            ao,
+            light,
+            tint,
+        } = *quad;
+
+        let (tile, layer) = tile_source(self.layout, texture);
+
+        // Each face has four vertices, so the loop below
+        // will run four times, once for each vertex in the
+        // quadrilateral face.
+        //
+        // pos_idx is (a reference to) an index into the hardcoded
+        // array of relative ``POSITIONS`` above.
+        for (i, pos_idx) in face.positions.iter().enumerate() {
+            // Stretch the unit cube position over the extent
+            // of the quad.
+            let rel = scale_position(POSITIONS[*pos_idx], face, extent);
+
+            // Add the vertex to the list of vertices that will be
+            // stored in the vertex buffer.
             //
-            // Each face has four vertices, so the loop below
-            // will run four times, once for each vertex in the
-            // quadrilateral face.
+            // The position must be converted from the relative cube
+            // position into the sector space. This is done by adding
+            // a different offset to each component, so that the origin
+            // of the cube in the correct "slot" in the sector grid.
             //
-            // pos_idx is (a reference to) an index into the hardcoded
-            // array of relative ``POSITIONS`` above.
-            for pos_idx in &f.positions {
-                let pos_idx = *pos_idx;
-
-                // Add the vertex to the list of vertices that will be
-                // stored in the vertex buffer.
-                //
-                // The position must be converted from the relative cube
-                // position into the sector space. This is done by adding
-                // a different offset to each component, so that the origin
-                // of the cube in the correct "slot" in the sector grid.
-                //
-                // As for the texture coordinate, it is calculated dynamically
-                // from the relative positions by the tex_coord function below.
-                vertices.push(VoxelVertex {
-                    pos: PosAttrib::new(translate3(POSITIONS[pos_idx], factors)),
-                    uv: UvAttrib::new(tex_coord(tex_info, blk, POSITIONS[pos_idx], f)),
-                });
+            // As for the texture coordinate, it is calculated dynamically
+            // from the relative positions by the tex_coord function below.
+            let pos = translate3(rel, factors);
+            let uv = orient(tex_coord(rel, face, extent), extent, orientation);
+            let color = match tint {
+                QuadTint::Fixed(color) => color,
+                QuadTint::Biome => self.biome_color(pos),
+            };
+
+            match &mut self.vertices {
+                Vertices::Full(vertices) => vertices.push(VoxelVertex {
+                    pos: PosAttrib::new(pos),
+                    uv: UvAttrib::new(uv),
+                    tile: TileAttrib::new(tile),
+                    layer: LayerAttrib::new(layer),
+                    ao: AoAttrib::new(ao[i] as f32),
+                    light: LightAttrib::new(light_level(light[i])),
+                    normal: NormalAttrib::new(face_normal(face.side)),
+                    tint: TintAttrib::new(tint_color(color)),
+                }),
+                Vertices::Packed(vertices) => vertices.push(pack_vertex(
+                    pos, uv, texture, face.side, ao[i], light[i], color,
+                )),
             }
+        }
+
+        // The quad is split into two triangles along one of
+        // its diagonals. Occlusion is interpolated differently
+        // depending on the diagonal, so the one that joins the
+        // less occluded pair of corners is chosen. Otherwise,
+        // a single dark corner would smear across the quad.
+        let first = if ao[0] + ao[2] < ao[1] + ao[3] { 1 } else { 0 };
+        let current_index = self.current_index;
+
+        // Each face uses the same relative set of indices
+        // for indexed rendering. Push the first triangle...
+        self.indices.push(current_index + first);
+        self.indices.push(current_index + (first + 1) % 4);
+        self.indices.push(current_index + (first + 2) % 4);
 
-            // Each face uses the same relative set of indices
-            // for indexed rendering. Push the first triangle...
-            indices.push(current_index);
-            indices.push(current_index + 1);
-            indices.push(current_index + 2);
+        // ... and the second.
+        self.indices.push(current_index + first);
+        self.indices.push(current_index + (first + 2) % 4);
+        self.indices.push(current_index + (first + 3) % 4);
 
-            // ... and the second.
-            indices.push(current_index);
-            indices.push(current_index + 2);
-            indices.push(current_index + 3);
+        // Each face has four vertices, so increment our
+        // counter by that fixed step.
+        self.current_index += 4;
+    }
+
+    // Return the grass color at a position in the sector.
+    fn biome_color(&self, pos: [f32; 3]) -> [u8; 3] {
+        let SectorIndex(sx, _, sz) = self.index;
+        let origin = |s: i32| (s * SECTOR_DIM_EXCL as i32 - SECTOR_PAD as i32) as f32;
+
+        quantize(biome::grass_color(origin(sx) + pos[0], origin(sz) + pos[2]))
+    }
 
-            // Each face has four vertices, so increment our
-            // counter by that fixed step.
-            current_index += 4;
+    fn finish(self) -> Option<PreGeometry> {
+        if self.current_index == 0 {
+            // In this case, there were no visible blocks
+            // in the sector, so None is returned for the
+            // model.
+            return None;
         }
+
+        Some(PreGeometry {
+            vertices: self.vertices,
+            indices: self.indices,
+        })
     }
+}
 
-    if current_index == 0 {
-        // In this case, there were no visible blocks
-        // in the sector, so None is returned for the
-        // model.
-        return None;
+// Returns the outward unit normal of the faces on the
+// given side of a cube.
+fn face_normal(side: Side) -> [f32; 3] {
+    match side {
+        Side::Front => [0., 0., 1.],
+        Side::Back => [0., 0., -1.],
+        Side::RightSide => [1., 0., 0.],
+        Side::LeftSide => [-1., 0., 0.],
+        Side::Top => [0., 1., 0.],
+        Side::Bottom => [0., -1., 0.],
     }
+}
+
+// Convert a color into 8 bits per channel.
+fn quantize(color: [f32; 3]) -> [u8; 3] {
+    let channel = |c: f32| (c.clamp(0., 1.) * 255.).round() as u8;
+
+    [channel(color[0]), channel(color[1]), channel(color[2])]
+}
 
-    Some(PreGeometry { vertices, indices })
+// Convert an 8-bit color into the range [0, 1].
+fn tint_color(color: [u8; 3]) -> [f32; 3] {
+    [
+        color[0] as f32 / 255.,
+        color[1] as f32 / 255.,
+        color[2] as f32 / 255.,
+    ]
+}
+
+// Convert the light of a corner into the range [0, 1].
+fn light_level(light: [u8; 2]) -> [f32; 2] {
+    let max = (MAX_LIGHT * LIGHT_STEPS) as f32;
+
+    [light[0] as f32 / max, light[1] as f32 / max]
 }
 
 // Returns the translated vertex position for the block with
@@ -233,14 +864,31 @@ where
     ]
 }
 
+// Scales the U and V components of a relative cube position
+// by the extent of a quad in the plane of the face.
+fn scale_position(orig: [f32; 3], face: &Face, extent: [f32; 2]) -> [f32; 3] {
+    let mut scaled = orig;
+
+    scaled[face.u_idx] *= extent[0];
+    scaled[face.v_idx] *= extent[1];
+
+    scaled
+}
+
 /// Calculate the texture coordinate for a vertex, given the relative
-/// cube position of the vertex and necessary metadata.
+/// position of the vertex on its quad and necessary metadata.
 ///
 /// The textures for the world are stored on a texture atlas.
 /// An individual texture on the atlas is called a "tile".
 ///
+/// The coordinate calculated here is relative to the *tile*, in units
+/// of whole tiles, so that a quad that spans several blocks repeats the
+/// tile once per block. The fragment shader wraps the coordinate into
+/// the tile whose origin is given by ``tile_origin``. With a texture
+/// array, the coordinate is used as is, since each layer repeats.
+///
 /// The texture coordinates are derived directly from the relative
-/// cube positions, passed as ``orig`` (for "original").
+/// quad positions, passed as ``orig`` (for "original").
 ///
 /// However, there is a complication. Depending on whether the face
 /// is on the side, top, or bottom of the cube, the 2D texture coodinates
@@ -257,69 +905,91 @@ where
 /// are flipped over either the U or V axis. To address this problem, a
 /// ``Face`` also stores boolean ``flip_u`` and ``flip_v`` fields that
 /// indicate whether the respective component of the texture coordinate
-/// should be inverted.
-///
-/// The two remaining arguments are ``tex_info`` and ``blk``.
-///
-/// ``tex_info`` is simply used to query the size of the texture atlas
-/// as a whole. This is necessary because OpenGL uses texture coordinate
-/// components in the relative range [0, 1], but the algorithm initially
-/// determines the texture coordinate in absolute pixel coordinates.
-/// Dividing by the width or height of the atlas yields the needed relative
-/// position.
-///
-/// ``blk`` is the block that we are creating the texture coordinate for.
-/// It is used to select the correct tile from the atlas.
+/// should be inverted. A flipped component is mirrored across the
+/// ``extent`` of the quad along that axis.
 #[rustfmt::skip]
-fn tex_coord(tex_info: &OutputInfo, blk: &Block, orig: [f32; 3], face: &Face) -> [f32; 2] {
+fn tex_coord(orig: [f32; 3], face: &Face, extent: [f32; 2]) -> [f32; 2] {
     // Alias some common values.
     let flip_u = face.flip_u;
     let flip_v = face.flip_v;
 
     let u_idx = face.u_idx;
     let v_idx = face.v_idx;
-    
-    let blk_side = face.side;
-    
-    // Query the size of the entire texture atlas.
-    let (width, height) = (tex_info.width, tex_info.height);
-    
+
+    // V is reversed since textures have an inverted y-axis.
+    let tile_u = if flip_u { -orig[u_idx] + extent[0] } else {  orig[u_idx]             };
+    let tile_v = if flip_v {  orig[v_idx]             } else { -orig[v_idx] + extent[1] };
+
+    [tile_u, tile_v]
+}
+
+/// Turn the texture coordinate ``uv`` of a quad with the given
+/// ``extent`` so that every repetition of the tile on the quad
+/// takes on ``orientation``.
+///
+/// Mirroring and turning the whole quad's coordinates by whole
+/// tiles does the same to each tile, since the shaders only
+/// look at the fractional part. The result still lies between
+/// zero and the extent, with U and V swapped by odd turns.
+fn orient(uv: [f32; 2], extent: [f32; 2], orientation: Orientation) -> [f32; 2] {
+    let [mut u, mut v] = uv;
+    let [mut width, mut height] = extent;
+
+    if orientation.mirrored {
+        u = width - u;
+    }
+
+    for _ in 0..orientation.quarter_turns {
+        let turned = [v, width - u];
+        u = turned[0];
+        v = turned[1];
+        std::mem::swap(&mut width, &mut height);
+    }
+
+    [u, v]
+}
+
+/// Return the atlas tile origin and the texture array layer
+/// of the tile with the texture ID ``blk_id``.
+///
+/// Only the one that ``layout`` uses is meaningful. The
+/// other is left at zero.
+fn tile_source(layout: TextureLayout, blk_id: BlockTextureID) -> ([f32; 2], f32) {
+    match layout {
+        TextureLayout::Atlas {
+            width,
+            height,
+            tile_size,
+            padding,
+        } => (tile_origin([width, height], tile_size, padding, blk_id), 0.),
+        TextureLayout::Array => ([0., 0.], blk_id as f32),
+    }
+}
+
+/// Calculate the texture coordinate of the upper left corner
+/// of the atlas tile with the texture ID ``blk_id``.
+///
+/// ``size`` is the size of the texture atlas as a whole, and each
+/// tile is ``tile_size`` pixels wide plus a border of ``padding``
+/// pixels on every side. The size is necessary because OpenGL uses
+/// texture coordinate components in the relative range [0, 1], but
+/// the algorithm initially determines the texture coordinate in
+/// absolute pixel coordinates. Dividing by the width or height of
+/// the atlas yields the needed relative position.
+#[rustfmt::skip]
+fn tile_origin(size: [u32; 2], tile_size: u32, padding: u32, blk_id: BlockTextureID) -> [f32; 2] {
+    // Each tile sits in a cell together with its border.
+    let cell = tile_size + 2 * padding;
+
     // Determine the number of tiles there are in a single row
     // of the texture atlas.
-    let tiles_per_row = width  / TILE_SIZE;
-    let tiles_per_col = height / TILE_SIZE;
-    
-    // Determine the texture coordinate with respect to the *tile*.
-    // These values will be in the open range [0, 1].
-    //
-    // V is reversed since textures have an inverted y-axis.
-    let tile_u = if flip_u { -orig[u_idx] + 1. } else {  orig[u_idx]      };
-    let tile_v = if flip_v {  orig[v_idx]      } else { -orig[v_idx] + 1. };
-    
-    // A small (half-pixel) adjustment needs to be added or subtracted to or from
-    // the ``tile_u`` and ``tile_v`` values.
-    //
-    // The offset is equal to 1 / 256 for a tile size of 16, which allows the
-    // texture coordinate to lie just within the bounds of the target pixel,
-    // rather than exactly the edge.
-    //
-    // Without this offset, fragments from the neighboring tile may be erroneously
-    // rendered.
-    let offset = 1. / (16. * TILE_SIZE_F32);
-    
-    let tile_u_adj = if tile_u < 0.5 { tile_u + offset } else { tile_u - offset };
-    let tile_v_adj = if tile_v < 0.5 { tile_v + offset } else { tile_v - offset };
-    
-    // Determine the block's texture id, and convert it to a f32.
-    // For some blocks, the texture depends on which side of the
-    // block is in consideration, so the ``texture_id`` method
-    // also takes the ``side`` field from our ``Face``.
-    let blk_id = blk.texture_id(blk_side);
-    
-    let atlas_u = (blk_id % tiles_per_row) as f32;
-    let atlas_v = (blk_id / tiles_per_row) as f32;
-    
-    // Select the correct corner of the tile in question.
-    [(tile_u_adj + atlas_u) * TILE_SIZE_F32 / width as f32,
-     (tile_v_adj + atlas_v) * TILE_SIZE_F32 / height as f32]
+    let tiles_per_row = size[0] / cell;
+
+    // Convert the block's texture id to the tile's column and row.
+    let atlas_u = blk_id % tiles_per_row;
+    let atlas_v = blk_id / tiles_per_row;
+
+    // Select the corner of the tile in question, inside its border.
+    [(atlas_u * cell + padding) as f32 / size[0] as f32,
+     (atlas_v * cell + padding) as f32 / size[1] as f32]
 }
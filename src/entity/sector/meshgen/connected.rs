@@ -0,0 +1,202 @@
+//! Finds the connections of faces with connected textures.
+//!
+//! A face connects to each of the eight faces around it in its
+//! plane that belong to the same kind of block and are visible
+//! themselves. A face whose neighbor is covered, for example
+//! by a block in front of it, draws its border there, so that
+//! the outline of every flat area of the block stays closed.
+//!
+//! The neighbors may lie in the padding of the sector, so the
+//! textures connect across sector boundaries as well.
+
+use super::{in_plane, Face, MeshSource};
+use crate::{block::Block, entity::sector::data::SectorCoords};
+
+// The directions of the bits of a neighbor mask, as seen
+// on the texture. See ``block::connection_tile``.
+const DIRECTIONS: [(isize, isize); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+
+/// Return the mask of the neighbors that the ``face`` of
+/// ``blk`` at ``coords`` connects to, for use with
+/// ``block::connection_tile``.
+///
+/// The face itself must be visible.
+pub(super) fn face_connections(
+    source: &impl MeshSource,
+    blk: Block,
+    coords: SectorCoords,
+    face: &Face,
+) -> u8 {
+    // Inner voxels always have a neighbor in front.
+    let front = coords.neighbor(face.side).unwrap();
+
+    // Texture coordinates may run against the U and V axes
+    // of the face, and V points down on the texture unless
+    // it is flipped. See ``tex_coord``.
+    let sign_u = if face.flip_u { -1 } else { 1 };
+    let sign_v = if face.flip_v { -1 } else { 1 };
+
+    let mut mask = 0;
+    for (bit, (right, up)) in DIRECTIONS.iter().enumerate() {
+        let (du, dv) = (right * sign_u, up * sign_v);
+
+        let same = source.block(in_plane(coords, face, du, dv)) == blk;
+        let uncovered = source.block(in_plane(front, face, du, dv)).is_transparent();
+
+        if same && uncovered {
+            mask |= 1 << bit;
+        }
+    }
+
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{gen_terrain, MeshNeighborhood, TerrainTextures, TextureLayout, Vertices, FACES},
+        *,
+    };
+    use crate::{
+        block,
+        entity::sector::{
+            data::{SECTOR_DIM_EXCL, SECTOR_PAD},
+            LightData, SectorData, SectorIndex, VertexFormat, WorldCoords,
+        },
+    };
+    use std::collections::HashMap;
+
+    type World = HashMap<SectorIndex, (SectorData, LightData)>;
+
+    // The two sectors on either side of ``x = 0``.
+    const WEST: SectorIndex = SectorIndex(-1, 0, 0);
+    const EAST: SectorIndex = SectorIndex(0, 0, 0);
+
+    // A wall of bricks from ``x = -4`` to ``x = 3`` and from
+    // ``y = 0`` to ``y = 3`` at ``z = 0``, across the boundary
+    // of the two sectors. The front face of one of them may be
+    // covered by a block of stone at ``cover``.
+    fn wall(cover: Option<WorldCoords>) -> World {
+        let mut world = HashMap::new();
+
+        for index in &[WEST, EAST] {
+            let mut voxels = SectorData::new();
+
+            for (coords, blk) in voxels.iter_mut() {
+                let pos = WorldCoords::from_sector(*index, coords);
+                let WorldCoords(x, y, z) = pos;
+
+                if Some(pos) == cover {
+                    *blk = Block::Stone;
+                } else if (-4..4).contains(&x) && (0..4).contains(&y) && z == 0 {
+                    *blk = Block::Bricks;
+                }
+            }
+
+            world.insert(*index, (voxels, LightData::new()));
+        }
+
+        world
+    }
+
+    // Return what the mesher of the sector at ``index`` sees.
+    fn neighborhood(world: &World, index: SectorIndex) -> MeshNeighborhood<'_> {
+        let SectorIndex(x, y, z) = index;
+        let (voxels, light) = &world[&index];
+
+        MeshNeighborhood::new(index, voxels, light, |[dx, dy, dz]| {
+            world
+                .get(&SectorIndex(x + dx, y + dy, z + dz))
+                .map(|(voxels, light)| (voxels, light))
+        })
+    }
+
+    // Return the padded coordinates of ``pos`` in its sector.
+    fn padded(pos: WorldCoords) -> SectorCoords {
+        let WorldCoords(x, y, z) = pos;
+        let padded = |c: i32| c.rem_euclid(SECTOR_DIM_EXCL as i32) as usize + SECTOR_PAD;
+
+        SectorCoords(padded(x), padded(y), padded(z))
+    }
+
+    // Return the neighbor mask of the front face of the brick
+    // at ``pos``.
+    fn front_mask(world: &World, pos: WorldCoords) -> u8 {
+        let source = neighborhood(world, pos.sector_index());
+
+        face_connections(&source, Block::Bricks, padded(pos), &FACES[0])
+    }
+
+    #[test]
+    fn walls_connect_across_sector_boundaries() {
+        let world = wall(None);
+
+        // The bricks next to the boundary connect to each other
+        // like the bricks inside of the wall.
+        for x in -2..2 {
+            assert_eq!(front_mask(&world, WorldCoords(x, 1, 0)), 0xFF, "{}", x);
+            assert_eq!(front_mask(&world, WorldCoords(x, 2, 0)), 0xFF, "{}", x);
+        }
+
+        // Only the outline of the wall has a border.
+        assert_ne!(front_mask(&world, WorldCoords(-4, 1, 0)), 0xFF);
+        assert_ne!(front_mask(&world, WorldCoords(3, 1, 0)), 0xFF);
+        assert_ne!(front_mask(&world, WorldCoords(0, 0, 0)), 0xFF);
+
+        // Both meshes show the tile without any border on the
+        // faces next to the boundary.
+        let textures = TerrainTextures::for_tests(TextureLayout::Array);
+        let strip = textures.blocks.connected(Block::Bricks).unwrap();
+        let inner = strip[block::connection_tile(0xFF)];
+
+        for pos in &[WorldCoords(-1, 1, 0), WorldCoords(0, 1, 0)] {
+            let source = neighborhood(&world, pos.sector_index());
+            let mesh = gen_terrain(&textures, &source, VertexFormat::Full).unwrap();
+            let vertices = match &mesh.vertices {
+                Vertices::Full(vertices) => vertices,
+                Vertices::Packed(_) => unreachable!(),
+            };
+
+            let SectorCoords(x, y, z) = padded(*pos);
+            let (x, y, front) = (x as f32, y as f32, (z + 1) as f32);
+            let face = vertices
+                .chunks(4)
+                .find(|quad| {
+                    quad.iter().all(|v| {
+                        let [vx, vy, vz] = *v.pos;
+                        vz == front && (vx == x || vx == x + 1.) && (vy == y || vy == y + 1.)
+                    })
+                })
+                .unwrap();
+
+            assert_eq!(*face[0].layer, inner as f32, "{:?}", pos);
+        }
+    }
+
+    #[test]
+    fn covered_neighbors_get_a_border() {
+        // Whichever side of the boundary is covered, the brick
+        // across it draws its border towards the cover, and
+        // only there.
+        for (covered, next) in &[(0, -1), (-1, 0)] {
+            let world = wall(Some(WorldCoords(*covered, 1, 1)));
+            let mask = front_mask(&world, WorldCoords(*next, 1, 0));
+
+            assert_eq!(mask.count_ones(), 7);
+            assert_ne!(block::connection_tile(mask), block::connection_tile(0xFF));
+
+            // The bricks farther away still connect.
+            let beyond = 2 * next - covered;
+            assert_eq!(front_mask(&world, WorldCoords(beyond, 1, 0)), 0xFF);
+        }
+    }
+}
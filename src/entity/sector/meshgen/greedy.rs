@@ -0,0 +1,346 @@
+//! Implements greedy meshing of sectors.
+//!
+//! Instead of emitting one quad for each visible voxel face,
+//! the greedy mesher merges adjacent coplanar faces that share
+//! the same texture into larger rectangles. A flat layer of
+//! grass on top of a sector, for example, becomes a single quad
+//! rather than 256 of them.
+//!
+//! The faces of each side are processed one slice of the sector
+//! at a time. The visible faces of a slice are collected into a
+//! mask, and rectangles are grown from the mask greedily: first
+//! as wide as possible, then as tall as the whole row allows.
+//!
+//! Faces whose corners are occluded or lit unevenly are never
+//! merged, since their shading could not be reproduced by a
+//! larger quad. Neither are faces of different fixed tints,
+//! tile variants or orientations, so blocks that vary their
+//! textures merge less.
+//! Biome tints are sampled at the corners of each quad, so a
+//! merged quad blends the climate colors a little more coarsely
+//! than single faces do.
+//!
+//! Blocks that are not cubes never merge, and are drawn on
+//! their own after all slices.
+
+use super::{
+    connected::face_connections, face_ao, face_light, face_visible, inner_coords, normal_idx,
+    push_shape, quad_texture, quad_tint, Face, MeshBuilder, MeshSource, PreGeometry, Quad,
+    QuadTint, TerrainTextures, VertexFormat, FACES,
+};
+use crate::{
+    block::{Block, BlockTextureID, Orientation},
+    entity::sector::data::{SectorCoords, SECTOR_DIM_EXCL, SECTOR_PAD},
+};
+
+// The number of faces along one edge of a slice.
+const SLICE_DIM: usize = SECTOR_DIM_EXCL;
+
+// Describes a visible face in a slice mask.
+//
+// Only faces with equal keys may be merged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct FaceKey {
+    texture: BlockTextureID,
+    orientation: Orientation,
+    ao: [u8; 4],
+    light: [[u8; 2]; 4],
+    tint: QuadTint,
+}
+
+impl FaceKey {
+    // Returns ``true`` if the face may be merged with others.
+    //
+    // Vertex attributes are interpolated across the whole
+    // merged quad, so only faces whose corners all share the
+    // same occlusion and light can be merged without changing
+    // the look.
+    fn mergeable(&self) -> bool {
+        self.ao.iter().all(|corner| *corner == self.ao[0])
+            && self.light.iter().all(|corner| *corner == self.light[0])
+    }
+}
+
+/// Generate the mesh for the sector that ``source``
+/// provides, merging coplanar faces with the same texture.
+///
+/// The result covers exactly the same faces as the
+/// mesh that ``gen_terrain`` generates, and is ``None``
+/// under the same circumstances.
+pub fn gen_terrain_greedy(
+    textures: &TerrainTextures,
+    source: &impl MeshSource,
+    format: VertexFormat,
+) -> Option<PreGeometry> {
+    if source.uniform_block().is_some() {
+        return None;
+    }
+
+    let mut builder = MeshBuilder::new(textures.layout, format, source.index());
+    let mut mask = [None; SLICE_DIM * SLICE_DIM];
+
+    for f in &FACES {
+        let (u_idx, v_idx) = (f.u_idx, f.v_idx);
+        let n_idx = normal_idx(u_idx, v_idx);
+
+        for depth in 0..SLICE_DIM {
+            // Collect the visible faces of this slice.
+            for b in 0..SLICE_DIM {
+                for a in 0..SLICE_DIM {
+                    let coords = slice_coords(u_idx, v_idx, n_idx, a, b, depth);
+                    mask[a + b * SLICE_DIM] = face_key(textures, source, coords, f);
+                }
+            }
+
+            // Grow rectangles out of the mask.
+            for b in 0..SLICE_DIM {
+                let mut a = 0;
+
+                while a < SLICE_DIM {
+                    let key = match mask[a + b * SLICE_DIM] {
+                        Some(key) => key,
+                        None => {
+                            a += 1;
+                            continue;
+                        }
+                    };
+
+                    let (w, h) = grow_rect(&mask, a, b, key);
+
+                    // Each face may only be covered once.
+                    for row in b..b + h {
+                        for cell in &mut mask[a + row * SLICE_DIM..a + w + row * SLICE_DIM] {
+                            *cell = None;
+                        }
+                    }
+
+                    let SectorCoords(x, y, z) = slice_coords(u_idx, v_idx, n_idx, a, b, depth);
+
+                    let quad = Quad {
+                        factors: (x as f32, y as f32, z as f32),
+                        extent: [w as f32, h as f32],
+                        texture: key.texture,
+                        orientation: key.orientation,
+                        ao: key.ao,
+                        light: key.light,
+                        tint: key.tint,
+                    };
+
+                    builder.push_face(f, &quad);
+
+                    a += w;
+                }
+            }
+        }
+    }
+
+    for coords in inner_coords() {
+        let blk = source.block(coords);
+
+        if blk != Block::Air && !blk.is_cube() {
+            push_shape(&mut builder, textures, source, coords);
+        }
+    }
+
+    builder.finish()
+}
+
+// Return the key of the ``side`` face of the voxel at
+// ``coords``, or ``None`` if the face is not visible.
+fn face_key(
+    textures: &TerrainTextures,
+    source: &impl MeshSource,
+    coords: SectorCoords,
+    face: &Face,
+) -> Option<FaceKey> {
+    let blk = source.block(coords);
+    let side = face.side;
+
+    if !blk.is_cube() || !face_visible(source, coords, side) {
+        return None;
+    }
+
+    let (texture, orientation) =
+        quad_texture(&textures.blocks, blk, side, source.index(), coords, || {
+            face_connections(source, blk, coords, face)
+        });
+
+    Some(FaceKey {
+        texture,
+        orientation,
+        ao: face_ao(source, coords, face),
+        light: face_light(source, coords, face),
+        tint: quad_tint(blk, side, source.index(), coords),
+    })
+}
+
+// Return the width and height of the largest rectangle
+// of faces with the given key that has its lower left
+// corner at ``(a, b)`` and is grown along U first.
+fn grow_rect(mask: &[Option<FaceKey>], a: usize, b: usize, key: FaceKey) -> (usize, usize) {
+    if !key.mergeable() {
+        return (1, 1);
+    }
+
+    let mut w = 1;
+    while a + w < SLICE_DIM && mask[a + w + b * SLICE_DIM] == Some(key) {
+        w += 1;
+    }
+
+    let mut h = 1;
+    while b + h < SLICE_DIM {
+        let row = (b + h) * SLICE_DIM;
+
+        if mask[a + row..a + w + row]
+            .iter()
+            .any(|cell| *cell != Some(key))
+        {
+            break;
+        }
+
+        h += 1;
+    }
+
+    (w, h)
+}
+
+// Return the sector coordinates of the voxel at ``(a, b)``
+// in the slice at ``depth`` along the normal axis.
+fn slice_coords(
+    u_idx: usize,
+    v_idx: usize,
+    n_idx: usize,
+    a: usize,
+    b: usize,
+    depth: usize,
+) -> SectorCoords {
+    let mut components = [0; 3];
+
+    components[u_idx] = a + SECTOR_PAD;
+    components[v_idx] = b + SECTOR_PAD;
+    components[n_idx] = depth + SECTOR_PAD;
+
+    SectorCoords(components[0], components[1], components[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::TextureLayout, *};
+    use crate::{
+        entity::sector::{gen_terrain, PaddedSector, SectorIndex, Vertices, MAX_LIGHT},
+        side::Side,
+        vertexattrib::VoxelVertex,
+    };
+
+    // A floor of ``blk`` that reaches through the padding,
+    // so only its top is visible. ``hole`` is a voxel of the
+    // top layer that is left out.
+    fn floor(blk: Block, hole: Option<SectorCoords>) -> PaddedSector {
+        PaddedSector::new(
+            SectorIndex(2, -1, 5),
+            |coords| {
+                if coords.1 <= FLOOR_TOP && Some(coords) != hole {
+                    blk
+                } else {
+                    Block::Air
+                }
+            },
+            |_, _| MAX_LIGHT,
+        )
+    }
+
+    // The padded Y coordinate of the top layer of ``floor``.
+    const FLOOR_TOP: usize = SECTOR_PAD + SECTOR_DIM_EXCL / 2;
+
+    // Return the vertices of each quad of ``mesh``, along with
+    // the padded coordinates of the voxel at its lower corner.
+    fn quads(mesh: &PreGeometry) -> Vec<(SectorCoords, &[VoxelVertex])> {
+        match &mesh.vertices {
+            Vertices::Full(vertices) => vertices
+                .chunks(4)
+                .map(|quad| {
+                    let corner = |axis: usize| {
+                        quad.iter()
+                            .map(|v| v.pos[axis])
+                            .fold(f32::INFINITY, f32::min) as usize
+                    };
+
+                    (SectorCoords(corner(0), FLOOR_TOP, corner(2)), quad)
+                })
+                .collect(),
+            Vertices::Packed(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn flat_floors_become_single_quads() {
+        let textures = TerrainTextures::for_tests(TextureLayout::Array);
+
+        for blk in &[Block::TestBlock, Block::Bricks] {
+            let mesh = gen_terrain_greedy(&textures, &floor(*blk, None), VertexFormat::Full);
+            assert_eq!(mesh.unwrap().vertices.len(), 4, "{:?}", blk);
+        }
+    }
+
+    #[test]
+    fn merged_faces_share_their_tile_and_orientation() {
+        let textures = TerrainTextures::for_tests(TextureLayout::Array);
+
+        for blk in &[Block::Stone, Block::Grass] {
+            let source = floor(*blk, None);
+            let mesh = gen_terrain_greedy(&textures, &source, VertexFormat::Full).unwrap();
+            let quads = quads(&mesh);
+
+            // Some runs of equal faces are merged even so.
+            assert!(quads.len() < SECTOR_DIM_EXCL * SECTOR_DIM_EXCL);
+
+            for (corner, quad) in quads {
+                let choose = |coords| {
+                    quad_texture(
+                        &textures.blocks,
+                        *blk,
+                        Side::Top,
+                        source.index(),
+                        coords,
+                        || 0,
+                    )
+                };
+                let (texture, orientation) = choose(corner);
+                assert_eq!(*quad[0].layer, texture as f32);
+
+                let SectorCoords(x0, y, z0) = corner;
+                let x1 = quad.iter().map(|v| v.pos[0] as usize).max().unwrap();
+                let z1 = quad.iter().map(|v| v.pos[2] as usize).max().unwrap();
+
+                for x in x0..x1 {
+                    for z in z0..z1 {
+                        assert_eq!(choose(SectorCoords(x, y, z)), (texture, orientation));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_stay_the_same_when_remeshed() {
+        let textures = TerrainTextures::for_tests(TextureLayout::Array);
+        let hole = SectorCoords(SECTOR_PAD + 3, FLOOR_TOP, SECTOR_PAD + 5);
+
+        for blk in &[Block::Stone, Block::Grass, Block::Soil] {
+            let before = gen_terrain(&textures, &floor(*blk, None), VertexFormat::Full).unwrap();
+            let after =
+                gen_terrain(&textures, &floor(*blk, Some(hole)), VertexFormat::Full).unwrap();
+            let before = quads(&before);
+
+            // Digging a hole only changes the faces next to it,
+            // the others keep their tiles and orientations.
+            for (SectorCoords(x, _, z), quad) in quads(&after) {
+                if (x as i32 - hole.0 as i32).abs() <= 1 && (z as i32 - hole.2 as i32).abs() <= 1 {
+                    continue;
+                }
+
+                assert!(before.iter().any(|(_, q)| *q == quad), "{:?}", blk);
+            }
+        }
+    }
+}
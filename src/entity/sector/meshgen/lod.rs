@@ -0,0 +1,335 @@
+//! Implements level-of-detail meshes for distant sectors.
+//!
+//! At level ``n``, a sector is divided into cells that are
+//! ``2^n`` blocks wide along each axis, and every cell is
+//! drawn as a single large block. A cell is solid if at least
+//! half of its voxels are. It takes on the most common block
+//! of the highest layer that holds any solid voxel, so that
+//! grassy hills stay green from afar.
+//!
+//! Neighboring sectors may be drawn at different levels, and
+//! their surfaces then do not quite meet. Solid cells at the
+//! four vertical edges of a sector that are part of the surface
+//! therefore get a skirt: their outer wall is always drawn, and
+//! it reaches one cell further down to hide any crack behind it.
+
+use super::{
+    gen_terrain_greedy, normal_idx, quad_texture, quad_tint, Face, MeshBuilder, MeshSource,
+    PreGeometry, Quad, TerrainTextures, VertexFormat, FACES, LIGHT_STEPS, POSITIONS,
+};
+use crate::{
+    block::Block,
+    entity::sector::{
+        data::{SectorCoords, SECTOR_DIM, SECTOR_DIM_EXCL, SECTOR_PAD},
+        light::LightChannel,
+    },
+    side::Side,
+};
+use std::cmp::Reverse;
+
+/// The number of levels of detail, including the
+/// full resolution level zero.
+pub const LOD_LEVELS: usize = 4;
+
+// The distance in blocks beyond which each of the
+// coarser levels is used.
+const LOD_DISTANCES: [f32; LOD_LEVELS - 1] = [64., 128., 224.];
+
+/// Return the level of detail for a sector whose center
+/// is ``distance`` blocks away from the viewer.
+pub fn lod_for_distance(distance: f32) -> usize {
+    LOD_DISTANCES.iter().filter(|d| distance > **d).count()
+}
+
+/// Generate the mesh for the sector that ``source``
+/// provides at the given level of detail, between zero
+/// and ``LOD_LEVELS`` (exclusive).
+///
+/// Level zero is the full resolution mesh that
+/// ``gen_terrain_greedy`` generates. At every further
+/// level, the edge length of the drawn cells doubles.
+/// Coarse meshes have no ambient occlusion, and each
+/// face is lit evenly by the brightest voxel in front
+/// of it.
+pub fn gen_terrain_lod(
+    textures: &TerrainTextures,
+    source: &impl MeshSource,
+    level: usize,
+    format: VertexFormat,
+) -> Option<PreGeometry> {
+    if level == 0 {
+        return gen_terrain_greedy(textures, source, format);
+    }
+
+    if source.uniform_block().is_some() {
+        return None;
+    }
+
+    let cells = Cells::downsample(source, 1 << level);
+    let mut builder = MeshBuilder::new(textures.layout, format, source.index());
+
+    for z in 1..=cells.dim {
+        for y in 1..=cells.dim {
+            for x in 1..=cells.dim {
+                let cell = [x, y, z];
+                let blk = cells.get(cell);
+
+                if blk.is_transparent() {
+                    continue;
+                }
+
+                let exposed = FACES
+                    .iter()
+                    .any(|f| cells.get(neighbor(cell, f.side)).is_transparent());
+
+                for f in &FACES {
+                    let adj = neighbor(cell, f.side);
+                    let skirt = exposed && is_vertical(f.side) && cells.is_padding(adj);
+
+                    if !skirt && !cells.get(adj).is_transparent() {
+                        continue;
+                    }
+
+                    // A skirt facing a solid cell is lit like the
+                    // top of the cell, rather than by the darkness
+                    // inside of the neighboring sector.
+                    let front = if cells.get(adj).is_transparent() {
+                        adj
+                    } else {
+                        neighbor(cell, Side::Top)
+                    };
+
+                    let (factors, extent) = cells.placement(cell, f, skirt);
+
+                    // A coarse cell repeats its tile across several
+                    // blocks, so connected textures are drawn without
+                    // any borders.
+                    let first = cells.first_voxel(cell);
+                    let (texture, orientation) =
+                        quad_texture(&textures.blocks, blk, f.side, source.index(), first, || {
+                            u8::MAX
+                        });
+                    let quad = Quad {
+                        factors,
+                        extent,
+                        texture,
+                        orientation,
+                        ao: [3; 4],
+                        light: cells.light(source, front),
+                        tint: quad_tint(blk, f.side, source.index(), first),
+                    };
+
+                    builder.push_face(f, &quad);
+                }
+            }
+        }
+    }
+
+    builder.finish()
+}
+
+// A sector downsampled into cells, surrounded by a
+// layer of cells that stand for the sector's padding.
+struct Cells {
+    // The number of cells along each axis, not
+    // counting the padding.
+    dim: usize,
+
+    // The edge length of a cell in blocks.
+    scale: usize,
+
+    blocks: Vec<Block>,
+}
+
+impl Cells {
+    fn downsample(source: &impl MeshSource, scale: usize) -> Cells {
+        let dim = SECTOR_DIM_EXCL / scale;
+        let padded = dim + 2;
+
+        let mut cells = Cells {
+            dim,
+            scale,
+            blocks: vec![Block::Air; padded * padded * padded],
+        };
+
+        for z in 0..padded {
+            for y in 0..padded {
+                for x in 0..padded {
+                    let cell = [x, y, z];
+
+                    // Only the padding cells that share a face with
+                    // an inner cell are ever looked at.
+                    let n_padding = cell
+                        .iter()
+                        .filter(|c| **c == 0 || **c == padded - 1)
+                        .count();
+                    if n_padding > 1 {
+                        continue;
+                    }
+
+                    let blk = representative(source, cells.voxel_range(cell));
+                    let idx = cells.index(cell);
+                    cells.blocks[idx] = blk;
+                }
+            }
+        }
+
+        cells
+    }
+
+    fn get(&self, cell: [usize; 3]) -> Block {
+        self.blocks[self.index(cell)]
+    }
+
+    fn is_padding(&self, cell: [usize; 3]) -> bool {
+        cell.iter().any(|c| *c == 0 || *c == self.dim + 1)
+    }
+
+    // Return the corner and the extent of the quad for the
+    // ``face`` of a cell. Skirts reach one cell further down.
+    fn placement(&self, cell: [usize; 3], face: &Face, skirt: bool) -> ((f32, f32, f32), [f32; 2]) {
+        let scale = self.scale as f32;
+        let n_idx = normal_idx(face.u_idx, face.v_idx);
+
+        let range = self.voxel_range(cell);
+        let mut corner = [range[0].0 as f32, range[1].0 as f32, range[2].0 as f32];
+
+        // The face is drawn by stretching a unit cube face
+        // along U and V only, so a face on the far side of
+        // the cell must be moved to the cell's far side.
+        if POSITIONS[face.positions[0]][n_idx] > 0. {
+            corner[n_idx] += scale - 1.;
+        }
+
+        let mut extent = [scale, scale];
+        if skirt {
+            corner[face.v_idx] -= scale;
+            extent[1] += scale;
+        }
+
+        ((corner[0], corner[1], corner[2]), extent)
+    }
+
+    // Return the light of a face whose front lies in the
+    // given cell, which is the brightest light of any voxel
+    // of that cell.
+    fn light(&self, source: &impl MeshSource, cell: [usize; 3]) -> [[u8; 2]; 4] {
+        let [(x0, x1), (y0, y1), (z0, z1)] = self.voxel_range(cell);
+        let mut brightest = [0; 2];
+
+        for z in z0..z1 {
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let coords = SectorCoords(x, y, z);
+
+                    if !source.block(coords).is_transparent() {
+                        continue;
+                    }
+
+                    for (level, channel) in brightest.iter_mut().zip(&LightChannel::ALL) {
+                        *level = (*level).max(source.light(coords, *channel) * LIGHT_STEPS);
+                    }
+                }
+            }
+        }
+
+        [brightest; 4]
+    }
+
+    // Return the coordinates of the lowest voxel of a cell.
+    fn first_voxel(&self, cell: [usize; 3]) -> SectorCoords {
+        let [(x, _), (y, _), (z, _)] = self.voxel_range(cell);
+
+        SectorCoords(x, y, z)
+    }
+
+    // Return the range of padded sector coordinates that a
+    // cell covers along each axis. Padding cells only cover
+    // the single layer of padding voxels.
+    fn voxel_range(&self, cell: [usize; 3]) -> [(usize, usize); 3] {
+        let mut range = [(0, 0); 3];
+
+        for (axis, c) in cell.iter().enumerate() {
+            range[axis] = if *c == 0 {
+                (0, SECTOR_PAD)
+            } else if *c == self.dim + 1 {
+                (SECTOR_DIM - SECTOR_PAD, SECTOR_DIM)
+            } else {
+                let start = SECTOR_PAD + (c - 1) * self.scale;
+                (start, start + self.scale)
+            };
+        }
+
+        range
+    }
+
+    fn index(&self, cell: [usize; 3]) -> usize {
+        let padded = self.dim + 2;
+
+        cell[0] + cell[1] * padded + cell[2] * padded * padded
+    }
+}
+
+// Return the block that stands for the voxels in the given
+// ranges of padded sector coordinates.
+fn representative(source: &impl MeshSource, range: [(usize, usize); 3]) -> Block {
+    let [(x0, x1), (y0, y1), (z0, z1)] = range;
+    let total = (x1 - x0) * (y1 - y0) * (z1 - z0);
+
+    let mut solid = 0;
+    let mut top = None;
+
+    // Scan from the top down, remembering the most common
+    // block of the first layer that holds a solid voxel.
+    for y in (y0..y1).rev() {
+        let mut counts = [0; Block::ALL.len()];
+
+        for z in z0..z1 {
+            for x in x0..x1 {
+                let blk = source.block(SectorCoords(x, y, z));
+
+                if !blk.is_transparent() {
+                    counts[blk.id() as usize] += 1;
+                }
+            }
+        }
+
+        let layer_solid: usize = counts.iter().sum();
+        solid += layer_solid;
+
+        if top.is_none() && layer_solid > 0 {
+            let (id, _) = counts
+                .iter()
+                .enumerate()
+                .max_by_key(|(id, count)| (**count, Reverse(*id)))
+                .unwrap();
+
+            top = Block::from_id(id as u8);
+        }
+    }
+
+    match top {
+        Some(blk) if solid * 2 >= total => blk,
+        _ => Block::Air,
+    }
+}
+
+// Return the cell next to ``cell`` on the given side.
+fn neighbor(cell: [usize; 3], side: Side) -> [usize; 3] {
+    let [x, y, z] = cell;
+
+    match side {
+        Side::Front => [x, y, z + 1],
+        Side::Back => [x, y, z - 1],
+        Side::RightSide => [x + 1, y, z],
+        Side::LeftSide => [x - 1, y, z],
+        Side::Top => [x, y + 1, z],
+        Side::Bottom => [x, y - 1, z],
+    }
+}
+
+// Returns ``true`` for the four sides that face
+// horizontally.
+fn is_vertical(side: Side) -> bool {
+    side != Side::Top && side != Side::Bottom
+}
@@ -0,0 +1,306 @@
+//! Implements the packed vertex format.
+//!
+//! A ``PackedVertex`` holds three 32-bit words. From the least
+//! significant bit up, they are laid out as follows:
+//!
+//! ```text
+//! word 0:  x (9) | y (9) | z (9) | side (3) | ao (2)
+//! word 1:  u (9) | v (9) | sky light (6) | block light (6) | unused (2)
+//! word 2:  red (8) | green (8) | blue (8) | texture ID (8)
+//! ```
+//!
+//! Positions and texture coordinates are stored in ``STEPS``
+//! steps per block, since the shapes of blocks that are not
+//! cubes end in between. Positions are stored with an offset
+//! of ``POS_OFFSET``, since the skirts of coarse meshes reach
+//! below the sector. The texture coordinate is measured in
+//! tiles, so it never exceeds the edge length of a sector.
+//! The side is the index into ``Side::ALL``, and both light
+//! levels are kept in ``LIGHT_STEPS`` steps per level, as they
+//! are computed. The third word holds the tint color, and the
+//! texture ID, which leaves room for 256 tiles.
+//!
+//! ``vs_packed.glsl`` must be kept in sync with this layout.
+
+use super::{face_normal, light_level, tile_source, tint_color, TextureLayout, LIGHT_STEPS};
+use crate::{
+    block::BlockTextureID,
+    entity::sector::light::MAX_LIGHT,
+    side::Side,
+    vertexattrib::{
+        AoAttrib, LayerAttrib, LightAttrib, NormalAttrib, PackedAttrib, PackedVertex, PosAttrib,
+        TileAttrib, TintAttrib, UvAttrib, VoxelVertex,
+    },
+};
+
+// The value that is added to every position component,
+// so that they are never negative.
+const POS_OFFSET: f32 = 8.;
+
+// The number of steps per block in which positions and
+// texture coordinates are stored. The shapes of blocks
+// are made of sixteenths of a block.
+const STEPS: f32 = 16.;
+
+// The bit offset and width of every field in the first word...
+const X: (u32, u32) = (0, 9);
+const Y: (u32, u32) = (9, 9);
+const Z: (u32, u32) = (18, 9);
+const SIDE: (u32, u32) = (27, 3);
+const AO: (u32, u32) = (30, 2);
+
+// ... in the second...
+const U: (u32, u32) = (0, 9);
+const V: (u32, u32) = (9, 9);
+const SKY_LIGHT: (u32, u32) = (18, 6);
+const BLOCK_LIGHT: (u32, u32) = (24, 6);
+
+// ... and in the third.
+const TINT: [(u32, u32); 3] = [(0, 8), (8, 8), (16, 8)];
+const TEXTURE: (u32, u32) = (24, 8);
+
+// The number of tiles that packed vertices can refer to.
+pub(super) const MAX_TILES: u32 = 1 << TEXTURE.1;
+
+/// Pack the attributes of a sector mesh vertex.
+///
+/// ``pos`` is the position within the padded sector and
+/// ``uv`` the texture coordinate in tiles, both of which
+/// must be multiples of a sixteenth. ``ao`` and ``light`` are
+/// given like in the quads that ``MeshBuilder`` draws, and
+/// ``tint`` is an 8-bit color.
+///
+/// # Panics
+///
+/// Panics in debug builds if a value does not fit into
+/// its field. Release builds cut such a value down to its
+/// field, so that it never spills into the others.
+pub fn pack_vertex(
+    pos: [f32; 3],
+    uv: [f32; 2],
+    texture: BlockTextureID,
+    side: Side,
+    ao: u8,
+    light: [u8; 2],
+    tint: [u8; 3],
+) -> PackedVertex {
+    debug_assert!(light.iter().all(|l| *l <= MAX_LIGHT * LIGHT_STEPS));
+
+    let side_idx = Side::ALL.iter().position(|s| *s == side).unwrap();
+
+    let first = field(X, steps(pos[0] + POS_OFFSET))
+        | field(Y, steps(pos[1] + POS_OFFSET))
+        | field(Z, steps(pos[2] + POS_OFFSET))
+        | field(SIDE, side_idx as u32)
+        | field(AO, ao as u32);
+
+    let second = field(U, steps(uv[0]))
+        | field(V, steps(uv[1]))
+        | field(SKY_LIGHT, light[0] as u32)
+        | field(BLOCK_LIGHT, light[1] as u32);
+
+    let third = TINT
+        .iter()
+        .zip(&tint)
+        .fold(field(TEXTURE, texture), |word, (f, c)| {
+            word | field(*f, *c as u32)
+        });
+
+    PackedVertex {
+        data: PackedAttrib::new([first, second, third]),
+    }
+}
+
+/// Unpack a vertex that ``pack_vertex`` packed.
+///
+/// The result is exactly the ``VoxelVertex`` that the
+/// meshers emit for the same attributes in the full vertex
+/// format, given the same ``layout``.
+pub fn unpack_vertex(vertex: PackedVertex, layout: TextureLayout) -> VoxelVertex {
+    let [first, second, third] = *vertex.data;
+
+    let pos = [
+        extract(X, first) as f32 / STEPS - POS_OFFSET,
+        extract(Y, first) as f32 / STEPS - POS_OFFSET,
+        extract(Z, first) as f32 / STEPS - POS_OFFSET,
+    ];
+    let uv = [
+        extract(U, second) as f32 / STEPS,
+        extract(V, second) as f32 / STEPS,
+    ];
+    let side = Side::ALL[extract(SIDE, first) as usize];
+    let light = [
+        extract(SKY_LIGHT, second) as u8,
+        extract(BLOCK_LIGHT, second) as u8,
+    ];
+
+    let (tile, layer) = tile_source(layout, extract(TEXTURE, third));
+    let tint = [
+        extract(TINT[0], third) as u8,
+        extract(TINT[1], third) as u8,
+        extract(TINT[2], third) as u8,
+    ];
+
+    VoxelVertex {
+        pos: PosAttrib::new(pos),
+        uv: UvAttrib::new(uv),
+        tile: TileAttrib::new(tile),
+        layer: LayerAttrib::new(layer),
+        ao: AoAttrib::new(extract(AO, first) as f32),
+        light: LightAttrib::new(light_level(light)),
+        normal: NormalAttrib::new(face_normal(side)),
+        tint: TintAttrib::new(tint_color(tint)),
+    }
+}
+
+// Convert a position or texture coordinate into steps.
+fn steps(value: f32) -> u32 {
+    (value * STEPS).round() as u32
+}
+
+// Shift a value into its field, keeping only the bits
+// that fit.
+fn field((offset, width): (u32, u32), value: u32) -> u32 {
+    debug_assert!(
+        value < 1 << width,
+        "{} does not fit into {} bits",
+        value,
+        width
+    );
+
+    (value & mask(width)) << offset
+}
+
+// Read the value of a field.
+fn extract((offset, width): (u32, u32), word: u32) -> u32 {
+    (word >> offset) & mask(width)
+}
+
+// Return a mask of the lowest ``width`` bits.
+fn mask(width: u32) -> u32 {
+    (1 << width) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{MeshBuilder, Quad, QuadTint, FACES},
+        *,
+    };
+    use crate::{
+        block::Orientation,
+        entity::sector::{SectorIndex, VertexFormat, Vertices},
+    };
+
+    // The largest texture ID that fits into its field.
+    const MAX_TEXTURE: BlockTextureID = MAX_TILES - 1;
+
+    // Draw ``quad`` on every face in both formats, and check
+    // that every packed vertex unpacks to its full vertex.
+    fn assert_round_trip(layout: TextureLayout, quad: &Quad) {
+        let index = SectorIndex(-3, 1, 7);
+        let mut full = MeshBuilder::new(layout, VertexFormat::Full, index);
+        let mut packed = MeshBuilder::new(layout, VertexFormat::Packed, index);
+
+        for face in &FACES {
+            full.push_face(face, quad);
+            packed.push_face(face, quad);
+        }
+
+        match (&full.vertices, &packed.vertices) {
+            (Vertices::Full(full), Vertices::Packed(packed)) => {
+                assert_eq!(full.len(), packed.len());
+
+                for (expected, vertex) in full.iter().zip(packed) {
+                    assert_eq!(unpack_vertex(*vertex, layout), *expected);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn quad(factors: (f32, f32, f32), extent: [f32; 2], light: u8, tint: QuadTint) -> Quad {
+        Quad {
+            factors,
+            extent,
+            texture: MAX_TEXTURE,
+            orientation: Orientation {
+                quarter_turns: 3,
+                mirrored: true,
+            },
+            ao: [0, 1, 2, 3],
+            light: [[light, 0], [0, light], [light, light], [light / 2, 1]],
+            tint,
+        }
+    }
+
+    #[test]
+    fn packed_vertices_unpack_to_full_vertices() {
+        let layouts = [
+            TextureLayout::Atlas {
+                width: 4096,
+                height: 4096,
+                tile_size: 14,
+                padding: 1,
+            },
+            TextureLayout::Array,
+        ];
+        let tints = [
+            QuadTint::Fixed([0, 0, 0]),
+            QuadTint::Fixed([255, 255, 255]),
+            QuadTint::Fixed([1, 128, 254]),
+            QuadTint::Biome,
+        ];
+
+        for layout in &layouts {
+            for tint in &tints {
+                for light in &[0, MAX_LIGHT * LIGHT_STEPS] {
+                    // The lowest skirts reach down to -8.
+                    assert_round_trip(*layout, &quad((-8., -8., -8.), [1., 1.], *light, *tint));
+
+                    // The last padded voxel ends at 18.
+                    assert_round_trip(*layout, &quad((17., 17., 17.), [1., 1.], *light, *tint));
+
+                    // A quad as large as the sector repeats its
+                    // tile up to the highest texture coordinate.
+                    assert_round_trip(*layout, &quad((1., 1., 1.), [16., 16.], *light, *tint));
+
+                    // The faces of a torch end in between voxels.
+                    let torch = (8. + 7. / 16., 1., 8. + 7. / 16.);
+                    assert_round_trip(*layout, &quad(torch, [2. / 16., 10. / 16.], *light, *tint));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fields_do_not_overlap() {
+        let third = [TINT[0], TINT[1], TINT[2], TEXTURE];
+
+        for words in &[
+            &[X, Y, Z, SIDE, AO][..],
+            &[U, V, SKY_LIGHT, BLOCK_LIGHT],
+            &third,
+        ] {
+            let mut used = 0u64;
+
+            for (offset, width) in words.iter() {
+                let bits = u64::from(mask(*width)) << offset;
+
+                assert_eq!(used & bits, 0);
+                used |= bits;
+            }
+
+            assert!(used <= u64::from(u32::MAX));
+        }
+    }
+
+    #[test]
+    fn packed_meshes_refuse_too_many_tiles() {
+        let too_many = MAX_TILES + 1;
+
+        assert!(VertexFormat::Packed.check_tile_count(MAX_TILES).is_ok());
+        assert!(VertexFormat::Packed.check_tile_count(too_many).is_err());
+        assert!(VertexFormat::Full.check_tile_count(too_many).is_ok());
+    }
+}
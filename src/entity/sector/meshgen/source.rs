@@ -0,0 +1,448 @@
+//! Provides the meshers with the voxels and light around
+//! a sector.
+//!
+//! Faces on the boundary of a sector depend on the voxels just
+//! outside of it, and ambient occlusion and smooth lighting
+//! also look at the voxels diagonally across its edges and
+//! corners. Sectors do not store any of these voxels, so
+//! ``MeshNeighborhood`` reads them from the neighboring sectors
+//! directly. This is how sectors are meshed, both when they
+//! are generated and after they are edited.
+//!
+//! Padding is optional: ``PaddedSector`` holds its own copy
+//! of the voxels around a sector, so that it can be meshed
+//! again later without its neighbors. Both produce exactly
+//! the same mesh for the same voxels.
+
+use crate::{
+    block::Block,
+    entity::sector::{
+        data::{
+            SectorCoords, SectorData, SECTOR_DIM, SECTOR_DIM_EXCL, SECTOR_MAX, SECTOR_MIN,
+            SECTOR_PAD,
+        },
+        light::{LightChannel, LightData, MAX_LIGHT},
+        SectorIndex,
+    },
+};
+
+// The number of voxels in a sector, padding included.
+const PADDED_LEN: usize = SECTOR_DIM * SECTOR_DIM * SECTOR_DIM;
+
+/// Gives the meshers access to the blocks and light
+/// levels of a sector and the voxels around it.
+///
+/// Positions are given in padded sector coordinates,
+/// so the sector itself spans ``SECTOR_MIN + 1`` to
+/// ``SECTOR_MAX - 1`` along every axis, and the outer
+/// layer belongs to the neighbors.
+pub trait MeshSource {
+    /// Return the index of the sector, which places the
+    /// voxels in the world.
+    fn index(&self) -> SectorIndex;
+
+    /// Return the block at ``coords``.
+    fn block(&self, coords: SectorCoords) -> Block;
+
+    /// Return the light level of one channel at ``coords``.
+    fn light(&self, coords: SectorCoords, channel: LightChannel) -> u8;
+
+    /// Return the block that fills the sector and all of
+    /// the voxels around it, if it is known to be uniform
+    /// and to have no visible faces.
+    ///
+    /// Only air and cubes can fill a sector without any
+    /// visible faces.
+    fn uniform_block(&self) -> Option<Block>;
+}
+
+/// A ``MeshSource`` that holds a copy of the voxels of
+/// a sector and of the padding around it.
+#[derive(Clone)]
+pub struct PaddedSector {
+    index: SectorIndex,
+    blocks: Box<[Block; PADDED_LEN]>,
+
+    // The sky and block light of every voxel.
+    light: Box<[[u8; 2]; PADDED_LEN]>,
+    uniform: Option<Block>,
+}
+
+impl PaddedSector {
+    /// Create a new ``PaddedSector`` for the sector at
+    /// ``index``.
+    ///
+    /// ``block`` and ``light`` are called once for every
+    /// voxel in padded sector coordinates, and return its
+    /// block and its light level in a channel.
+    pub fn new<B, L>(index: SectorIndex, block: B, light: L) -> PaddedSector
+    where
+        B: Fn(SectorCoords) -> Block,
+        L: Fn(SectorCoords, LightChannel) -> u8,
+    {
+        let mut padded = PaddedSector {
+            index,
+            blocks: Box::new([Block::Air; PADDED_LEN]),
+            light: Box::new([[0; 2]; PADDED_LEN]),
+            uniform: None,
+        };
+
+        for i in 0..PADDED_LEN {
+            let coords = SectorCoords(
+                i % SECTOR_DIM,
+                i / SECTOR_DIM % SECTOR_DIM,
+                i / (SECTOR_DIM * SECTOR_DIM),
+            );
+
+            padded.blocks[i] = block(coords);
+            padded.light[i] = [
+                light(coords, LightChannel::Sky),
+                light(coords, LightChannel::Block),
+            ];
+        }
+
+        let first = padded.blocks[0];
+        let hidden = first == Block::Air || first.is_cube();
+        if hidden && padded.blocks.iter().all(|blk| *blk == first) {
+            padded.uniform = Some(first);
+        }
+
+        padded
+    }
+
+    /// Copy the voxels that ``source`` provides for a sector,
+    /// padding included, into a new ``PaddedSector``.
+    pub fn from_source(source: &impl MeshSource) -> PaddedSector {
+        Self::new(
+            source.index(),
+            |coords| source.block(coords),
+            |coords, channel| source.light(coords, channel),
+        )
+    }
+
+    // Determine the array index of a padded coordinate.
+    fn array_index(coords: SectorCoords) -> usize {
+        let SectorCoords(x, y, z) = coords;
+
+        x + y * SECTOR_DIM + z * SECTOR_DIM * SECTOR_DIM
+    }
+}
+
+impl MeshSource for PaddedSector {
+    fn index(&self) -> SectorIndex {
+        self.index
+    }
+
+    fn block(&self, coords: SectorCoords) -> Block {
+        self.blocks[Self::array_index(coords)]
+    }
+
+    fn light(&self, coords: SectorCoords, channel: LightChannel) -> u8 {
+        let [sky, block] = self.light[Self::array_index(coords)];
+
+        match channel {
+            LightChannel::Sky => sky,
+            LightChannel::Block => block,
+        }
+    }
+
+    fn uniform_block(&self) -> Option<Block> {
+        self.uniform
+    }
+}
+
+// The voxels and light of a single sector.
+type SectorRef<'a> = (&'a SectorData, &'a LightData);
+
+/// A ``MeshSource`` that reads the voxels around the
+/// sector from up to 26 neighboring sectors.
+///
+/// A missing neighbor, such as one beyond the edge
+/// of the world, is treated as air in full daylight.
+#[derive(Clone, Copy)]
+pub struct MeshNeighborhood<'a> {
+    index: SectorIndex,
+
+    // The sectors in a 3x3x3 cube around the center,
+    // indexed like the voxels of a sector.
+    sectors: [Option<SectorRef<'a>>; 27],
+}
+
+impl<'a> MeshNeighborhood<'a> {
+    /// Create a new ``MeshNeighborhood`` around the sector
+    /// at ``index``.
+    ///
+    /// ``neighbor`` is called with the offset of every
+    /// surrounding sector, each component between ``-1``
+    /// and ``1``, and returns its voxels and light if it
+    /// is available.
+    pub fn new<F>(
+        index: SectorIndex,
+        voxels: &'a SectorData,
+        light: &'a LightData,
+        mut neighbor: F,
+    ) -> MeshNeighborhood<'a>
+    where
+        F: FnMut([i32; 3]) -> Option<SectorRef<'a>>,
+    {
+        let mut sectors = [None; 27];
+
+        for (i, sector) in sectors.iter_mut().enumerate() {
+            let offset = [i as i32 % 3 - 1, i as i32 / 3 % 3 - 1, i as i32 / 9 - 1];
+
+            *sector = if offset == [0, 0, 0] {
+                Some((voxels, light))
+            } else {
+                neighbor(offset)
+            };
+        }
+
+        MeshNeighborhood { index, sectors }
+    }
+
+    // Return the sector that holds the voxel at ``coords``,
+    // along with the voxel's coordinates in that sector.
+    fn locate(&self, coords: SectorCoords) -> (Option<SectorRef<'a>>, SectorCoords) {
+        let SectorCoords(x, y, z) = coords;
+        let mut index = 0;
+        let mut local = [0; 3];
+
+        for (axis, c) in [x, y, z].iter().enumerate().rev() {
+            let (offset, l) = match *c {
+                SECTOR_MIN => (0, SECTOR_DIM_EXCL - 1),
+                SECTOR_MAX => (2, 0),
+                _ => (1, *c - SECTOR_PAD),
+            };
+
+            index = index * 3 + offset;
+            local[axis] = l;
+        }
+
+        (
+            self.sectors[index],
+            SectorCoords(local[0], local[1], local[2]),
+        )
+    }
+}
+
+impl<'a> MeshSource for MeshNeighborhood<'a> {
+    fn index(&self) -> SectorIndex {
+        self.index
+    }
+
+    fn block(&self, coords: SectorCoords) -> Block {
+        match self.locate(coords) {
+            (Some((voxels, _)), local) => *voxels.block(local),
+            (None, _) => Block::Air,
+        }
+    }
+
+    fn light(&self, coords: SectorCoords, channel: LightChannel) -> u8 {
+        match (self.locate(coords), channel) {
+            ((Some((_, light)), local), _) => light.get(local, channel),
+            ((None, _), LightChannel::Sky) => MAX_LIGHT,
+            ((None, _), LightChannel::Block) => 0,
+        }
+    }
+
+    fn uniform_block(&self) -> Option<Block> {
+        let blk = self.sectors[13]?.0.uniform_block()?;
+
+        // A uniform sector of air has no faces no matter
+        // what surrounds it. Otherwise, it must be made of
+        // cubes, and every voxel around it must be the same.
+        if blk == Block::Air {
+            return Some(blk);
+        } else if !blk.is_cube() {
+            return None;
+        }
+
+        for z in 0..SECTOR_DIM {
+            for y in 0..SECTOR_DIM {
+                for x in 0..SECTOR_DIM {
+                    let outer = [x, y, z]
+                        .iter()
+                        .any(|c| *c == SECTOR_MIN || *c == SECTOR_MAX);
+
+                    if outer && self.block(SectorCoords(x, y, z)) != blk {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        Some(blk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entity::sector::{
+            meshgen::{
+                gen_terrain, gen_terrain_greedy, gen_terrain_lod, PreGeometry, TerrainTextures,
+                TextureLayout, Vertices, LOD_LEVELS,
+            },
+            VertexFormat, WorldCoords,
+        },
+        util::hash::hash3,
+    };
+    use std::collections::HashMap;
+
+    // A random world, made of small boxes of the same block
+    // with single voxels of air scattered through them.
+    fn world_block(pos: WorldCoords, seed: u32) -> Block {
+        let WorldCoords(x, y, z) = pos;
+
+        if hash3(x, y, z, seed).is_multiple_of(5) {
+            return Block::Air;
+        }
+
+        let cell = hash3(x.div_euclid(3), y.div_euclid(2), z.div_euclid(3), seed);
+        match cell % 3 {
+            0 => Block::Air,
+            _ => Block::ALL[cell as usize / 3 % Block::ALL.len()],
+        }
+    }
+
+    fn world_light(pos: WorldCoords, channel: LightChannel, seed: u32) -> u8 {
+        let WorldCoords(x, y, z) = pos;
+
+        let seed = match channel {
+            LightChannel::Sky => seed,
+            LightChannel::Block => !seed,
+        };
+
+        (hash3(x, y, z, seed) % (MAX_LIGHT as u32 + 1)) as u8
+    }
+
+    // Generate the sectors around ``center`` from the
+    // random world.
+    fn world_sectors(
+        center: SectorIndex,
+        seed: u32,
+    ) -> HashMap<SectorIndex, (SectorData, LightData)> {
+        let SectorIndex(x, y, z) = center;
+        let mut sectors = HashMap::new();
+
+        for nx in x - 1..x + 2 {
+            for ny in y - 1..y + 2 {
+                for nz in z - 1..z + 2 {
+                    let index = SectorIndex(nx, ny, nz);
+                    let mut voxels = SectorData::new();
+                    let mut light = LightData::new();
+
+                    for i in 0..SECTOR_DIM_EXCL.pow(3) {
+                        let coords = SectorCoords(
+                            i % SECTOR_DIM_EXCL,
+                            i / SECTOR_DIM_EXCL % SECTOR_DIM_EXCL,
+                            i / (SECTOR_DIM_EXCL * SECTOR_DIM_EXCL),
+                        );
+                        let pos = WorldCoords::from_sector(index, coords);
+
+                        *voxels.block_mut(coords) = world_block(pos, seed);
+                        for channel in &LightChannel::ALL {
+                            light.set(coords, *channel, world_light(pos, *channel, seed));
+                        }
+                    }
+
+                    sectors.insert(index, (voxels, light));
+                }
+            }
+        }
+
+        sectors
+    }
+
+    fn assert_same(a: Option<PreGeometry>, b: Option<PreGeometry>) {
+        let (a, b) = match (a, b) {
+            (Some(a), Some(b)) => (a, b),
+            (None, None) => return,
+            _ => panic!("only one of the meshes is empty"),
+        };
+
+        assert_eq!(a.indices, b.indices);
+        match (&a.vertices, &b.vertices) {
+            (Vertices::Full(a), Vertices::Full(b)) => assert_eq!(a, b),
+            (Vertices::Packed(a), Vertices::Packed(b)) => assert_eq!(a, b),
+            _ => panic!("the meshes have different vertex formats"),
+        }
+    }
+
+    #[test]
+    fn neighborhood_and_padded_sector_mesh_alike() {
+        let textures = TerrainTextures::for_tests(TextureLayout::Array);
+
+        for (seed, center) in [
+            (1, SectorIndex(0, 0, 0)),
+            (2, SectorIndex(-3, 1, 5)),
+            (3, SectorIndex(7, -2, -1)),
+        ]
+        .iter()
+        {
+            let sectors = world_sectors(*center, *seed);
+            let SectorIndex(x, y, z) = *center;
+            let (voxels, light) = &sectors[center];
+
+            let neighborhood = MeshNeighborhood::new(*center, voxels, light, |[dx, dy, dz]| {
+                sectors
+                    .get(&SectorIndex(x + dx, y + dy, z + dz))
+                    .map(|(voxels, light)| (voxels, light))
+            });
+            let padded = PaddedSector::new(
+                *center,
+                |coords| world_block(WorldCoords::from_padded(*center, coords), *seed),
+                |coords, channel| {
+                    world_light(WorldCoords::from_padded(*center, coords), channel, *seed)
+                },
+            );
+
+            // The random world is never uniform, so there are
+            // always some faces to compare.
+            assert!(gen_terrain(&textures, &padded, VertexFormat::Full).is_some());
+
+            for format in &[VertexFormat::Full, VertexFormat::Packed] {
+                assert_same(
+                    gen_terrain(&textures, &neighborhood, *format),
+                    gen_terrain(&textures, &padded, *format),
+                );
+                assert_same(
+                    gen_terrain_greedy(&textures, &neighborhood, *format),
+                    gen_terrain_greedy(&textures, &padded, *format),
+                );
+
+                for level in 1..LOD_LEVELS {
+                    assert_same(
+                        gen_terrain_lod(&textures, &neighborhood, level, *format),
+                        gen_terrain_lod(&textures, &padded, level, *format),
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn missing_neighbors_are_open_air() {
+        let mut voxels = SectorData::new();
+        let light = LightData::new();
+        let index = SectorIndex(0, 0, 0);
+        *voxels.block_mut(SectorCoords(0, 0, 0)) = Block::Stone;
+
+        let neighborhood = MeshNeighborhood::new(index, &voxels, &light, |_| None);
+
+        assert_eq!(
+            neighborhood.block(SectorCoords(SECTOR_MIN, 1, 1)),
+            Block::Air
+        );
+        assert_eq!(neighborhood.block(SectorCoords(1, 1, 1)), Block::Stone);
+        assert_eq!(
+            neighborhood.light(SectorCoords(1, SECTOR_MAX, 1), LightChannel::Sky),
+            MAX_LIGHT
+        );
+        assert_eq!(
+            neighborhood.light(SectorCoords(1, SECTOR_MAX, 1), LightChannel::Block),
+            0
+        );
+    }
+}
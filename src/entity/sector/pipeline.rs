@@ -0,0 +1,587 @@
+//! Implements the scheduling of the world generation pipeline.
+//!
+//! Sectors are generated in several *stages*. Later stages,
+//! such as decoration or lighting, may depend on the state
+//! of the neighboring sectors, so a sector is only allowed
+//! to advance once all of its neighbors have completed the
+//! stage before. The sector is meshed after the final stage.
+//!
+//! Lighting additionally depends on every sector above the
+//! sector's neighbors, since the sky may be blocked at any
+//! height.
+//!
+//! The ``Scheduler`` in this module only keeps track of
+//! which step every sector is ready to run. It does not
+//! perform any work itself. Sectors near the *focus*, such
+//! as the sector the player is in, are handed out first,
+//! and sectors that are no longer needed can be cancelled.
+
+use super::{SectorIndex, SectorRegion};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+};
+
+/// The stages of world generation, in the order
+/// they are applied to a sector.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GenStage {
+    /// The basic shape of the terrain is generated.
+    Terrain,
+
+    /// Caves are carved out of the terrain.
+    Carve,
+
+    /// Features are placed on the terrain.
+    Decorate,
+
+    /// Light levels are computed.
+    Light,
+}
+
+impl GenStage {
+    /// The stage that every sector begins with.
+    pub const FIRST: GenStage = GenStage::Terrain;
+
+    /// The stage after which a sector is meshed.
+    pub const FINAL: GenStage = GenStage::Light;
+
+    /// Return the stage that follows ``self``, or
+    /// ``None`` if ``self`` is the final stage.
+    pub fn next(self) -> Option<GenStage> {
+        use GenStage::*;
+
+        match self {
+            Terrain => Some(Carve),
+            Carve => Some(Decorate),
+            Decorate => Some(Light),
+            Light => None,
+        }
+    }
+
+    /// Returns ``true`` if the stage depends on every sector
+    /// above its neighbors, in addition to the neighbors
+    /// themselves.
+    pub fn needs_column(self) -> bool {
+        self == GenStage::Light
+    }
+}
+
+/// A unit of work for a single sector.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// Run the given generation stage.
+    Stage(GenStage),
+
+    /// Build the sector's mesh.
+    Mesh,
+}
+
+// Tracks the generation progress of one sector.
+#[derive(Clone, Copy, Debug, Default)]
+struct Progress {
+    completed: Option<GenStage>,
+    meshed: bool,
+    cancelled: bool,
+    busy: bool,
+    queued: bool,
+}
+
+impl Progress {
+    // Return the step this sector will run next, or
+    // ``None`` if it is already meshed or was cancelled.
+    fn next_step(&self) -> Option<Step> {
+        if self.meshed || self.cancelled {
+            return None;
+        }
+
+        match self.completed {
+            None => Some(Step::Stage(GenStage::FIRST)),
+            Some(GenStage::FINAL) => Some(Step::Mesh),
+            Some(stage) => stage.next().map(Step::Stage),
+        }
+    }
+}
+
+// A sector in the ready queue.
+//
+// Sectors closer to the focus come first, and sectors at
+// the same distance in the order in which they became ready.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Pending {
+    distance: i32,
+    order: u64,
+    index: SectorIndex,
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Pending) -> Ordering {
+        (self.distance, self.order).cmp(&(other.distance, other.order))
+    }
+}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Pending) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Decides the order in which sectors advance
+/// through the generation pipeline.
+///
+/// A sector may run a stage only once every neighbor
+/// (including the diagonal ones) has completed the
+/// previous stage. Likewise, a sector is meshed only
+/// once every neighbor has completed the final stage.
+/// Stages that need the whole column wait for every
+/// sector above the neighbors as well.
+/// Neighbors outside of the generated region, and
+/// cancelled neighbors, never hold a sector back.
+///
+/// Of the sectors that are ready, the one closest to
+/// the focus is handed out first.
+pub struct Scheduler {
+    region: SectorRegion,
+    progress: HashMap<SectorIndex, Progress>,
+    ready: BinaryHeap<Reverse<Pending>>,
+    focus: Option<SectorIndex>,
+    enqueued: u64,
+    remaining: usize,
+}
+
+impl Scheduler {
+    /// Create a new ``Scheduler`` for every sector
+    /// in ``region``.
+    ///
+    /// Initially, every sector is ready for the first
+    /// stage, and there is no focus. Until one is set,
+    /// sectors are handed out in the order that ``region``
+    /// iterates over them.
+    pub fn new(region: SectorRegion) -> Scheduler {
+        let mut scheduler = Scheduler {
+            region,
+            progress: HashMap::new(),
+            ready: BinaryHeap::new(),
+            focus: None,
+            enqueued: 0,
+            remaining: region.len(),
+        };
+
+        for index in region.iter() {
+            scheduler.progress.insert(index, Progress::default());
+            scheduler.enqueue(index);
+        }
+
+        scheduler
+    }
+
+    /// Return the next sector and the step it is
+    /// ready to run, if there is one.
+    ///
+    /// The sector is considered busy until
+    /// ``complete`` is called for it.
+    pub fn next_ready(&mut self) -> Option<(SectorIndex, Step)> {
+        loop {
+            let Reverse(Pending { index, .. }) = self.ready.pop()?;
+            let progress = self.progress.get_mut(&index).unwrap();
+
+            // Cancelled sectors are left in the queue.
+            if !progress.queued {
+                continue;
+            }
+
+            progress.queued = false;
+            progress.busy = true;
+
+            return Some((index, progress.next_step().unwrap()));
+        }
+    }
+
+    /// Record that the sector at ``index`` has
+    /// finished the step it was running.
+    ///
+    /// The sector itself, its neighbors, and the sectors
+    /// below them are then checked for steps that have
+    /// become ready.
+    ///
+    /// Returns ``false`` if the sector was cancelled while
+    /// the step ran, in which case its result is of no use.
+    pub fn complete(&mut self, index: SectorIndex) -> bool {
+        let progress = self.progress.get_mut(&index).unwrap();
+
+        progress.busy = false;
+
+        match progress.next_step() {
+            Some(Step::Stage(stage)) => progress.completed = Some(stage),
+            Some(Step::Mesh) => {
+                progress.meshed = true;
+                self.remaining -= 1;
+            }
+            None => return false,
+        }
+
+        self.wake_around(index);
+        true
+    }
+
+    /// Stop generating the sector at ``index``.
+    ///
+    /// None of its remaining steps are handed out, and its
+    /// neighbors stop waiting for it. A step that it is
+    /// running may still be completed, but ``complete``
+    /// reports that it was cancelled.
+    /// Sectors that are already meshed are left alone.
+    pub fn cancel(&mut self, index: SectorIndex) {
+        match self.progress.get_mut(&index) {
+            Some(progress) if progress.next_step().is_some() => {
+                progress.cancelled = true;
+                progress.queued = false;
+            }
+            _ => return,
+        }
+
+        self.remaining -= 1;
+        self.wake_around(index);
+    }
+
+    /// Hand out the sectors that are ready in the order
+    /// of their distance to the sector at ``focus``,
+    /// from nearest to farthest.
+    pub fn set_focus(&mut self, focus: SectorIndex) {
+        if self.focus == Some(focus) {
+            return;
+        }
+
+        self.focus = Some(focus);
+        self.ready = self
+            .ready
+            .drain()
+            .map(|Reverse(pending)| {
+                Reverse(Pending {
+                    distance: distance(Some(focus), pending.index),
+                    ..pending
+                })
+            })
+            .collect();
+    }
+
+    /// Returns ``true`` if the sector at ``index`` will run
+    /// no more steps, because it has been meshed or cancelled,
+    /// or because it lies outside of the region.
+    pub fn is_done(&self, index: SectorIndex) -> bool {
+        match self.progress.get(&index) {
+            Some(progress) => progress.next_step().is_none(),
+            None => true,
+        }
+    }
+
+    /// Returns ``true`` once every sector has been meshed
+    /// or cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.remaining == 0
+    }
+
+    // Check the sectors whose steps may depend on the sector
+    // at ``index``: the sector itself, its neighbors, and the
+    // sectors below them.
+    fn wake_around(&mut self, index: SectorIndex) {
+        let SectorIndex(x, y, z) = index;
+
+        for nx in x - 1..x + 2 {
+            for ny in self.region.min.1..y + 2 {
+                for nz in z - 1..z + 2 {
+                    self.enqueue_if_ready(SectorIndex(nx, ny, nz));
+                }
+            }
+        }
+    }
+
+    // Add the sector to the ready queue.
+    fn enqueue(&mut self, index: SectorIndex) {
+        self.progress.get_mut(&index).unwrap().queued = true;
+        self.ready.push(Reverse(Pending {
+            distance: distance(self.focus, index),
+            order: self.enqueued,
+            index,
+        }));
+        self.enqueued += 1;
+    }
+
+    // Add the sector to the ready queue if it is idle and
+    // its neighbors have reached the prerequisite stage.
+    fn enqueue_if_ready(&mut self, index: SectorIndex) {
+        let (step, prerequisite) = match self.progress.get(&index) {
+            Some(p) if !p.busy && !p.queued => match p.next_step() {
+                Some(step) => (step, p.completed),
+                None => return,
+            },
+            _ => return,
+        };
+
+        // Every step but the first requires the neighbors to
+        // have completed the same stage as this sector.
+        if step != Step::Stage(GenStage::FIRST) && !self.neighbors_reached(index, prerequisite) {
+            return;
+        }
+
+        if let Step::Stage(stage) = step {
+            if stage.needs_column() && !self.column_reached(index, prerequisite) {
+                return;
+            }
+        }
+
+        self.enqueue(index);
+    }
+
+    fn neighbors_reached(&self, index: SectorIndex, stage: Option<GenStage>) -> bool {
+        let SectorIndex(x, y, z) = index;
+
+        for nx in x - 1..x + 2 {
+            for ny in y - 1..y + 2 {
+                for nz in z - 1..z + 2 {
+                    let neighbor = SectorIndex(nx, ny, nz);
+
+                    if neighbor == index || !self.region.contains(neighbor) {
+                        continue;
+                    }
+
+                    let progress = &self.progress[&neighbor];
+                    if !progress.cancelled && progress.completed < stage {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    // Returns ``true`` if every sector above the neighbors
+    // of the sector at ``index`` has completed ``stage``.
+    fn column_reached(&self, index: SectorIndex, stage: Option<GenStage>) -> bool {
+        let SectorIndex(x, y, z) = index;
+
+        for nx in x - 1..x + 2 {
+            for ny in y + 2..self.region.max.1 {
+                for nz in z - 1..z + 2 {
+                    match self.progress.get(&SectorIndex(nx, ny, nz)) {
+                        Some(p) if !p.cancelled && p.completed < stage => return false,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        true
+    }
+}
+
+// Return the squared distance between two sectors, or zero
+// if there is no focus.
+fn distance(focus: Option<SectorIndex>, index: SectorIndex) -> i32 {
+    match focus {
+        Some(SectorIndex(fx, fy, fz)) => {
+            let SectorIndex(x, y, z) = index;
+            let (dx, dy, dz) = (x - fx, y - fy, z - fz);
+
+            dx * dx + dy * dy + dz * dz
+        }
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(x: i32, y: i32, z: i32) -> SectorRegion {
+        SectorRegion::new(SectorIndex(0, 0, 0), SectorIndex(x, y, z))
+    }
+
+    // Hand out every ready step, complete them all at once,
+    // and repeat until nothing is ready. Returns the batches
+    // of steps in the order they were handed out.
+    fn run(scheduler: &mut Scheduler) -> Vec<Vec<(SectorIndex, Step)>> {
+        let mut batches = Vec::new();
+
+        loop {
+            let batch: Vec<_> = std::iter::from_fn(|| scheduler.next_ready()).collect();
+
+            if batch.is_empty() {
+                return batches;
+            }
+
+            for (index, _) in &batch {
+                assert!(scheduler.complete(*index));
+            }
+
+            batches.push(batch);
+        }
+    }
+
+    fn steps_of(batches: &[Vec<(SectorIndex, Step)>], index: SectorIndex) -> Vec<Step> {
+        batches
+            .iter()
+            .flatten()
+            .filter(|(i, _)| *i == index)
+            .map(|(_, step)| *step)
+            .collect()
+    }
+
+    #[test]
+    fn sectors_run_every_step_in_order() {
+        let region = region(3, 2, 3);
+        let mut scheduler = Scheduler::new(region);
+        let batches = run(&mut scheduler);
+
+        let expected = vec![
+            Step::Stage(GenStage::Terrain),
+            Step::Stage(GenStage::Carve),
+            Step::Stage(GenStage::Decorate),
+            Step::Stage(GenStage::Light),
+            Step::Mesh,
+        ];
+
+        for index in region.iter() {
+            assert_eq!(steps_of(&batches, index), expected, "{:?}", index);
+        }
+
+        assert!(scheduler.is_finished());
+    }
+
+    #[test]
+    fn steps_wait_for_their_neighbors() {
+        let region = region(4, 3, 4);
+        let mut scheduler = Scheduler::new(region);
+        let mut completed: HashMap<SectorIndex, Option<GenStage>> =
+            region.iter().map(|index| (index, None)).collect();
+
+        for batch in run(&mut scheduler) {
+            for &(index, step) in &batch {
+                let prerequisite = completed[&index];
+                let SectorIndex(x, y, z) = index;
+
+                if step != Step::Stage(GenStage::FIRST) {
+                    for neighbor in region.iter().filter(|n| {
+                        (n.0 - x).abs() <= 1 && (n.1 - y).abs() <= 1 && (n.2 - z).abs() <= 1
+                    }) {
+                        assert!(completed[&neighbor] >= prerequisite, "{:?}", step);
+                    }
+                }
+
+                if step == Step::Stage(GenStage::Light) {
+                    for above in region
+                        .iter()
+                        .filter(|n| (n.0 - x).abs() <= 1 && n.1 >= y + 2 && (n.2 - z).abs() <= 1)
+                    {
+                        assert!(completed[&above] >= prerequisite);
+                    }
+                }
+            }
+
+            for &(index, step) in &batch {
+                if let Step::Stage(stage) = step {
+                    completed.insert(index, Some(stage));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sectors_start_in_region_order_without_focus() {
+        let region = region(3, 3, 3);
+        let mut scheduler = Scheduler::new(region);
+        let first: Vec<_> = std::iter::from_fn(|| scheduler.next_ready())
+            .map(|(index, _)| index)
+            .collect();
+
+        assert_eq!(first, region.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn focus_hands_out_nearest_sectors_first() {
+        let mut scheduler = Scheduler::new(region(5, 1, 5));
+        scheduler.set_focus(SectorIndex(4, 0, 4));
+
+        let first = scheduler.next_ready().unwrap();
+        assert_eq!(
+            first,
+            (SectorIndex(4, 0, 4), Step::Stage(GenStage::Terrain))
+        );
+
+        // The player moved to the opposite corner.
+        scheduler.set_focus(SectorIndex(0, 0, 0));
+
+        let rest: Vec<_> = std::iter::from_fn(|| scheduler.next_ready())
+            .map(|(index, _)| distance(Some(SectorIndex(0, 0, 0)), index))
+            .collect();
+
+        assert_eq!(rest.len(), 24);
+        assert_eq!(rest[0], 0);
+        assert!(rest.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn focus_applies_to_later_steps() {
+        let mut scheduler = Scheduler::new(region(5, 1, 5));
+        scheduler.set_focus(SectorIndex(2, 0, 2));
+
+        // Let every sector finish its terrain, so all of
+        // them become ready for the next stage together.
+        for (index, _) in std::iter::from_fn(|| scheduler.next_ready()).collect::<Vec<_>>() {
+            scheduler.complete(index);
+        }
+
+        scheduler.set_focus(SectorIndex(0, 0, 4));
+
+        let (index, step) = scheduler.next_ready().unwrap();
+        assert_eq!(index, SectorIndex(0, 0, 4));
+        assert_eq!(step, Step::Stage(GenStage::Carve));
+    }
+
+    #[test]
+    fn cancelled_sectors_are_skipped() {
+        let region = region(3, 1, 3);
+        let cancelled = SectorIndex(1, 0, 1);
+        let mut scheduler = Scheduler::new(region);
+
+        scheduler.cancel(cancelled);
+        let batches = run(&mut scheduler);
+
+        assert!(steps_of(&batches, cancelled).is_empty());
+        for index in region.iter().filter(|index| *index != cancelled) {
+            assert_eq!(steps_of(&batches, index).last(), Some(&Step::Mesh));
+        }
+
+        assert!(scheduler.is_finished());
+    }
+
+    #[test]
+    fn cancelling_a_busy_sector_discards_its_step() {
+        let region = region(3, 1, 3);
+        let mut scheduler = Scheduler::new(region);
+
+        let (busy, _) = scheduler.next_ready().unwrap();
+        scheduler.cancel(busy);
+        assert!(!scheduler.complete(busy));
+
+        // Its neighbors are generated without it.
+        let batches = run(&mut scheduler);
+
+        assert!(steps_of(&batches, busy).is_empty());
+        assert!(scheduler.is_finished());
+    }
+
+    #[test]
+    fn cancelling_a_meshed_sector_does_nothing() {
+        let region = region(1, 1, 1);
+        let index = SectorIndex(0, 0, 0);
+        let mut scheduler = Scheduler::new(region);
+
+        run(&mut scheduler);
+        scheduler.cancel(index);
+
+        assert!(scheduler.is_finished());
+        assert!(scheduler.next_ready().is_none());
+    }
+}
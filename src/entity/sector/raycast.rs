@@ -0,0 +1,326 @@
+//! Finds the first block that a ray hits.
+//!
+//! The ray is walked from block to block with the voxel
+//! traversal of Amanatides and Woo, "A Fast Voxel Traversal
+//! Algorithm for Ray Tracing". For each axis, it tracks how
+//! far along the ray the next block boundary on that axis
+//! lies, and always steps across the closest one. Every
+//! block that the ray passes through is visited exactly
+//! once, in order, so no thin corner is skipped.
+
+use super::WorldCoords;
+use crate::{block::Block, maths::vector::Vec3f, side::Side};
+
+/// A block that was hit by a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// The position of the block that was hit.
+    pub pos: WorldCoords,
+
+    /// The face of the block that the ray entered
+    /// through.
+    pub side: Side,
+
+    /// The distance from the origin of the ray to the
+    /// point where it entered the block.
+    pub distance: f32,
+}
+
+/// Cast a ray from ``origin`` along ``direction`` and return
+/// the first block that is not air within ``max_distance``.
+///
+/// ``block`` returns the block at the given position, or
+/// ``None`` if it is not known, for example because its
+/// sector is not loaded. The ray stops without a hit at
+/// such a block. The block that contains ``origin`` is
+/// never hit, and neither is anything if ``direction`` is
+/// zero.
+pub fn raycast<F>(
+    origin: Vec3f,
+    direction: Vec3f,
+    max_distance: f32,
+    mut block: F,
+) -> Option<RayHit>
+where
+    F: FnMut(WorldCoords) -> Option<Block>,
+{
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+
+    let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+    if length == 0. {
+        return None;
+    }
+
+    let WorldCoords(x, y, z) = WorldCoords::containing(Vec3f::new(origin[0], origin[1], origin[2]));
+    let mut pos = [x, y, z];
+
+    // For each axis, the direction of the steps, the
+    // distance along the ray between two boundaries and
+    // the distance to the next boundary.
+    let mut step = [0; 3];
+    let mut delta = [f32::INFINITY; 3];
+    let mut next = [f32::INFINITY; 3];
+
+    for axis in 0..3 {
+        let d = direction[axis] / length;
+        let offset = origin[axis] - origin[axis].floor();
+
+        if d > 0. {
+            step[axis] = 1;
+            delta[axis] = 1. / d;
+            next[axis] = (1. - offset) / d;
+        } else if d < 0. {
+            step[axis] = -1;
+            delta[axis] = -1. / d;
+            next[axis] = offset / -d;
+        }
+    }
+
+    loop {
+        let axis = if next[0] <= next[1] && next[0] <= next[2] {
+            0
+        } else if next[1] <= next[2] {
+            1
+        } else {
+            2
+        };
+
+        let distance = next[axis];
+        if distance > max_distance {
+            return None;
+        }
+
+        pos[axis] += step[axis];
+        next[axis] += delta[axis];
+
+        let pos = WorldCoords(pos[0], pos[1], pos[2]);
+
+        match block(pos)? {
+            Block::Air => continue,
+            _ => {
+                return Some(RayHit {
+                    pos,
+                    side: entry_side(axis, step[axis]),
+                    distance,
+                })
+            }
+        }
+    }
+}
+
+// Return the face through which a ray that steps along
+// ``axis`` in the direction of ``step`` enters a block.
+fn entry_side(axis: usize, step: i32) -> Side {
+    match (axis, step > 0) {
+        (0, true) => Side::LeftSide,
+        (0, false) => Side::RightSide,
+        (1, true) => Side::Bottom,
+        (1, false) => Side::Top,
+        (_, true) => Side::Back,
+        (_, false) => Side::Front,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{entity::sector::SectorIndex, util::hash::hash3};
+
+    // A world of stone wherever ``solid`` says so, and air
+    // everywhere else.
+    fn world(solid: impl Fn(WorldCoords) -> bool) -> impl FnMut(WorldCoords) -> Option<Block> {
+        move |pos| Some(if solid(pos) { Block::Stone } else { Block::Air })
+    }
+
+    fn hit(pos: WorldCoords, side: Side, distance: f32) -> Option<RayHit> {
+        Some(RayHit {
+            pos,
+            side,
+            distance,
+        })
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_face_they_enter() {
+        let origin = Vec3f::new(0.5, 5.5, 0.5);
+        let floor_and_walls = |WorldCoords(x, y, z)| y <= 0 || x.abs() >= 3 || z.abs() >= 3;
+        let cast = |direction| raycast(origin, direction, 10., world(floor_and_walls));
+
+        let cases = [
+            (
+                Vec3f::new(0., -1., 0.),
+                WorldCoords(0, 0, 0),
+                Side::Top,
+                4.5,
+            ),
+            (
+                Vec3f::new(1., 0., 0.),
+                WorldCoords(3, 5, 0),
+                Side::LeftSide,
+                2.5,
+            ),
+            (
+                Vec3f::new(-1., 0., 0.),
+                WorldCoords(-3, 5, 0),
+                Side::RightSide,
+                2.5,
+            ),
+            (
+                Vec3f::new(0., 0., 1.),
+                WorldCoords(0, 5, 3),
+                Side::Back,
+                2.5,
+            ),
+            (
+                Vec3f::new(0., 0., -1.),
+                WorldCoords(0, 5, -3),
+                Side::Front,
+                2.5,
+            ),
+        ];
+
+        for (direction, pos, side, distance) in &cases {
+            assert_eq!(cast(*direction), hit(*pos, *side, *distance));
+        }
+
+        // Nothing is above, and the length of the direction
+        // does not matter.
+        assert_eq!(cast(Vec3f::new(0., 1., 0.)), None);
+        assert_eq!(
+            cast(Vec3f::new(0., -0.01, 0.)),
+            hit(WorldCoords(0, 0, 0), Side::Top, 4.5)
+        );
+        assert_eq!(cast(Vec3f::new(0., 0., 0.)), None);
+    }
+
+    #[test]
+    fn rays_cross_sector_boundaries_at_negative_coordinates() {
+        // The ray leaves the sector at ``z = -16``.
+        let origin = Vec3f::new(-0.5, 3.5, -15.5);
+        let wall = |WorldCoords(_, _, z)| z <= -18;
+        let result = raycast(origin, Vec3f::new(0., 0., -1.), 10., world(wall));
+
+        assert_eq!(result, hit(WorldCoords(-1, 3, -18), Side::Front, 1.5));
+        assert_eq!(
+            WorldCoords(-1, 3, -18).sector_index(),
+            SectorIndex(-1, 0, -2)
+        );
+    }
+
+    #[test]
+    fn diagonal_rays_enter_through_the_nearest_face() {
+        // The ray rises by one block every two blocks, so it
+        // reaches the ceiling before the wall.
+        let origin = Vec3f::new(-0.5, -0.5, 0.5);
+        let direction = Vec3f::new(-2., 1., 0.);
+        let length = 5f32.sqrt();
+
+        let ceiling = |WorldCoords(x, y, _)| y >= 1 || x <= -5;
+        let result = raycast(origin, direction, 10., world(ceiling)).unwrap();
+        assert_eq!(
+            (result.pos, result.side),
+            (WorldCoords(-4, 1, 0), Side::Bottom)
+        );
+        assert!((result.distance - 1.5 * length).abs() < 1e-5);
+
+        let wall = |WorldCoords(x, y, _)| y >= 2 || x <= -3;
+        let result = raycast(origin, direction, 10., world(wall)).unwrap();
+        assert_eq!(
+            (result.pos, result.side),
+            (WorldCoords(-3, 0, 0), Side::RightSide)
+        );
+        assert!((result.distance - 0.75 * length).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rays_start_on_block_boundaries() {
+        // The origin belongs to the block on its positive
+        // side, so a ray that points away from it enters the
+        // next block right away.
+        let origin = Vec3f::new(2., 1., 0.5);
+        let solid = |WorldCoords(x, _, _)| x == 1 || x == 3;
+
+        assert_eq!(
+            raycast(origin, Vec3f::new(-1., 0., 0.), 10., world(solid)),
+            hit(WorldCoords(1, 1, 0), Side::RightSide, 0.)
+        );
+        assert_eq!(
+            raycast(origin, Vec3f::new(1., 0., 0.), 10., world(solid)),
+            hit(WorldCoords(3, 1, 0), Side::LeftSide, 1.)
+        );
+
+        // The block that contains the origin is never hit.
+        let inside = |WorldCoords(x, _, _)| x >= 2;
+        assert_eq!(
+            raycast(origin, Vec3f::new(1., 0., 0.), 10., world(inside)),
+            hit(WorldCoords(3, 1, 0), Side::LeftSide, 1.)
+        );
+    }
+
+    #[test]
+    fn rays_end_at_the_maximum_distance() {
+        let origin = Vec3f::new(0.5, 0.5, 0.5);
+        let wall = |WorldCoords(x, _, _)| x >= 5;
+        let cast =
+            |max_distance| raycast(origin, Vec3f::new(1., 0., 0.), max_distance, world(wall));
+
+        assert_eq!(cast(4.4), None);
+        assert_eq!(cast(4.5), hit(WorldCoords(5, 0, 0), Side::LeftSide, 4.5));
+    }
+
+    #[test]
+    fn rays_stop_at_unloaded_sectors() {
+        // The sector beyond ``x = 16`` is not loaded, but would
+        // hold a wall.
+        let mut visited = Vec::new();
+        let result = raycast(
+            Vec3f::new(10.5, 0.5, 0.5),
+            Vec3f::new(1., 0., 0.),
+            100.,
+            |pos| {
+                visited.push(pos);
+                match pos.sector_index() {
+                    SectorIndex(0, _, _) => Some(Block::Air),
+                    _ => None,
+                }
+            },
+        );
+
+        assert_eq!(result, None);
+        assert_eq!(visited.last(), Some(&WorldCoords(16, 0, 0)));
+    }
+
+    #[test]
+    fn rays_hit_the_same_blocks_as_small_steps() {
+        // A world in which one block in eight is solid.
+        let solid = |WorldCoords(x, y, z)| hash3(x, y, z, 7).is_multiple_of(8);
+        let random = |i: i32, axis: i32| (hash3(i, axis, 0, 11) % 2001) as f32 / 1000. - 1.;
+
+        for i in 0..200 {
+            let origin = Vec3f::new(random(i, 0) * 40., random(i, 1) * 40., random(i, 2) * 40.);
+            let direction = Vec3f::new(random(i, 3), random(i, 4), random(i, 5));
+            let length = (direction.x.powi(2) + direction.y.powi(2) + direction.z.powi(2)).sqrt();
+            let result = raycast(origin, direction, 20., world(solid));
+
+            // March along the ray in tiny steps instead.
+            let start = WorldCoords::containing(origin);
+            let marched = (0..20_000)
+                .map(|step| {
+                    let t = step as f32 * 1e-3;
+                    let pos = origin + direction * (t / length);
+                    (WorldCoords::containing(pos), t)
+                })
+                .find(|(pos, _)| *pos != start && solid(*pos));
+
+            match (result, marched) {
+                (Some(result), Some((pos, t))) => {
+                    assert_eq!(result.pos, pos, "ray {}", i);
+                    assert!((result.distance - t).abs() < 2e-3, "ray {}", i);
+                }
+                (None, None) => (),
+                _ => panic!("ray {}: {:?} but marched to {:?}", i, result, marched),
+            }
+        }
+    }
+}
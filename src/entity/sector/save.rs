@@ -0,0 +1,222 @@
+//! Implements the on-disk save format for sectors.
+//!
+//! Each sector is stored on its own. A saved sector begins
+//! with a short header that holds a magic number, the format
+//! version, and the ``SectorIndex`` of the sector. The voxel
+//! data follows, either as a single block ID for uniform
+//! sectors, or as one block ID per voxel in the order of
+//! ``SectorData::iter``.
+//!
+//! All integers are stored in little-endian byte order.
+
+use super::{
+    data::{SectorData, SECTOR_LEN},
+    SectorIndex,
+};
+use crate::block::Block;
+use std::io::{self, Read, Write};
+
+// Identifies a file as a saved sector.
+const MAGIC: &[u8; 4] = b"SBXS";
+
+// The current version of the save format. Version 1
+// stored the padding of each sector as well.
+const VERSION: u8 = 2;
+
+// Marks voxel data that consists of a single block ID.
+const UNIFORM: u8 = 0;
+
+// Marks voxel data that holds one block ID per voxel.
+const FULL: u8 = 1;
+
+/// Return the name of the file in which the sector
+/// at ``index`` is saved.
+pub fn sector_file_name(index: SectorIndex) -> String {
+    let SectorIndex(x, y, z) = index;
+    format!("{}_{}_{}.sector", x, y, z)
+}
+
+/// Write the sector at ``index`` with the voxel data
+/// ``voxels`` to ``w`` in the save format.
+pub fn write_sector(w: &mut impl Write, index: SectorIndex, voxels: &SectorData) -> io::Result<()> {
+    let SectorIndex(x, y, z) = index;
+
+    w.write_all(MAGIC)?;
+    w.write_all(&[VERSION])?;
+
+    for component in &[x, y, z] {
+        w.write_all(&component.to_le_bytes())?;
+    }
+
+    match voxels.uniform_block() {
+        Some(blk) => w.write_all(&[UNIFORM, blk.id()]),
+        None => {
+            let ids: Vec<u8> = voxels.iter().map(|(_, blk)| blk.id()).collect();
+
+            w.write_all(&[FULL])?;
+            w.write_all(&ids)
+        }
+    }
+}
+
+/// Read a sector in the save format from ``r``,
+/// returning its index and voxel data.
+pub fn read_sector(r: &mut impl Read) -> io::Result<(SectorIndex, SectorData)> {
+    let mut header = [0; 5];
+    r.read_exact(&mut header)?;
+
+    if &header[..4] != MAGIC {
+        return Err(invalid("not a saved sector"));
+    }
+
+    if header[4] != VERSION {
+        return Err(invalid("unsupported sector version"));
+    }
+
+    let mut components = [0; 3];
+    for component in &mut components {
+        let mut bytes = [0; 4];
+        r.read_exact(&mut bytes)?;
+
+        *component = i32::from_le_bytes(bytes);
+    }
+
+    let index = SectorIndex(components[0], components[1], components[2]);
+
+    let mut kind = [0];
+    r.read_exact(&mut kind)?;
+
+    let voxels = match kind[0] {
+        UNIFORM => {
+            let mut id = [0];
+            r.read_exact(&mut id)?;
+
+            SectorData::uniform(block_from_id(id[0])?)
+        }
+        FULL => {
+            let mut ids = vec![0; SECTOR_LEN];
+            r.read_exact(&mut ids)?;
+
+            let mut voxels = SectorData::new();
+            for ((_, blk), id) in voxels.iter_mut().zip(ids) {
+                *blk = block_from_id(id)?;
+            }
+
+            voxels
+        }
+        _ => return Err(invalid("unknown voxel data kind")),
+    };
+
+    Ok((index, voxels))
+}
+
+fn block_from_id(id: u8) -> io::Result<Block> {
+    Block::from_id(id).ok_or_else(|| invalid("unknown block ID"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write the sector at ``index``, read it back and check
+    // that nothing was lost.
+    fn round_trip(index: SectorIndex, voxels: &SectorData) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_sector(&mut bytes, index, voxels).unwrap();
+
+        let (read_index, read_voxels) = read_sector(&mut &bytes[..]).unwrap();
+        assert_eq!(read_index, index);
+        assert_eq!(read_voxels.uniform_block(), voxels.uniform_block());
+        assert!(read_voxels
+            .iter()
+            .map(|(_, blk)| blk)
+            .eq(voxels.iter().map(|(_, blk)| blk)));
+
+        bytes
+    }
+
+    // Return a saved sector whose voxels are all ``blk``.
+    fn saved_uniform(blk: Block) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_sector(&mut bytes, SectorIndex(0, 0, 0), &SectorData::uniform(blk)).unwrap();
+
+        bytes
+    }
+
+    fn read_error(bytes: &[u8]) -> io::Error {
+        match read_sector(&mut &bytes[..]) {
+            Ok(_) => panic!("the sector was read"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn uniform_sectors_store_one_block() {
+        let bytes = round_trip(
+            SectorIndex(-3, 7, -2_000_000),
+            &SectorData::uniform(Block::Stone),
+        );
+
+        assert_eq!(bytes.len(), 4 + 1 + 3 * 4 + 2);
+    }
+
+    #[test]
+    fn full_sectors_store_every_block() {
+        let mut voxels = SectorData::new();
+        for (i, (_, blk)) in voxels.iter_mut().enumerate() {
+            *blk = Block::ALL[i % Block::ALL.len()];
+        }
+
+        let bytes = round_trip(SectorIndex(1, -1, 0), &voxels);
+
+        assert_eq!(bytes.len(), 4 + 1 + 3 * 4 + 1 + SECTOR_LEN);
+    }
+
+    #[test]
+    fn bad_magic_numbers_are_rejected() {
+        let mut bytes = saved_uniform(Block::Stone);
+        bytes[0] = b'X';
+
+        let e = read_error(&bytes);
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "not a saved sector");
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        for version in &[VERSION - 1, VERSION + 1] {
+            let mut bytes = saved_uniform(Block::Stone);
+            bytes[MAGIC.len()] = *version;
+
+            let e = read_error(&bytes);
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(e.to_string(), "unsupported sector version");
+        }
+    }
+
+    #[test]
+    fn unknown_block_ids_are_rejected() {
+        let mut bytes = saved_uniform(Block::Stone);
+        *bytes.last_mut().unwrap() = 255;
+
+        let e = read_error(&bytes);
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(e.to_string(), "unknown block ID");
+    }
+
+    #[test]
+    fn truncated_sectors_are_rejected() {
+        let mut voxels = SectorData::new();
+        *voxels.iter_mut().next().unwrap().1 = Block::Bricks;
+
+        let mut bytes = Vec::new();
+        write_sector(&mut bytes, SectorIndex(0, 0, 0), &voxels).unwrap();
+        bytes.pop();
+
+        assert_eq!(read_error(&bytes).kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
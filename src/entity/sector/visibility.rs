@@ -0,0 +1,456 @@
+//! Decides which sectors could possibly be seen from the
+//! camera, so that caves behind solid rock are not drawn.
+//!
+//! When a sector is meshed, its transparent voxels are flood
+//! filled to find out which of its six faces are joined by
+//! a path through the sector. Each frame, a breadth-first
+//! search then walks from the camera's sector to each of its
+//! neighbors, but only leaves a sector through a face that is
+//! connected to the face it was entered through. The search
+//! never turns back towards the camera along any axis, since
+//! a line of sight cannot do so either.
+//!
+//! A sector that is reached might still be hidden, since the
+//! connections only say that some path joins two faces, not
+//! whether it is straight enough to look through.
+
+use super::{
+    data::{SectorCoords, SectorData, SECTOR_DIM_EXCL, SECTOR_LEN},
+    SectorIndex, SectorRegion,
+};
+use crate::side::Side;
+use std::collections::VecDeque;
+
+/// Records which pairs of the six faces of a sector are
+/// joined by a path of transparent voxels.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct FaceConnections {
+    // For each side, a mask of the sides it is connected
+    // to, indexed in the order of ``Side::ALL``.
+    links: [u8; 6],
+}
+
+impl FaceConnections {
+    /// Create a new ``FaceConnections`` in which no two
+    /// faces are connected.
+    pub fn none() -> FaceConnections {
+        FaceConnections::default()
+    }
+
+    /// Create a new ``FaceConnections`` in which every face
+    /// is connected to every other.
+    pub fn all() -> FaceConnections {
+        FaceConnections {
+            links: [0b11_1111; 6],
+        }
+    }
+
+    /// Find the connections between the faces of a sector by
+    /// flood filling its transparent voxels.
+    ///
+    /// Only the voxels inside of the sector are considered.
+    pub fn from_sector(data: &SectorData) -> FaceConnections {
+        if let Some(blk) = data.uniform_block() {
+            return if blk.is_transparent() {
+                FaceConnections::all()
+            } else {
+                FaceConnections::none()
+            };
+        }
+
+        let mut connections = FaceConnections::none();
+        let mut visited = vec![false; SECTOR_LEN];
+        let mut stack = Vec::new();
+
+        // Only regions of air that touch the boundary of the
+        // sector can connect any faces, so the flood fills
+        // start there.
+        for start in (0..SECTOR_LEN).filter(|i| boundary_sides(*i) != 0) {
+            if visited[start] || !is_open(data, start) {
+                continue;
+            }
+
+            let mut touched = 0;
+            visited[start] = true;
+            stack.push(start);
+
+            while let Some(i) = stack.pop() {
+                touched |= boundary_sides(i);
+
+                for side in &Side::ALL {
+                    if let Some(next) = step(i, *side) {
+                        if !visited[next] && is_open(data, next) {
+                            visited[next] = true;
+                            stack.push(next);
+                        }
+                    }
+                }
+            }
+
+            connections.join(touched);
+        }
+
+        connections
+    }
+
+    /// Returns ``true`` if a path through the sector joins
+    /// the faces on ``a`` and ``b``.
+    pub fn connected(self, a: Side, b: Side) -> bool {
+        self.links[a as usize] & (1 << b as usize) != 0
+    }
+
+    // Connect every pair of the sides in ``mask``.
+    fn join(&mut self, mask: u8) {
+        for (side, links) in self.links.iter_mut().enumerate() {
+            if mask & (1 << side) != 0 {
+                *links |= mask;
+            }
+        }
+    }
+}
+
+/// The sectors that might be visible from the camera.
+#[derive(Clone, Debug)]
+pub struct VisibleSet {
+    region: SectorRegion,
+
+    // Whether each sector of the region might be visible,
+    // or ``None`` if every sector might be.
+    visible: Option<Vec<bool>>,
+}
+
+impl VisibleSet {
+    /// Create a new ``VisibleSet`` that contains every
+    /// sector, for when nothing is known to be hidden.
+    pub fn everything() -> VisibleSet {
+        VisibleSet {
+            region: SectorRegion::new(SectorIndex(0, 0, 0), SectorIndex(0, 0, 0)),
+            visible: None,
+        }
+    }
+
+    /// Returns ``true`` if the sector at ``index`` might
+    /// be visible.
+    pub fn contains(&self, index: SectorIndex) -> bool {
+        match self.visible {
+            Some(ref visible) => slot(&self.region, index).is_some_and(|i| visible[i]),
+            None => true,
+        }
+    }
+}
+
+/// Find every sector of ``region`` that might be visible from
+/// a camera in the sector at ``start``.
+///
+/// ``connections`` returns the face connections of the sector
+/// at the given index. Sectors that have not been loaded yet
+/// should count as fully connected, so that they do not hide
+/// the sectors behind them. Sectors outside of ``region`` are
+/// never entered, and if ``start`` lies outside of it, every
+/// sector might be visible.
+pub fn visible_sectors<F>(
+    region: SectorRegion,
+    start: SectorIndex,
+    mut connections: F,
+) -> VisibleSet
+where
+    F: FnMut(SectorIndex) -> FaceConnections,
+{
+    let start_slot = match slot(&region, start) {
+        Some(i) => i,
+        None => return VisibleSet::everything(),
+    };
+
+    let mut visible = vec![false; region.len()];
+    let mut queue = VecDeque::new();
+
+    // A sector may lead on to different sectors depending on
+    // the face it was entered through, so it is visited once
+    // for each of them. This holds the mask of the faces that
+    // each sector has been entered through.
+    let mut entries = vec![0u8; region.len()];
+
+    // Each entry holds the face through which the sector was
+    // entered, and a mask of the directions taken so far.
+    visible[start_slot] = true;
+    queue.push_back((start, None, 0u8));
+
+    while let Some((index, entered, directions)) = queue.pop_front() {
+        let links = connections(index);
+
+        for side in &Side::ALL {
+            let side = *side;
+            let next = index.neighbor(side);
+            let back = side.opposite();
+
+            // Turning back would mean looking back through
+            // a sector that has already been passed.
+            if directions & (1 << back as usize) != 0 {
+                continue;
+            }
+
+            if let Some(entered) = entered {
+                if !links.connected(entered, side) {
+                    continue;
+                }
+            }
+
+            let i = match slot(&region, next) {
+                Some(i) if entries[i] & (1 << back as usize) == 0 => i,
+                _ => continue,
+            };
+
+            entries[i] |= 1 << back as usize;
+            visible[i] = true;
+            queue.push_back((next, Some(back), directions | 1 << side as usize));
+        }
+    }
+
+    VisibleSet {
+        region,
+        visible: Some(visible),
+    }
+}
+
+// Return the position of the sector at ``index`` in an array
+// that holds every sector of ``region``, if it is in it.
+fn slot(region: &SectorRegion, index: SectorIndex) -> Option<usize> {
+    if !region.contains(index) {
+        return None;
+    }
+
+    let SectorRegion { min, max } = *region;
+    let (w, h) = ((max.0 - min.0) as usize, (max.1 - min.1) as usize);
+    let (x, y, z) = (
+        (index.0 - min.0) as usize,
+        (index.1 - min.1) as usize,
+        (index.2 - min.2) as usize,
+    );
+
+    Some(x + w * (y + h * z))
+}
+
+// Returns ``true`` if the voxel at the inner index ``i``
+// can be seen through.
+fn is_open(data: &SectorData, i: usize) -> bool {
+    data.block(sector_coords(i)).is_transparent()
+}
+
+// Convert an index into the voxels of a sector into sector
+// coordinates.
+fn sector_coords(i: usize) -> SectorCoords {
+    const DIM: usize = SECTOR_DIM_EXCL;
+
+    SectorCoords(i % DIM, i / DIM % DIM, i / (DIM * DIM))
+}
+
+// Return the mask of the sides of the sector that the voxel
+// at the inner index ``i`` lies on.
+fn boundary_sides(i: usize) -> u8 {
+    const DIM: usize = SECTOR_DIM_EXCL;
+    const LAST: usize = DIM - 1;
+
+    let (x, y, z) = (i % DIM, i / DIM % DIM, i / (DIM * DIM));
+
+    let mut mask = 0;
+    for (on_side, side) in &[
+        (z == LAST, Side::Front),
+        (z == 0, Side::Back),
+        (x == LAST, Side::RightSide),
+        (x == 0, Side::LeftSide),
+        (y == LAST, Side::Top),
+        (y == 0, Side::Bottom),
+    ] {
+        if *on_side {
+            mask |= 1 << *side as usize;
+        }
+    }
+
+    mask
+}
+
+// Return the inner index of the neighbor of the voxel at
+// the inner index ``i`` on ``side``, if it is inside of
+// the sector.
+fn step(i: usize, side: Side) -> Option<usize> {
+    const DIM: usize = SECTOR_DIM_EXCL;
+
+    let (x, y, z) = (i % DIM, i / DIM % DIM, i / (DIM * DIM));
+    let inside = match side {
+        Side::Front => z + 1 < DIM,
+        Side::Back => z > 0,
+        Side::RightSide => x + 1 < DIM,
+        Side::LeftSide => x > 0,
+        Side::Top => y + 1 < DIM,
+        Side::Bottom => y > 0,
+    };
+
+    if !inside {
+        return None;
+    }
+
+    Some(match side {
+        Side::Front => i + DIM * DIM,
+        Side::Back => i - DIM * DIM,
+        Side::RightSide => i + 1,
+        Side::LeftSide => i - 1,
+        Side::Top => i + DIM,
+        Side::Bottom => i - DIM,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    const LAST: usize = SECTOR_DIM_EXCL - 1;
+
+    // A sector of stone in which ``open`` returns ``true``
+    // for the voxels that are carved out.
+    fn carved<F>(open: F) -> SectorData
+    where
+        F: Fn(usize, usize, usize) -> bool,
+    {
+        let mut data = SectorData::uniform(Block::Stone);
+
+        for (coords, blk) in data.iter_mut() {
+            let SectorCoords(x, y, z) = coords;
+
+            if open(x, y, z) {
+                *blk = Block::Air;
+            }
+        }
+
+        data
+    }
+
+    // Every pair of sides that are connected.
+    fn pairs(connections: FaceConnections) -> Vec<(Side, Side)> {
+        let mut pairs = Vec::new();
+
+        for (i, a) in Side::ALL.iter().enumerate() {
+            for b in &Side::ALL[i + 1..] {
+                if connections.connected(*a, *b) {
+                    pairs.push((*a, *b));
+                }
+            }
+        }
+
+        pairs
+    }
+
+    #[test]
+    fn solid_sectors_connect_nothing() {
+        let uniform = SectorData::uniform(Block::Stone);
+        assert_eq!(
+            FaceConnections::from_sector(&uniform),
+            FaceConnections::none()
+        );
+
+        // A sector that is only stored in full, but solid.
+        let full = carved(|_, _, _| false);
+        assert_eq!(full.uniform_block(), None);
+        assert_eq!(FaceConnections::from_sector(&full), FaceConnections::none());
+    }
+
+    #[test]
+    fn hollow_sectors_connect_nothing() {
+        let inner = |c: usize| c > 0 && c < LAST;
+        let hollow = carved(|x, y, z| inner(x) && inner(y) && inner(z));
+
+        assert_eq!(
+            FaceConnections::from_sector(&hollow),
+            FaceConnections::none()
+        );
+    }
+
+    #[test]
+    fn open_sectors_connect_everything() {
+        let air = SectorData::uniform(Block::Air);
+        assert_eq!(FaceConnections::from_sector(&air), FaceConnections::all());
+
+        // Slabs and torches can be seen past.
+        let slabs = carved(|_, y, _| y % 2 == 0);
+        let mut with_blocks = slabs.clone();
+        for (coords, blk) in with_blocks.iter_mut() {
+            if coords.1 % 2 == 0 {
+                *blk = if coords.0 % 2 == 0 {
+                    Block::Slab
+                } else {
+                    Block::Torch
+                };
+            }
+        }
+
+        assert_eq!(
+            FaceConnections::from_sector(&slabs),
+            FaceConnections::from_sector(&with_blocks)
+        );
+    }
+
+    #[test]
+    fn tunnels_connect_the_faces_they_pass_through() {
+        let straight = carved(|_, y, z| y == 8 && z == 8);
+        assert_eq!(
+            pairs(FaceConnections::from_sector(&straight)),
+            vec![(Side::RightSide, Side::LeftSide)]
+        );
+
+        // A tunnel from the left side that turns upwards in
+        // the middle, next to a shaft that touches no side.
+        let bent = carved(|x, y, z| {
+            z == 8 && ((y == 4 && x <= 8) || (x == 8 && y >= 4) || (x == 12 && y > 2 && y < 10))
+        });
+        assert_eq!(
+            pairs(FaceConnections::from_sector(&bent)),
+            vec![(Side::LeftSide, Side::Top)]
+        );
+    }
+
+    #[test]
+    fn sectors_behind_a_solid_shell_are_hidden() {
+        let region = SectorRegion::new(SectorIndex(-2, -2, -2), SectorIndex(3, 3, 3));
+        let ring = |index: SectorIndex| {
+            let SectorIndex(x, y, z) = index;
+            x.abs().max(y.abs()).max(z.abs())
+        };
+
+        // The camera's sector is open, and every sector
+        // around it is solid.
+        let shell = |index| {
+            if ring(index) == 1 {
+                FaceConnections::none()
+            } else {
+                FaceConnections::all()
+            }
+        };
+
+        // Only the sectors that share a face with the camera's
+        // sector are entered. The edges and corners of the
+        // shell can only be seen through the shell itself.
+        let visible = visible_sectors(region, SectorIndex(0, 0, 0), shell);
+        for index in region.iter() {
+            let SectorIndex(x, y, z) = index;
+            let adjacent = x.abs() + y.abs() + z.abs() <= 1;
+
+            assert_eq!(visible.contains(index), adjacent, "{:?}", index);
+        }
+
+        // A tunnel through the shell only leads onwards.
+        let tunnel = carved(|_, y, z| y == 8 && z == 8);
+        let pierced = |index| {
+            if index == SectorIndex(1, 0, 0) {
+                FaceConnections::from_sector(&tunnel)
+            } else {
+                shell(index)
+            }
+        };
+
+        let visible = visible_sectors(region, SectorIndex(0, 0, 0), pierced);
+        assert!(visible.contains(SectorIndex(2, 0, 0)));
+        assert!(visible.contains(SectorIndex(2, 1, 0)));
+        assert!(!visible.contains(SectorIndex(-2, 0, 0)));
+        assert!(!visible.contains(SectorIndex(0, 2, 0)));
+    }
+}
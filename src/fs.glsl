@@ -1,11 +1,51 @@
 in vec2 pass_uv;
+in vec2 pass_tile;
+in float pass_ao;
+in vec2 pass_light;
+in vec3 pass_normal;
+in float pass_layer;
+in vec3 pass_tint;
 
 out vec4 frag_color;
 
-uniform sampler2D terrain_texture;
+// The highest light level.
+const float MAX_LIGHT = 15.0;
+
+// The direction towards the sun, and how much darker
+// faces turned away from it are.
+uniform vec3 sun_direction;
+uniform float sun_strength;
+
+// The brightness of a fully occluded corner, and how much
+// brighter each light level is than the one below.
+uniform float ao_min;
+uniform float light_falloff;
+
+// Samples the block texture. It is defined by either
+// terrain_atlas.glsl or terrain_array.glsl, which are
+// appended to this shader.
+vec3 terrain_color();
 
 void main(void) {
-    frag_color = vec4(texture(terrain_texture, pass_uv).rgb, 1.0);
+    // Darken occluded corners. pass_ao ranges from zero
+    // (fully occluded) to three (not occluded at all).
+    float occlusion = mix(ao_min, 1.0, pass_ao / 3.0);
+    
+    // The brighter of sky and block light wins. pass_light
+    // holds both in the range [0, 1].
+    float level = max(pass_light.x, pass_light.y) * MAX_LIGHT;
+    float brightness = pow(light_falloff, MAX_LIGHT - level);
+    
+    // Shade each side of a block by how directly it faces
+    // the sun, so that top, side, and bottom faces differ.
+    float facing = max(dot(normalize(pass_normal), normalize(sun_direction)), 0.0);
+    float shade = mix(1.0 - sun_strength, 1.0, facing);
+    
+    // Recolor the texture, for example by the grass
+    // color of the biome.
+    vec3 color = terrain_color() * pass_tint;
+    
+    frag_color = vec4(color * occlusion * brightness * shade, 1.0);
     
     /*
     frag_color += vec4(2.0, 2.0, 2.0, 2.0);
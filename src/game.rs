@@ -0,0 +1,459 @@
+//! Ties the world, the player and the renderer together.
+//!
+//! A ``Game`` holds the state of a running game, apart from
+//! the window. Each frame, ``update`` advances it by the time
+//! that has passed, given the state of the controls, and
+//! ``draw`` hands the sectors that might be visible to a
+//! ``Renderer``. Neither needs a window, so a game can be
+//! driven by tests with a ``RecordingRenderer``.
+
+use crate::{
+    block::Block,
+    entity::{
+        camera::Camera,
+        player::Player,
+        sector::{self, CullStats, RayHit, SectorManager},
+    },
+    maths::{
+        frustum::Frustum,
+        matrix::{Projection, Transform},
+        vector::{MathVec, Vec2f, Vec3f},
+    },
+    render::{Draw, Frame, Outline, Renderer},
+};
+use std::f32::consts::PI;
+
+/// How fast the player moves, in blocks per second.
+pub const MOVE_SPEED: f32 = 4.0;
+
+/// How far away from the player's eyes blocks can be
+/// broken or placed, in blocks.
+pub const REACH: f32 = 5.0;
+
+/// The vertical field of view, in radians.
+pub const FOV: f32 = 40.0 * PI / 180.0;
+
+// The distances of the near and far clipping planes.
+const NEAR: f32 = 0.1;
+const FAR: f32 = 1000.0;
+
+/// The state of the controls during one update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Input {
+    /// The direction to move the player in, relative to
+    /// where it looks: ``x`` points right, ``y`` up and
+    /// ``z`` backwards. Each component lies between -1
+    /// and 1, and is scaled by ``MOVE_SPEED``.
+    pub movement: Vec3f,
+
+    /// How far to turn the player's view, in radians,
+    /// as pitch and yaw.
+    pub look: Vec2f,
+
+    /// Whether to break the block that the player
+    /// looks at.
+    pub break_block: bool,
+
+    /// Whether to place the selected block against the
+    /// face of the block that the player looks at. Has
+    /// no effect if ``break_block`` is set.
+    pub place_block: bool,
+}
+
+impl Input {
+    /// Create a new ``Input`` in which no control
+    /// is used.
+    pub fn new() -> Input {
+        Default::default()
+    }
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input {
+            movement: Vec3f::new(0., 0., 0.),
+            look: Vec2f::new(0., 0.),
+            break_block: false,
+            place_block: false,
+        }
+    }
+}
+
+/// A running game, drawn by a ``Renderer``.
+pub struct Game<R: Renderer> {
+    renderer: R,
+    sectors: SectorManager,
+    player: Player,
+    selected: Block,
+    camera: Camera,
+    projection: Projection,
+    culling: CullStats,
+}
+
+impl<R: Renderer> Game<R> {
+    /// Create a new ``Game`` with the player standing on
+    /// the origin, drawn by ``renderer`` onto a surface of
+    /// the given size in pixels.
+    ///
+    /// The player starts out placing stone.
+    pub fn new(renderer: R, sectors: SectorManager, size: [u32; 2]) -> Game<R> {
+        let mut game = Game {
+            renderer,
+            sectors,
+            player: Player::at_origin(),
+            selected: Block::Stone,
+            camera: Camera::new(),
+            projection: Projection::new(FOV, 1., NEAR, FAR),
+            culling: CullStats::new(),
+        };
+
+        game.resize(size);
+        game
+    }
+
+    /// Advance the game by ``dt`` seconds.
+    ///
+    /// Sectors that have finished generating are loaded,
+    /// and the player is moved and turned by ``input``,
+    /// without passing into blocks. The sectors around the
+    /// player are generated first.
+    /// Then, blocks are broken or placed, and the sectors
+    /// that changed are remeshed.
+    pub fn update(&mut self, dt: f64, input: &Input) {
+        self.sectors.finalize_sectors(&mut self.renderer);
+
+        let distance = MOVE_SPEED * dt as f32;
+        let Vec3f { x, y, z } = input.movement;
+
+        let sectors = &self.sectors;
+        let block = |pos| sectors.block(pos);
+
+        self.player.move_x(x * distance, block);
+        self.player.slide((0., y * distance, 0.), block);
+        self.player.move_z(z * distance, block);
+        self.player.spin(input.look);
+
+        self.camera.snap_to(&self.player);
+        self.sectors.focus(self.player.position());
+
+        if input.break_block || input.place_block {
+            self.edit_target(input.break_block);
+        }
+
+        self.sectors.remesh_sectors(&mut self.renderer);
+    }
+
+    /// Return the block that the player looks at, if it
+    /// is within ``REACH``.
+    pub fn target(&self) -> Option<RayHit> {
+        self.sectors.raycast(
+            self.player.eye_position(),
+            self.player.look_direction(),
+            REACH,
+        )
+    }
+
+    // Break the block that the player looks at, or place
+    // the selected block against it.
+    fn edit_target(&mut self, break_block: bool) {
+        let hit = match self.target() {
+            Some(hit) => hit,
+            None => return,
+        };
+
+        if break_block {
+            self.sectors.set_block(hit.pos, Block::Air);
+            return;
+        }
+
+        // Blocks are only placed into air, and solid blocks
+        // never where they would trap the player.
+        let pos = hit.pos.neighbor(hit.side);
+        let traps = self.selected.collision_shape().is_some() && self.player.overlaps_block(pos);
+        if self.sectors.block(pos) == Some(Block::Air) && !traps {
+            self.sectors.set_block(pos, self.selected);
+        }
+    }
+
+    /// Draw a frame.
+    ///
+    /// Sectors outside of the view frustum, or hidden
+    /// behind solid rock, are not drawn, and distant
+    /// sectors are drawn with coarser meshes. The block
+    /// that the player looks at is outlined.
+    pub fn draw(&mut self) {
+        let view = self.camera.to_matrix();
+        let projection = self.projection.to_matrix();
+        let frustum = Frustum::from_matrix(&(&projection * &view));
+        let visible = self.sectors.visible_sectors(self.player.position());
+
+        self.culling = CullStats::new();
+
+        let mut draws = Vec::new();
+        for (index, sector) in &self.sectors {
+            let distance = (index.center() - self.player.position()).mag();
+            let level = sector::lod_for_distance(distance);
+
+            match sector.lod_geometry(level) {
+                Some(mesh) if self.culling.check(&frustum, &visible, *index) => draws.push(Draw {
+                    mesh,
+                    model: sector.translation().clone(),
+                }),
+                _ => {}
+            }
+        }
+
+        let outlines = self
+            .target()
+            .and_then(|hit| Outline::of_block(self.sectors.block(hit.pos)?, hit.pos))
+            .into_iter()
+            .collect();
+
+        self.renderer.draw_frame(&Frame {
+            view,
+            projection,
+            draws,
+            outlines,
+        });
+    }
+
+    /// Adapt the projection to a surface of the given
+    /// size in pixels.
+    pub fn resize(&mut self, size: [u32; 2]) {
+        let [width, height] = size;
+        self.projection.aspect = width as f32 / height.max(1) as f32;
+    }
+
+    /// Return the renderer that draws the game.
+    pub fn renderer(&self) -> &R {
+        &self.renderer
+    }
+
+    /// Return the renderer that draws the game mutably.
+    pub fn renderer_mut(&mut self) -> &mut R {
+        &mut self.renderer
+    }
+
+    /// Return the sectors of the world.
+    pub fn sectors(&self) -> &SectorManager {
+        &self.sectors
+    }
+
+    /// Return the player.
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    /// Return the block that the player places.
+    pub fn selected_block(&self) -> Block {
+        self.selected
+    }
+
+    /// Choose the block that the player places.
+    pub fn select_block(&mut self, blk: Block) {
+        self.selected = blk;
+    }
+
+    /// Returns how many sectors were drawn and culled
+    /// in the last frame.
+    pub fn cull_stats(&self) -> CullStats {
+        self.culling
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entity::{
+            player::EYE_HEIGHT,
+            sector::{
+                SectorIndex, SectorRegion, TerrainTextures, TextureLayout, VertexFormat,
+                WorldCoords, LOD_LEVELS,
+            },
+        },
+        render::{MeshId, RecordingRenderer},
+    };
+    use std::{
+        collections::HashSet,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    // A game of the sectors around the origin, after all of
+    // them have been generated and loaded.
+    fn game() -> Game<RecordingRenderer> {
+        let textures = TerrainTextures::for_tests(TextureLayout::Array);
+        let region = SectorRegion::new(SectorIndex(-1, -1, -1), SectorIndex(1, 1, 1));
+        let sectors = SectorManager::with_region(textures, VertexFormat::Full, region);
+        let mut game = Game::new(RecordingRenderer::new(), sectors, [640, 360]);
+
+        let start = Instant::now();
+        while game.sectors().iter().count() < region.len() {
+            assert!(
+                start.elapsed() < Duration::from_secs(60),
+                "sectors never loaded"
+            );
+
+            thread::sleep(Duration::from_millis(1));
+            game.update(0., &Input::new());
+        }
+
+        game
+    }
+
+    // Every mesh that the sectors refer to, at any level
+    // of detail.
+    fn sector_meshes(game: &Game<RecordingRenderer>) -> HashSet<MeshId> {
+        game.sectors()
+            .iter()
+            .flat_map(|(_, sector)| (0..LOD_LEVELS).filter_map(move |l| sector.lod_geometry(l)))
+            .collect()
+    }
+
+    fn geometry(game: &Game<RecordingRenderer>, index: SectorIndex) -> Option<MeshId> {
+        game.sectors()
+            .iter()
+            .find(|(i, _)| **i == index)
+            .and_then(|(_, sector)| sector.geometry())
+    }
+
+    #[test]
+    fn loaded_sectors_are_uploaded_and_drawn() {
+        let mut game = game();
+        let meshes = sector_meshes(&game);
+
+        // Only the sectors below the surface have any faces,
+        // and nothing else was uploaded.
+        assert!(!meshes.is_empty());
+        assert_eq!(game.renderer().mesh_count(), meshes.len());
+        for mesh in &meshes {
+            assert!(game.renderer().mesh(*mesh).is_some());
+        }
+
+        for n in 1..=3 {
+            game.draw();
+
+            let frame = game.renderer().last_frame().unwrap();
+            let stats = game.cull_stats();
+
+            // Sectors without any faces are not even tested.
+            assert_eq!(game.renderer().frame_count(), n);
+            assert!(!frame.draws.is_empty());
+            assert_eq!(frame.draws.len(), stats.drawn);
+            assert!(stats.drawn + stats.culled + stats.occluded <= meshes.len());
+            for draw in &frame.draws {
+                assert!(meshes.contains(&draw.mesh));
+            }
+        }
+    }
+
+    #[test]
+    fn players_move_and_turn_with_the_input() {
+        let mut game = game();
+        let start = game.player().position();
+
+        // Walk forwards for a second, along negative Z.
+        let mut input = Input::new();
+        input.movement = Vec3f::new(0., 0., -1.);
+        for _ in 0..10 {
+            game.update(0.1, &input);
+            game.draw();
+        }
+
+        let moved = game.player().position() - start;
+        assert!((moved.z + MOVE_SPEED).abs() < 1e-3, "{:?}", moved);
+        assert!(moved.x.abs() < 1e-3 && moved.y.abs() < 1e-3, "{:?}", moved);
+        assert_eq!(game.renderer().frame_count(), 10);
+
+        // Then turn right by a quarter and walk again,
+        // which now leads along positive X.
+        let before = game.player().position();
+        let mut input = Input::new();
+        input.look = Vec2f::new(0., -PI / 2.);
+        game.update(0., &input);
+
+        let mut input = Input::new();
+        input.movement = Vec3f::new(0., 0., -1.);
+        game.update(0.25, &input);
+
+        let moved = game.player().position() - before;
+        assert!((moved.x - MOVE_SPEED / 4.).abs() < 1e-3, "{:?}", moved);
+        assert!(moved.z.abs() < 1e-3, "{:?}", moved);
+    }
+
+    #[test]
+    fn broken_blocks_are_remeshed_and_outlined() {
+        let mut game = game();
+
+        // Step into the middle of the block, and look straight
+        // down at the grass below the player.
+        let mut input = Input::new();
+        input.movement = Vec3f::new(1., 0., 1.);
+        input.look = Vec2f::new(-PI / 2., 0.);
+        game.update(0.5 / MOVE_SPEED as f64, &input);
+        game.draw();
+
+        let below = WorldCoords(0, -1, 0);
+        let outlines = &game.renderer().last_frame().unwrap().outlines;
+        assert_eq!(outlines.len(), 1);
+        assert_eq!(game.target().map(|hit| hit.pos), Some(below));
+
+        let index = below.sector_index();
+        let old = geometry(&game, index).unwrap();
+        let old_vertices = game.renderer().mesh(old).unwrap().vertices.len();
+
+        let mut input = Input::new();
+        input.break_block = true;
+        game.update(0., &input);
+        game.draw();
+
+        // The old mesh was replaced by one that shows the hole.
+        let new = geometry(&game, index).unwrap();
+        assert_eq!(game.sectors().block(below), Some(Block::Air));
+        assert!(game.renderer().mesh(old).is_none());
+        assert!(game.renderer().mesh(new).unwrap().vertices.len() > old_vertices);
+        assert_eq!(game.renderer().mesh_count(), sector_meshes(&game).len());
+
+        let frame = game.renderer().last_frame().unwrap();
+        assert!(frame.draws.iter().any(|draw| draw.mesh == new));
+        assert!(frame.draws.iter().all(|draw| draw.mesh != old));
+    }
+
+    #[test]
+    fn torches_are_placed_at_the_feet_of_the_player() {
+        let mut game = game();
+
+        // Step into the middle of the block, and look straight
+        // down at the grass below the player from its eyes.
+        let mut input = Input::new();
+        input.movement = Vec3f::new(1., 0., 1.);
+        input.look = Vec2f::new(-PI / 2., 0.);
+        game.update(0.5 / MOVE_SPEED as f64, &input);
+
+        let (feet, eye) = (game.player().position(), game.player().eye_position());
+        assert_eq!(eye, feet + Vec3f::new(0., EYE_HEIGHT, 0.));
+        let (below, pos) = (WorldCoords(0, -1, 0), WorldCoords(0, 0, 0));
+        assert_eq!(game.target().map(|hit| hit.pos), Some(below));
+
+        // Stone would trap the player, but a torch does not.
+        let mut input = Input::new();
+        input.place_block = true;
+
+        game.update(0., &input);
+        assert_eq!(game.sectors().block(pos), Some(Block::Air));
+
+        game.select_block(Block::Torch);
+        game.update(0., &input);
+        assert_eq!(game.sectors().block(pos), Some(Block::Torch));
+
+        // The player walks on through the torch.
+        let before = game.player().position();
+        let mut input = Input::new();
+        input.movement = Vec3f::new(0., 0., -1.);
+        game.update(1. / MOVE_SPEED as f64, &input);
+
+        let moved = game.player().position() - before;
+        assert!((moved.z + 1.).abs() < 1e-3, "{:?}", moved);
+    }
+}
@@ -0,0 +1,260 @@
+//! Draws the game into its window with OpenGL, through
+//! ``luminance``.
+
+use luminance::{
+    context::GraphicsContext,
+    face_culling::FaceCulling,
+    framebuffer::Framebuffer,
+    linear::M44,
+    pipeline::BoundTexture,
+    pixel::Floating,
+    render_state::RenderState,
+    shader::program::{Program, Uniform},
+    tess::{Mode, Tess, TessBuilder},
+    texture::{Dim2, Dim2Array, Flat},
+};
+use luminance_derive::UniformInterface;
+use luminance_glfw_custom::surface::{GlfwSurface, Surface};
+use sandbox::{
+    entity::sector::{PreGeometry, TextureLayout, VertexFormat, Vertices},
+    render::{
+        Frame, MeshId, Renderer, AO_MIN, LIGHT_FALLOFF, OUTLINE_COLOR, SUN_DIRECTION, SUN_STRENGTH,
+    },
+    resource::ResourceManager,
+    vertexattrib::{OutlineVertex, PosAttrib, Semantic},
+};
+use std::collections::HashMap;
+
+const VS: &str = include_str!("vs.glsl");
+const VS_PACKED: &str = include_str!("vs_packed.glsl");
+const FS: &str = include_str!("fs.glsl");
+
+// The two ways the fragment shader can sample block
+// textures, one for each ``TextureLayout``.
+const TERRAIN_ATLAS: &str = include_str!("terrain_atlas.glsl");
+const TERRAIN_ARRAY: &str = include_str!("terrain_array.glsl");
+
+// The shaders that draw the lines of outlines.
+const OUTLINE_VS: &str = include_str!("outline_vs.glsl");
+const OUTLINE_FS: &str = include_str!("outline_fs.glsl");
+
+const BLACK: [f32; 4] = [0., 0., 0., 0.];
+
+#[derive(UniformInterface)]
+struct ShaderInterface {
+    //time: Uniform<f32>,
+    model_mat: Uniform<M44>,
+    view_mat: Uniform<M44>,
+    projection_mat: Uniform<M44>,
+    // Only the texture uniforms of the layout in use are
+    // present in the shader.
+    #[uniform(unbound)]
+    terrain_texture: Uniform<&'static BoundTexture<'static, Flat, Dim2, Floating>>,
+    #[uniform(unbound)]
+    tile_size: Uniform<[f32; 2]>,
+    #[uniform(unbound)]
+    terrain_array: Uniform<&'static BoundTexture<'static, Flat, Dim2Array, Floating>>,
+    sun_direction: Uniform<[f32; 3]>,
+    sun_strength: Uniform<f32>,
+    ao_min: Uniform<f32>,
+    light_falloff: Uniform<f32>,
+    // The atlas grid is only needed to decode packed vertices.
+    #[uniform(unbound)]
+    atlas_columns: Uniform<u32>,
+    #[uniform(unbound)]
+    atlas_cell: Uniform<[f32; 2]>,
+    #[uniform(unbound)]
+    atlas_padding: Uniform<[f32; 2]>,
+}
+
+#[derive(UniformInterface)]
+struct OutlineInterface {
+    view_mat: Uniform<M44>,
+    projection_mat: Uniform<M44>,
+    color: Uniform<[f32; 3]>,
+}
+
+/// A ``Renderer`` that draws into a GLFW window.
+///
+/// It owns the window, which is also where input
+/// events come from.
+pub struct LuminanceRenderer {
+    surface: GlfwSurface,
+    back_buffer: Framebuffer<Flat, Dim2, (), ()>,
+    program: Program<Semantic, (), ShaderInterface>,
+    outline_program: Program<Semantic, (), OutlineInterface>,
+    resources: ResourceManager,
+    meshes: HashMap<MeshId, Tess>,
+    next_id: u32,
+}
+
+impl LuminanceRenderer {
+    /// Create a new ``LuminanceRenderer`` that draws
+    /// meshes with the given ``TextureLayout`` and
+    /// ``VertexFormat`` into ``surface``.
+    ///
+    /// ``resources`` must have been loaded into the
+    /// context of ``surface``.
+    pub fn new(
+        surface: GlfwSurface,
+        resources: ResourceManager,
+        layout: TextureLayout,
+        format: VertexFormat,
+    ) -> LuminanceRenderer {
+        // Shader compilation
+        let vs = match format {
+            VertexFormat::Full => VS,
+            VertexFormat::Packed => VS_PACKED,
+        };
+        let fs = match layout {
+            TextureLayout::Atlas { .. } => format!("{}{}", FS, TERRAIN_ATLAS),
+            TextureLayout::Array => format!("{}{}", FS, TERRAIN_ARRAY),
+        };
+        let (program, _) =
+            Program::<Semantic, (), ShaderInterface>::from_strings(None, vs, None, &fs)
+                .expect("program creation");
+        let (outline_program, _) = Program::<Semantic, (), OutlineInterface>::from_strings(
+            None, OUTLINE_VS, None, OUTLINE_FS,
+        )
+        .expect("outline program creation");
+
+        LuminanceRenderer {
+            back_buffer: Framebuffer::back_buffer(surface.size()),
+            surface,
+            program,
+            outline_program,
+            resources,
+            meshes: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Return the window that is drawn into.
+    pub fn surface_mut(&mut self) -> &mut GlfwSurface {
+        &mut self.surface
+    }
+
+    /// Adapt to a window that was resized to the
+    /// given size in pixels.
+    pub fn resize(&mut self, size: [u32; 2]) {
+        self.back_buffer = Framebuffer::back_buffer(size);
+    }
+}
+
+impl Renderer for LuminanceRenderer {
+    fn upload_mesh(&mut self, pre_geo: PreGeometry) -> MeshId {
+        let builder = TessBuilder::new(&mut self.surface);
+        let builder = match pre_geo.vertices {
+            Vertices::Full(vertices) => builder.add_vertices(vertices),
+            Vertices::Packed(vertices) => builder.add_vertices(vertices),
+        };
+
+        let tess = builder
+            .set_indices(pre_geo.indices)
+            .set_mode(Mode::Triangle)
+            .build()
+            .unwrap();
+
+        let id = MeshId(self.next_id);
+        self.next_id += 1;
+        self.meshes.insert(id, tess);
+
+        id
+    }
+
+    fn drop_mesh(&mut self, mesh: MeshId) {
+        self.meshes.remove(&mesh);
+    }
+
+    fn draw_frame(&mut self, frame: &Frame) {
+        let LuminanceRenderer {
+            surface,
+            back_buffer,
+            program,
+            outline_program,
+            resources,
+            meshes,
+            ..
+        } = self;
+
+        // Outlines change from frame to frame, so they are
+        // built anew each time.
+        let outlines: Vec<Tess> = frame
+            .outlines
+            .iter()
+            .map(|outline| {
+                let vertices: Vec<_> = outline
+                    .vertices
+                    .iter()
+                    .map(|pos| OutlineVertex {
+                        pos: PosAttrib::new(*pos),
+                    })
+                    .collect();
+
+                TessBuilder::new(&mut *surface)
+                    .add_vertices(vertices)
+                    .set_indices(outline.indices.clone())
+                    .set_mode(Mode::Line)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+
+        let terrain_atlas = resources.texture_mgr().terrain_atlas();
+        let terrain_tex = resources.texture_mgr().terrain();
+        let terrain_array = resources.texture_mgr().terrain_array();
+        let tile_size = terrain_atlas.tile_extent();
+
+        surface
+            .pipeline_builder()
+            .pipeline(back_buffer, BLACK, |pipe, shd_gate| {
+                let bound_terrain_tex = pipe.bind_texture(terrain_tex.inner());
+                let bound_terrain_array = pipe.bind_texture(terrain_array.inner());
+
+                shd_gate.shade(program, |rdr_gate, iface| {
+                    iface.projection_mat.update(frame.projection.0);
+                    iface.view_mat.update(frame.view.0);
+                    iface.terrain_texture.update(&bound_terrain_tex);
+                    iface.tile_size.update(tile_size);
+                    iface.terrain_array.update(&bound_terrain_array);
+                    iface.sun_direction.update(SUN_DIRECTION);
+                    iface.sun_strength.update(SUN_STRENGTH);
+                    iface.ao_min.update(AO_MIN);
+                    iface.light_falloff.update(LIGHT_FALLOFF);
+                    iface.atlas_columns.update(terrain_atlas.columns());
+                    iface.atlas_cell.update(terrain_atlas.cell_extent());
+                    iface.atlas_padding.update(terrain_atlas.padding_extent());
+
+                    for draw in &frame.draws {
+                        let tess = match meshes.get(&draw.mesh) {
+                            Some(tess) => tess,
+                            None => continue,
+                        };
+
+                        iface.model_mat.update(draw.model.0);
+
+                        let state = RenderState::default().set_face_culling(FaceCulling::default());
+
+                        rdr_gate.render(state, |tess_gate| {
+                            tess_gate.render(&mut *surface, tess.into());
+                        });
+                    }
+                });
+
+                shd_gate.shade(outline_program, |rdr_gate, iface| {
+                    iface.projection_mat.update(frame.projection.0);
+                    iface.view_mat.update(frame.view.0);
+                    iface.color.update(OUTLINE_COLOR);
+
+                    for tess in &outlines {
+                        rdr_gate.render(RenderState::default(), |tess_gate| {
+                            tess_gate.render(&mut *surface, tess.into());
+                        });
+                    }
+                });
+            });
+
+        // Show the backbuffer
+        surface.swap_buffers();
+    }
+}
@@ -1,8 +1,12 @@
 //! The core game engine is in this crate.
 
+pub mod biome;
 pub mod block;
+pub mod cli;
 pub mod entity;
+pub mod game;
 pub mod maths;
+pub mod render;
 pub mod resource;
 pub mod side;
 pub mod util;
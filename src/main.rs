@@ -1,42 +1,17 @@
-use glfw::{Action, CursorMode, Key, WindowEvent};
-use luminance::{
-    context::GraphicsContext,
-    face_culling::FaceCulling,
-    framebuffer::Framebuffer,
-    linear::M44,
-    pipeline::BoundTexture,
-    pixel::Floating,
-    render_state::RenderState,
-    shader::program::{Program, Uniform},
-    texture::{Dim2, Flat},
-};
-use luminance_derive::UniformInterface;
+mod gpu;
+
+use glfw::{Action, CursorMode, Key, MouseButton, WindowEvent};
+use gpu::LuminanceRenderer;
 use luminance_glfw_custom::surface::{GlfwSurface, Surface, WindowDim, WindowOpt};
 use sandbox::{
-    entity::{camera::Camera, player::Player, sector::SectorManager},
-    maths::{
-        matrix::{Projection, Transform},
-        vector::{MathVec, Vec2f, Vec3, Vec4, Vec4f},
-    },
-    resource::ResourceManager,
+    block::Block,
+    entity::sector::{SectorManager, TextureLayout, VertexFormat},
+    game::{Game, Input},
+    maths::vector::{MathVec, Vec2f, Vec3, Vec3f, Vec4, Vec4f},
+    resource::{ResourceManager, TextureManager},
     timing::Clock,
-    vertexattrib::Semantic,
 };
-use std::f32::consts::PI;
-
-const VS: &'static str = include_str!("vs.glsl");
-const FS: &'static str = include_str!("fs.glsl");
-
-const BLACK: [f32; 4] = [0., 0., 0., 0.];
-
-#[derive(UniformInterface)]
-struct ShaderInterface {
-    //time: Uniform<f32>,
-    model_mat: Uniform<M44>,
-    view_mat: Uniform<M44>,
-    projection_mat: Uniform<M44>,
-    terrain_texture: Uniform<&'static BoundTexture<'static, Flat, Dim2, Floating>>,
-}
+use std::{env, process};
 
 fn main() {
     // Informal tests of math
@@ -80,27 +55,40 @@ fn main() {
 
     // Resource loading
     let res_mgr = ResourceManager::load_all(&mut surface);
-    let terrain_tex = res_mgr.texture_mgr().terrain();
-
-    // Shader compilation
-    let (program, _) = Program::<Semantic, (), ShaderInterface>::from_strings(None, VS, None, FS)
-        .expect("program creation");
-
-    // Create a ``Player`
-    let mut player = Player::at_origin();
-
-    // Camera and projection matrix
-    let mut cam = Camera::new();
-    let mut proj_mat = make_proj(&surface).to_matrix();
+    let terrain_atlas = res_mgr.texture_mgr().terrain_atlas();
+
+    // Sample block textures from a texture array instead
+    // of the atlas if requested.
+    let layout = if env::args().any(|arg| arg == "--texture-array") {
+        TextureLayout::Array
+    } else {
+        terrain_atlas.layout()
+    };
+
+    // Store vertices in the packed format if requested.
+    let format = if env::args().any(|arg| arg == "--packed-vertices") {
+        VertexFormat::Packed
+    } else {
+        VertexFormat::Full
+    };
+
+    if let Err(e) = format.check_tile_count(terrain_atlas.tile_count()) {
+        eprintln!("cannot use the vertex format: {}", e);
+        process::exit(1);
+    }
 
     // Create a ``SectorManager``.
-    let mut sector_mgr = SectorManager::new(terrain_tex.info());
+    let sector_mgr = SectorManager::new(
+        TextureManager::terrain_textures(&terrain_atlas, layout),
+        format,
+    );
 
-    // Framebuffer
-    let mut back_buffer = Framebuffer::back_buffer(surface.size());
+    // The renderer takes over the window
+    let size = surface.size();
+    let renderer = LuminanceRenderer::new(surface, res_mgr, layout, format);
+    let mut game = Game::new(renderer, sector_mgr, size);
 
-    // Track frame time and window resize
-    let mut resized = true;
+    // Track frame time
     let mut clock = Clock::begin();
     'game: loop {
         // Handle timing
@@ -108,134 +96,128 @@ fn main() {
 
         //std::thread::sleep(Duration::from_millis(200));
 
-        // Load pending sectors
-        sector_mgr.finalize_sectors(&mut surface);
+        // Clicks only break or place one block each
+        let mut break_block = false;
+        let mut place_block = false;
 
         // Poll events
-        for event in surface.poll_events() {
+        let events: Vec<_> = game.renderer_mut().surface_mut().poll_events().collect();
+        for event in events {
             match event {
                 WindowEvent::Close | WindowEvent::Key(Key::Escape, _, Action::Release, _) => {
                     break 'game
                 }
 
                 WindowEvent::Key(Key::P, _, Action::Release, _) => {
+                    let culling = game.cull_stats();
+
                     println!("{}\t{}", 1. / dt, dt);
+                    println!(
+                        "{} sectors drawn, {} culled, {} occluded",
+                        culling.drawn, culling.culled, culling.occluded
+                    );
+                }
+
+                WindowEvent::MouseButton(MouseButton::Button1, Action::Press, _) => {
+                    break_block = true;
+                }
+
+                WindowEvent::MouseButton(MouseButton::Button2, Action::Press, _) => {
+                    place_block = true;
+                }
+
+                WindowEvent::Key(key, _, Action::Press, _) => {
+                    if let Some(blk) = hotbar_block(key) {
+                        game.select_block(blk);
+                    }
                 }
 
                 WindowEvent::FramebufferSize(width, height) => {
                     println!("resize!");
-                    back_buffer = Framebuffer::back_buffer([width as u32, height as u32]);
-                    resized = true;
+                    let size = [width as u32, height as u32];
+                    game.renderer_mut().resize(size);
+                    game.resize(size);
                 }
 
                 _ => {}
             }
         }
 
-        // Handle resize
-        if resized {
-            println!("make proj matrix!");
-            proj_mat = make_proj(&surface).to_matrix();
-        }
-
-        let move_speed = 4.0 * dt as f32;
+        let mut input = read_input(game.renderer_mut().surface_mut());
+        input.break_block = break_block;
+        input.place_block = place_block;
+        game.update(dt, &input);
 
-        // Movement
-        if surface.lib_handle().get_key(Key::D) == Action::Press {
-            player.move_x(move_speed);
-        } else if surface.lib_handle().get_key(Key::A) == Action::Press {
-            player.move_x(-move_speed);
-        }
-
-        if surface.lib_handle().get_key(Key::Space) == Action::Press {
-            player.slide((0., move_speed, 0.));
-        } else if surface.lib_handle().get_key(Key::LeftShift) == Action::Press {
-            player.slide((0., -move_speed, 0.));
-        }
+        // Render frame
+        game.draw();
+    }
+}
 
-        if surface.lib_handle().get_key(Key::S) == Action::Press {
-            //player.slide((0., 0., move_speed));
-            player.move_z(move_speed);
-        } else if surface.lib_handle().get_key(Key::W) == Action::Press {
-            //player.slide((0., 0., -move_speed));
-            player.move_z(-move_speed);
-        }
+// Read the state of the controls from the keyboard
+// and the mouse.
+fn read_input(surface: &mut GlfwSurface) -> Input {
+    let mut input = Input::new();
+
+    {
+        let window = surface.lib_handle();
+        let pressed = |key| window.get_key(key) == Action::Press;
+        let axis = |positive, negative| {
+            if pressed(positive) {
+                1.
+            } else if pressed(negative) {
+                -1.
+            } else {
+                0.
+            }
+        };
 
-        let rot_speed = 0.012;
+        // Movement
+        input.movement = Vec3f::new(
+            axis(Key::D, Key::A),
+            axis(Key::Space, Key::LeftShift),
+            axis(Key::S, Key::W),
+        );
 
         // Pan / pitch with arrow keys
-
-        if surface.lib_handle().get_key(Key::Left) == Action::Press {
-            player.spin((0., rot_speed));
-        } else if surface.lib_handle().get_key(Key::Right) == Action::Press {
-            player.spin((0., -rot_speed));
-        }
-
-        if surface.lib_handle().get_key(Key::Up) == Action::Press {
-            player.spin((rot_speed, 0.));
-        } else if surface.lib_handle().get_key(Key::Down) == Action::Press {
-            player.spin((-rot_speed, 0.));
-        }
-
-        // Pan / pitch with mouse
-
-        let mouse_speed: f64 = 0.002;
-
-        //println!("{:?}", surface.lib_handle().get_cursor_pos());
-
-        let mouse_delta = surface.lib_handle().get_cursor_pos();
-        surface.lib_handle_mut().set_cursor_pos(0., 0.);
-
-        // swap x and y
-        let cam_delta = (
-            (-mouse_delta.1 * mouse_speed) as f32,
-            (-mouse_delta.0 * mouse_speed) as f32,
+        let rot_speed = 0.012;
+        input.look = Vec2f::new(
+            axis(Key::Up, Key::Down) * rot_speed,
+            axis(Key::Left, Key::Right) * rot_speed,
         );
-        player.spin(cam_delta);
-
-        // Update camera
-        cam.snap_to(&player);
-
-        // Render frame
-        surface
-            .pipeline_builder()
-            .pipeline(&back_buffer, BLACK, |pipe, shd_gate| {
-                let bound_terrain_tex = pipe.bind_texture(terrain_tex.inner());
-
-                shd_gate.shade(&program, |rdr_gate, iface| {
-                    if resized {
-                        println!("load proj matrix!");
-                        iface.projection_mat.update(proj_mat.0);
-                    }
-
-                    iface.view_mat.update(cam.to_matrix().0);
-                    iface.terrain_texture.update(&bound_terrain_tex);
+    }
 
-                    for (_, sector) in &sector_mgr {
-                        iface.model_mat.update(sector.translation().0);
+    // Pan / pitch with mouse
 
-                        let state = RenderState::default().set_face_culling(FaceCulling::default());
+    let mouse_speed: f64 = 0.002;
 
-                        if let Some(geometry) = sector.geometry() {
-                            rdr_gate.render(state, |tess_gate| {
-                                tess_gate.render(&mut surface, geometry.into());
-                            });
-                        }
-                    }
-                });
-            });
+    let mouse_delta = surface.lib_handle().get_cursor_pos();
+    surface.lib_handle_mut().set_cursor_pos(0., 0.);
 
-        // Show the backbuffer
-        surface.swap_buffers();
+    // swap x and y
+    input.look += Vec2f::new(
+        (-mouse_delta.1 * mouse_speed) as f32,
+        (-mouse_delta.0 * mouse_speed) as f32,
+    );
 
-        // Reset resize flag
-        resized = false;
-    }
+    input
 }
 
-fn make_proj(surface: &impl Surface) -> Projection {
-    let [w, h] = surface.size();
-    let (w, h) = (w as f32, h as f32);
-
-    Projection::new(40.0 * PI / 180.0, w / h, 0.1, 1000.0)
+// Return the block that the number key ``key`` selects,
+// if any. The keys from one upward select every block
+// but air, in the order of their IDs.
+fn hotbar_block(key: Key) -> Option<Block> {
+    let keys = [
+        Key::Num1,
+        Key::Num2,
+        Key::Num3,
+        Key::Num4,
+        Key::Num5,
+        Key::Num6,
+        Key::Num7,
+        Key::Num8,
+        Key::Num9,
+    ];
+
+    let n = keys.iter().position(|k| *k == key)?;
+    Block::ALL.get(n + 1).cloned()
 }
@@ -1,5 +1,7 @@
 //! This module implements mathematical tools, objects, and abstractions
 //! that are useful for the simulation.
 
+pub mod aabb;
+pub mod frustum;
 pub mod matrix;
 pub mod vector;
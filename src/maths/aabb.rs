@@ -0,0 +1,44 @@
+//! This module provides axis-aligned bounding boxes, which
+//! describe the space that blocks and players take up.
+
+use super::vector::Vec3f;
+
+/// A box whose edges are parallel to the axes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    /// The corner with the lowest coordinates.
+    pub min: Vec3f,
+
+    /// The corner with the highest coordinates.
+    pub max: Vec3f,
+}
+
+impl Aabb {
+    /// Create a new ``Aabb`` between the given corners.
+    pub fn new(min: impl Into<Vec3f>, max: impl Into<Vec3f>) -> Aabb {
+        Aabb {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+
+    /// Return this box moved by ``offset``.
+    pub fn translate(self, offset: impl Into<Vec3f>) -> Aabb {
+        let offset = offset.into();
+
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Return this box grown by ``margin`` on every side.
+    pub fn inflate(self, margin: f32) -> Aabb {
+        let margin = Vec3f::new(margin, margin, margin);
+
+        Aabb {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+}
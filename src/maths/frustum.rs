@@ -0,0 +1,163 @@
+//! This module provides view frustums, which decide whether an
+//! object could possibly be seen by a camera.
+//!
+//! A frustum is bounded by six planes, which are read directly
+//! off the rows of the combined projection and view matrix, as
+//! described by Gribb and Hartmann in "Fast Extraction of
+//! Viewing Frustum Planes from the World-View-Projection Matrix".
+
+use super::{matrix::Mat4x4, vector::Vec3f};
+
+/// The volume of space that a camera can see.
+///
+/// Each of the six planes is stored as ``[a, b, c, d]``, so
+/// that a point ``(x, y, z)`` lies on the inner side of the
+/// plane if ``a * x + b * y + c * z + d`` is not negative.
+/// The planes are not normalized, as only the sign matters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    planes: [[f32; 4]; 6],
+}
+
+impl Frustum {
+    /// Extract the frustum from the combined projection and
+    /// view matrix, ``&projection * &view``.
+    ///
+    /// The frustum is then given in the coordinates that the
+    /// view matrix transforms from, which is world space for
+    /// the matrix of a ``Camera``.
+    pub fn from_matrix(matrix: &Mat4x4) -> Frustum {
+        // The matrix is stored column by column.
+        let row = |r: usize| {
+            [
+                matrix.0[0][r],
+                matrix.0[1][r],
+                matrix.0[2][r],
+                matrix.0[3][r],
+            ]
+        };
+
+        // A point is inside of the frustum if each of its
+        // clip coordinates lies between -w and w.
+        let w = row(3);
+        let plane = |r: usize, sign: f32| {
+            let row = row(r);
+            [
+                w[0] + sign * row[0],
+                w[1] + sign * row[1],
+                w[2] + sign * row[2],
+                w[3] + sign * row[3],
+            ]
+        };
+
+        Frustum {
+            planes: [
+                plane(0, 1.),  // left
+                plane(0, -1.), // right
+                plane(1, 1.),  // bottom
+                plane(1, -1.), // top
+                plane(2, 1.),  // near
+                plane(2, -1.), // far
+            ],
+        }
+    }
+
+    /// Returns ``true`` if ``point`` lies inside of the frustum.
+    pub fn contains_point(&self, point: Vec3f) -> bool {
+        self.planes.iter().all(|p| distance(p, point) >= 0.)
+    }
+
+    /// Returns ``true`` if the axis-aligned box between ``min``
+    /// and ``max`` lies at least partly inside of the frustum.
+    ///
+    /// The test is conservative: a large box just outside of
+    /// an edge or corner of the frustum may still be reported
+    /// as visible, but a box that is visible never is culled.
+    pub fn intersects_box(&self, min: Vec3f, max: Vec3f) -> bool {
+        self.planes.iter().all(|p| {
+            // The box is outside of the plane if even its
+            // corner furthest along the plane's normal is.
+            let corner = Vec3f::new(
+                if p[0] >= 0. { max.x } else { min.x },
+                if p[1] >= 0. { max.y } else { min.y },
+                if p[2] >= 0. { max.z } else { min.z },
+            );
+
+            distance(p, corner) >= 0.
+        })
+    }
+}
+
+// Returns the signed distance of ``point`` from ``plane``,
+// scaled by the length of the plane's normal.
+fn distance(plane: &[f32; 4], point: Vec3f) -> f32 {
+    plane[0] * point.x + plane[1] * point.y + plane[2] * point.z + plane[3]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maths::matrix::{Projection, Transform};
+    use std::f32::consts::FRAC_PI_2;
+
+    // The frustum of a camera at the origin that looks along
+    // negative Z, and sees a quarter turn in each direction,
+    // so that its side planes are ``|x| = -z`` and ``|y| = -z``.
+    fn frustum() -> Frustum {
+        Frustum::from_matrix(&Projection::new(FRAC_PI_2, 1., 1., 100.).to_matrix())
+    }
+
+    fn intersects(min: (f32, f32, f32), max: (f32, f32, f32)) -> bool {
+        frustum().intersects_box(min.into(), max.into())
+    }
+
+    #[test]
+    fn points_inside_of_every_plane_are_contained() {
+        let frustum = frustum();
+
+        assert!(frustum.contains_point(Vec3f::new(0., 0., -10.)));
+        assert!(frustum.contains_point(Vec3f::new(9., -9., -10.)));
+
+        assert!(!frustum.contains_point(Vec3f::new(0., 0., 10.)));
+        assert!(!frustum.contains_point(Vec3f::new(0., 0., -0.5)));
+        assert!(!frustum.contains_point(Vec3f::new(0., 0., -101.)));
+        assert!(!frustum.contains_point(Vec3f::new(11., 0., -10.)));
+        assert!(!frustum.contains_point(Vec3f::new(0., -11., -10.)));
+    }
+
+    #[test]
+    fn boxes_inside_of_the_frustum_intersect_it() {
+        assert!(intersects((-1., -1., -11.), (1., 1., -9.)));
+
+        // A box around the whole frustum.
+        assert!(intersects((-200., -200., -200.), (200., 200., 200.)));
+    }
+
+    #[test]
+    fn boxes_outside_of_any_plane_are_culled() {
+        // Left, right, bottom and top.
+        assert!(!intersects((-30., -1., -11.), (-20., 1., -9.)));
+        assert!(!intersects((20., -1., -11.), (30., 1., -9.)));
+        assert!(!intersects((-1., -30., -11.), (1., -20., -9.)));
+        assert!(!intersects((-1., 20., -11.), (1., 30., -9.)));
+
+        // Behind the camera, closer than the near plane, and
+        // beyond the far plane.
+        assert!(!intersects((-1., -1., 1.), (1., 1., 2.)));
+        assert!(!intersects((-0.1, -0.1, -0.9), (0.1, 0.1, -0.5)));
+        assert!(!intersects((-1., -1., -120.), (1., 1., -110.)));
+    }
+
+    #[test]
+    fn boxes_that_straddle_a_plane_intersect_it() {
+        // Left, right, bottom and top.
+        assert!(intersects((-12., -1., -11.), (-8., 1., -9.)));
+        assert!(intersects((8., -1., -11.), (12., 1., -9.)));
+        assert!(intersects((-1., -12., -11.), (1., -8., -9.)));
+        assert!(intersects((-1., 8., -11.), (1., 12., -9.)));
+
+        // Near and far.
+        assert!(intersects((-0.1, -0.1, -2.), (0.1, 0.1, 0.5)));
+        assert!(intersects((-1., -1., -110.), (1., 1., -90.)));
+    }
+}
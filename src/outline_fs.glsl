@@ -0,0 +1,8 @@
+out vec4 frag_color;
+
+// The color of every line.
+uniform vec3 color;
+
+void main(void) {
+    frag_color = vec4(color, 1.0);
+}
@@ -0,0 +1,9 @@
+layout (location = 0) in vec3 pos;
+
+uniform mat4 view_mat;
+uniform mat4 projection_mat;
+
+void main(void) {
+    // Outlines are given in world coordinates.
+    gl_Position = projection_mat * view_mat * vec4(pos, 1.0);
+}
@@ -0,0 +1,94 @@
+//! Separates drawing the world from the graphics API.
+//!
+//! The game hands its meshes to a ``Renderer`` and describes
+//! each frame as a list of draw calls, without knowing how
+//! they are carried out. The game itself renders with OpenGL,
+//! which needs a window, while the ``RecordingRenderer`` only
+//! remembers what it was asked to draw, so that the game can
+//! run in tests.
+//!
+//! Apart from meshes, a frame may hold ``Outline``s, which are
+//! drawn as lines over the world to highlight blocks.
+//!
+//! This module also provides a small software rasterizer that
+//! shades meshes the way ``fs.glsl`` does, so that pictures
+//! of the world can be taken on machines without a GPU and
+//! compared against earlier ones.
+
+mod outline;
+mod raster;
+mod recording;
+
+pub use outline::Outline;
+pub use raster::{Image, Rasterizer};
+pub use recording::RecordingRenderer;
+
+use crate::{entity::sector::PreGeometry, maths::matrix::Mat4x4};
+
+/// The direction towards the sun. Faces are shaded by
+/// how directly they face it.
+pub const SUN_DIRECTION: [f32; 3] = [0.4, 1.0, 0.6];
+
+/// How much darker faces turned away from the sun are,
+/// from zero (no shading) to one (black).
+pub const SUN_STRENGTH: f32 = 0.4;
+
+/// The brightness of a fully occluded corner, from zero
+/// (black) to one (no occlusion).
+pub const AO_MIN: f32 = 0.45;
+
+/// Each light level is this much brighter than the one
+/// below it.
+pub const LIGHT_FALLOFF: f32 = 0.8;
+
+/// The color of the lines of an ``Outline``.
+pub const OUTLINE_COLOR: [f32; 3] = [0.0, 0.0, 0.0];
+
+/// Identifies a mesh that was uploaded to a ``Renderer``.
+///
+/// Each ``Renderer`` hands out its own IDs, so they
+/// mean nothing to any other.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct MeshId(pub u32);
+
+/// A request to draw a mesh.
+#[derive(Clone, Debug)]
+pub struct Draw {
+    /// The mesh to draw.
+    pub mesh: MeshId,
+
+    /// The transformation that places the mesh in the world.
+    pub model: Mat4x4,
+}
+
+/// Everything that is drawn in one frame.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// The matrix of the ``Camera``.
+    pub view: Mat4x4,
+
+    /// The matrix of the ``Projection``.
+    pub projection: Mat4x4,
+
+    /// The meshes to draw, in order.
+    pub draws: Vec<Draw>,
+
+    /// The outlines to draw after the meshes. Only the
+    /// parts of them that no mesh hides are visible.
+    pub outlines: Vec<Outline>,
+}
+
+/// Draws meshes with some graphics API.
+pub trait Renderer {
+    /// Store a mesh for drawing, and return its ID.
+    fn upload_mesh(&mut self, pre_geo: PreGeometry) -> MeshId;
+
+    /// Free a mesh. Its ID must not be used again.
+    fn drop_mesh(&mut self, mesh: MeshId);
+
+    /// Draw a frame and present it.
+    ///
+    /// Draws of meshes that are unknown to the renderer
+    /// are skipped.
+    fn draw_frame(&mut self, frame: &Frame);
+}
@@ -0,0 +1,179 @@
+//! Generates the outlines that highlight blocks.
+//!
+//! An outline is made of the line segments along the edges of
+//! the shape of a block. It is grown slightly beyond the block,
+//! so that its edges lie in front of the block's faces, and of
+//! the faces of the blocks around it, rather than fighting them
+//! for depth.
+
+use crate::{
+    block::Block,
+    entity::sector::WorldCoords,
+    maths::{aabb::Aabb, vector::Vec3f},
+};
+
+// How far an outline lies outside of the shape of its block.
+const MARGIN: f32 = 0.002;
+
+// The twelve edges of a box, as pairs of its corners. Bit
+// zero of a corner is set if it lies at the maximum along
+// X, bit one along Y and bit two along Z.
+#[rustfmt::skip]
+const EDGES: [u32; 24] = [
+    0, 1, 2, 3, 4, 5, 6, 7,
+    0, 2, 1, 3, 4, 6, 5, 7,
+    0, 4, 1, 5, 2, 6, 3, 7,
+];
+
+/// Line segments in world coordinates.
+///
+/// Every two ``indices`` refer to the ``vertices`` at both
+/// ends of one line segment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Outline {
+    pub vertices: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl Outline {
+    /// Create the ``Outline`` of the twelve edges of a box.
+    pub fn of_box(bounds: Aabb) -> Outline {
+        let Aabb { min, max } = bounds;
+        let pick = |corner: u32, bit: u32, min: f32, max: f32| {
+            if corner & bit == 0 {
+                min
+            } else {
+                max
+            }
+        };
+
+        let vertices = (0..8)
+            .map(|c| {
+                [
+                    pick(c, 1, min.x, max.x),
+                    pick(c, 2, min.y, max.y),
+                    pick(c, 4, min.z, max.z),
+                ]
+            })
+            .collect();
+
+        Outline {
+            vertices,
+            indices: EDGES.to_vec(),
+        }
+    }
+
+    /// Create the ``Outline`` of the shape of ``blk`` at
+    /// ``pos``, or return ``None`` if the block has no shape.
+    pub fn of_block(blk: Block, pos: WorldCoords) -> Option<Outline> {
+        let WorldCoords(x, y, z) = pos;
+        let shape = blk.shape()?;
+
+        Some(Self::of_box(
+            shape
+                .translate(Vec3f::new(x as f32, y as f32, z as f32))
+                .inflate(MARGIN),
+        ))
+    }
+
+    /// Iterate over the ends of every line segment.
+    pub fn lines(&self) -> impl Iterator<Item = ([f32; 3], [f32; 3])> + '_ {
+        self.indices.chunks(2).map(move |line| {
+            (
+                self.vertices[line[0] as usize],
+                self.vertices[line[1] as usize],
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Return the lowest and highest corners of the outline.
+    fn bounds(outline: &Outline) -> ([f32; 3], [f32; 3]) {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+
+        for v in &outline.vertices {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(v[axis]);
+                max[axis] = max[axis].max(v[axis]);
+            }
+        }
+
+        (min, max)
+    }
+
+    fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+        for axis in 0..3 {
+            assert!(
+                (actual[axis] - expected[axis]).abs() < 1e-5,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn boxes_have_twelve_distinct_edges() {
+        let outline = Outline::of_box(Aabb::new((1., 2., 3.), (2., 4., 6.)));
+        let mut edges = Vec::new();
+        let mut per_axis = [0; 3];
+
+        for (a, b) in outline.lines() {
+            let changed: Vec<_> = (0..3).filter(|&axis| a[axis] != b[axis]).collect();
+            assert_eq!(changed.len(), 1, "{:?} to {:?} is not along an axis", a, b);
+
+            // Each edge spans the whole box along its axis.
+            let axis = changed[0];
+            assert_eq!((a[axis] - b[axis]).abs(), [1., 2., 3.][axis]);
+            per_axis[axis] += 1;
+
+            let edge = if a < b { (a, b) } else { (b, a) };
+            assert!(!edges.contains(&edge), "{:?} appears twice", edge);
+            edges.push(edge);
+        }
+
+        assert_eq!(outline.vertices.len(), 8);
+        assert_eq!(edges.len(), 12);
+        assert_eq!(per_axis, [4, 4, 4]);
+    }
+
+    #[test]
+    fn blocks_are_outlined_at_their_position_with_a_margin() {
+        let outline = Outline::of_block(Block::Stone, WorldCoords(3, -2, -7)).unwrap();
+        let (min, max) = bounds(&outline);
+
+        assert_near(min, [3. - MARGIN, -2. - MARGIN, -7. - MARGIN]);
+        assert_near(max, [4. + MARGIN, -1. + MARGIN, -6. + MARGIN]);
+        assert_eq!(outline.lines().count(), 12);
+    }
+
+    #[test]
+    fn outlines_follow_the_shape_of_the_block() {
+        let pos = WorldCoords(-1, 4, 2);
+
+        let (min, max) = bounds(&Outline::of_block(Block::Slab, pos).unwrap());
+        assert_near(min, [-1. - MARGIN, 4. - MARGIN, 2. - MARGIN]);
+        assert_near(max, [MARGIN, 4.5 + MARGIN, 3. + MARGIN]);
+
+        let (min, max) = bounds(&Outline::of_block(Block::Torch, pos).unwrap());
+        assert_near(
+            min,
+            [-1. + 7. / 16. - MARGIN, 4. - MARGIN, 2. + 7. / 16. - MARGIN],
+        );
+        assert_near(
+            max,
+            [
+                -1. + 9. / 16. + MARGIN,
+                4. + 10. / 16. + MARGIN,
+                2. + 9. / 16. + MARGIN,
+            ],
+        );
+
+        assert_eq!(Outline::of_block(Block::Air, pos), None);
+    }
+}
@@ -0,0 +1,619 @@
+//! Implements a software rasterizer for sector meshes.
+//!
+//! Triangles are transformed by the same matrices as in the
+//! vertex shader, clipped against the near plane and filled
+//! pixel by pixel, keeping the fragment that is closest to the
+//! camera. Like with the game's render state, triangles that
+//! face away from the camera are culled. The attributes are
+//! interpolated with perspective correction, and every pixel
+//! is textured and lit as in ``fs.glsl`` and
+//! ``terrain_atlas.glsl``.
+//!
+//! ``Outline``s are drawn as lines that are one pixel wide,
+//! and are hidden by the meshes in front of them, but do not
+//! hide anything themselves.
+//!
+//! Textures are sampled from the nearest texel and pixels are
+//! only sampled at their centers, so images differ slightly
+//! from what a GPU draws. They are exactly reproducible,
+//! however, which is what comparisons against earlier images
+//! need.
+
+use super::{Outline, AO_MIN, LIGHT_FALLOFF, OUTLINE_COLOR, SUN_DIRECTION, SUN_STRENGTH};
+use crate::{
+    entity::sector::{unpack_vertex, PreGeometry, Vertices, MAX_LIGHT},
+    maths::matrix::Mat4x4,
+    resource::{self, TextureAtlas, RGB},
+    vertexattrib::VoxelVertex,
+};
+use std::io::{self, Read, Write};
+
+// The number of attributes that are interpolated across a
+// triangle: the texture coordinate, the ambient occlusion,
+// the sky and block light and the tint.
+const ATTRIBS: usize = 8;
+
+/// An image with 8-bit RGB pixels, stored row by row
+/// from the top.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Create a new black ``Image`` of the given size.
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![0; (width * height) as usize * RGB],
+        }
+    }
+
+    /// Decode a PNG image.
+    ///
+    /// An error is returned if the image is not 8-bit
+    /// RGB or RGBA. Alpha is discarded.
+    pub fn read_png(r: impl Read) -> io::Result<Image> {
+        let (width, height, pixels) = resource::read_png(r)?;
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Return the width of the image in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Return the height of the image in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Return the RGB pixels of the image.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Return the color of the pixel in column ``x``
+    /// and row ``y``.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = (x + y * self.width) as usize * RGB;
+
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    /// Count the pixels in which any channel differs from
+    /// ``other`` by more than ``tolerance``.
+    ///
+    /// Images of different sizes differ in every pixel.
+    pub fn differences(&self, other: &Image, tolerance: u8) -> usize {
+        if (self.width, self.height) != (other.width, other.height) {
+            return self.pixels.len().max(other.pixels.len()) / RGB;
+        }
+
+        self.pixels
+            .chunks(RGB)
+            .zip(other.pixels.chunks(RGB))
+            .filter(|(a, b)| {
+                a.iter()
+                    .zip(b.iter())
+                    .any(|(a, b)| (*a as i16 - *b as i16).abs() > tolerance as i16)
+            })
+            .count()
+    }
+
+    /// Encode the image as a PNG image.
+    pub fn write_png(&self, w: impl Write) -> io::Result<()> {
+        resource::write_png(w, self.width, self.height, &self.pixels)
+    }
+
+    // Store a color with channels from zero to one.
+    fn set(&mut self, x: u32, y: u32, color: [f32; 3]) {
+        let i = (x + y * self.width) as usize * RGB;
+
+        for (pixel, c) in self.pixels[i..i + RGB].iter_mut().zip(&color) {
+            *pixel = (c.clamp(0., 1.) * 255.).round() as u8;
+        }
+    }
+}
+
+/// Draws meshes into an ``Image``, hiding the parts that
+/// lie behind others.
+///
+/// The meshes must have been generated with the
+/// ``TextureLayout`` of the atlas that they are drawn with.
+pub struct Rasterizer<'a> {
+    atlas: &'a TextureAtlas,
+    view_projection: Mat4x4,
+    image: Image,
+
+    // The depth of the closest fragment of each pixel
+    // so far, in normalized device coordinates.
+    depth: Vec<f32>,
+}
+
+impl<'a> Rasterizer<'a> {
+    /// Create a new ``Rasterizer`` that draws into a black
+    /// image of the given size.
+    ///
+    /// ``projection`` and ``view`` are the matrices of a
+    /// ``Projection`` and a ``Camera``.
+    pub fn new(
+        atlas: &'a TextureAtlas,
+        width: u32,
+        height: u32,
+        projection: &Mat4x4,
+        view: &Mat4x4,
+    ) -> Rasterizer<'a> {
+        Rasterizer {
+            atlas,
+            view_projection: projection * view,
+            image: Image::new(width, height),
+            depth: vec![f32::INFINITY; (width * height) as usize],
+        }
+    }
+
+    /// Draw a mesh that is placed in the world by ``model``,
+    /// such as the translation of a ``Sector``.
+    pub fn draw(&mut self, model: &Mat4x4, pre_geo: &PreGeometry) {
+        let unpacked: Vec<VoxelVertex>;
+        let vertices = match &pre_geo.vertices {
+            Vertices::Full(vertices) => vertices,
+            Vertices::Packed(vertices) => {
+                let layout = self.atlas.layout();
+                unpacked = vertices.iter().map(|v| unpack_vertex(*v, layout)).collect();
+                &unpacked
+            }
+        };
+
+        let mvp = &self.view_projection * model;
+
+        for triangle in pre_geo.indices.chunks(3) {
+            let corners: Vec<_> = triangle
+                .iter()
+                .map(|i| clip_vertex(&mvp, &vertices[*i as usize]))
+                .collect();
+
+            // The tile and the normal are the same across
+            // each quad.
+            let first = &vertices[triangle[0] as usize];
+            let normal = transform_normal(model, *first.normal);
+            let tile = *first.tile;
+
+            let polygon: Vec<_> = clip_near(&corners)
+                .iter()
+                .map(|v| self.to_screen(v))
+                .collect();
+
+            for i in 1..polygon.len().saturating_sub(1) {
+                self.fill(tile, normal, [&polygon[0], &polygon[i], &polygon[i + 1]]);
+            }
+        }
+    }
+
+    /// Draw the lines of an ``Outline`` over the meshes
+    /// drawn so far.
+    pub fn draw_outline(&mut self, outline: &Outline) {
+        for (a, b) in outline.lines() {
+            let ends = [
+                clip_point(&self.view_projection, a),
+                clip_point(&self.view_projection, b),
+            ];
+
+            if let Some([a, b]) = clip_line_near(ends) {
+                let (a, b) = (self.to_screen(&a), self.to_screen(&b));
+                self.line(&a, &b);
+            }
+        }
+    }
+
+    /// Return the image drawn so far.
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Finish drawing and return the image.
+    pub fn into_image(self) -> Image {
+        self.image
+    }
+
+    // Project a clipped vertex onto the image. Rows run
+    // from the top of the image down.
+    fn to_screen(&self, v: &ClipVertex) -> ScreenVertex {
+        let [x, y, z, w] = v.pos;
+        let inv_w = 1. / w;
+
+        let mut attribs = v.attribs;
+        for a in attribs.iter_mut() {
+            *a *= inv_w;
+        }
+
+        ScreenVertex {
+            x: (x * inv_w * 0.5 + 0.5) * self.image.width as f32,
+            y: (0.5 - y * inv_w * 0.5) * self.image.height as f32,
+            z: z * inv_w,
+            inv_w,
+            attribs,
+        }
+    }
+
+    // Fill the pixels whose centers lie inside of a triangle.
+    fn fill(&mut self, tile: [f32; 2], normal: [f32; 3], [a, b, c]: [&ScreenVertex; 3]) {
+        // Front faces wind counterclockwise, which is clockwise
+        // on the image as its rows run downwards.
+        let area = edge(a, b, c.x, c.y);
+        if area >= 0. {
+            return;
+        }
+
+        let (width, height) = (self.image.width, self.image.height);
+        let min_x = a.x.min(b.x).min(c.x).floor().max(0.) as u32;
+        let min_y = a.y.min(b.y).min(c.y).floor().max(0.) as u32;
+        let max_x = (a.x.max(b.x).max(c.x).ceil().max(0.) as u32).min(width);
+        let max_y = (a.y.max(b.y).max(c.y).ceil().max(0.) as u32).min(height);
+
+        let shade = sun_shade(normal);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge(b, c, px, py) / area;
+                let wb = edge(c, a, px, py) / area;
+                let wc = edge(a, b, px, py) / area;
+
+                if wa < 0. || wb < 0. || wc < 0. {
+                    continue;
+                }
+
+                // Depth is linear on the screen, unlike the
+                // attributes, which are divided by w.
+                let z = wa * a.z + wb * b.z + wc * c.z;
+                let i = (x + y * width) as usize;
+
+                if !(-1. ..=1.).contains(&z) || z >= self.depth[i] {
+                    continue;
+                }
+
+                let inv_w = wa * a.inv_w + wb * b.inv_w + wc * c.inv_w;
+                let mut attribs = [0.; ATTRIBS];
+                for (k, attrib) in attribs.iter_mut().enumerate() {
+                    *attrib = (wa * a.attribs[k] + wb * b.attribs[k] + wc * c.attribs[k]) / inv_w;
+                }
+
+                self.depth[i] = z;
+                let color = self.shade(tile, shade, &attribs);
+                self.image.set(x, y, color);
+            }
+        }
+    }
+
+    // Set the pixels along a line with the color of outlines,
+    // wherever no mesh lies in front of it.
+    fn line(&mut self, a: &ScreenVertex, b: &ScreenVertex) {
+        let (width, height) = (self.image.width, self.image.height);
+        let steps = (b.x - a.x).abs().max((b.y - a.y).abs()).ceil().max(1.) as u32;
+
+        for step in 0..=steps {
+            // Depth is linear on the screen.
+            let t = step as f32 / steps as f32;
+            let x = a.x + (b.x - a.x) * t;
+            let y = a.y + (b.y - a.y) * t;
+            let z = a.z + (b.z - a.z) * t;
+
+            if x < 0. || y < 0. || x >= width as f32 || y >= height as f32 {
+                continue;
+            }
+
+            let (x, y) = (x as u32, y as u32);
+            let i = (x + y * width) as usize;
+
+            if (-1. ..=1.).contains(&z) && z < self.depth[i] {
+                self.image.set(x, y, OUTLINE_COLOR);
+            }
+        }
+    }
+
+    // Compute the color of a fragment like fs.glsl does.
+    fn shade(&self, tile: [f32; 2], sun: f32, attribs: &[f32; ATTRIBS]) -> [f32; 3] {
+        let [u, v, ao, sky, block, r, g, b] = *attribs;
+
+        let occlusion = AO_MIN + (1. - AO_MIN) * ao / 3.;
+        let max = MAX_LIGHT as f32;
+        let brightness = LIGHT_FALLOFF.powf(max - sky.max(block) * max);
+        let grey = occlusion * brightness * sun;
+
+        let texel = self.sample(tile, [u, v]);
+
+        [
+            texel[0] * r * grey,
+            texel[1] * g * grey,
+            texel[2] * b * grey,
+        ]
+    }
+
+    // Sample the nearest texel of a tile like
+    // terrain_atlas.glsl does.
+    fn sample(&self, tile: [f32; 2], uv: [f32; 2]) -> [f32; 3] {
+        let size = [self.atlas.width() as f32, self.atlas.height() as f32];
+        let extent = self.atlas.tile_extent();
+
+        // Wrap the repeating coordinate into the tile, half
+        // a texel away from its edges.
+        let texel = |c: usize| {
+            let half_texel = 0.5 / size[c];
+            let local = (uv[c] - uv[c].floor()) * extent[c];
+            let local = local.max(half_texel).min(extent[c] - half_texel);
+
+            ((tile[c] + local) * size[c]) as usize
+        };
+
+        let (x, y) = (texel(0), texel(1));
+        let i = (x + y * self.atlas.width() as usize) * RGB;
+        let pixel = &self.atlas.pixels()[i..i + RGB];
+
+        [
+            pixel[0] as f32 / 255.,
+            pixel[1] as f32 / 255.,
+            pixel[2] as f32 / 255.,
+        ]
+    }
+}
+
+// A vertex in clip space, along with the attributes
+// that are interpolated across triangles.
+#[derive(Clone, Copy)]
+struct ClipVertex {
+    pos: [f32; 4],
+    attribs: [f32; ATTRIBS],
+}
+
+// A vertex on the image. ``z`` is the depth in normalized
+// device coordinates, and the attributes are divided by w,
+// so that they can be interpolated linearly.
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    z: f32,
+    inv_w: f32,
+    attribs: [f32; ATTRIBS],
+}
+
+// Transform a vertex into clip space like vs.glsl does.
+fn clip_vertex(mvp: &Mat4x4, vertex: &VoxelVertex) -> ClipVertex {
+    let [x, y, z] = *vertex.pos;
+    let m = &mvp.0;
+
+    let mut pos = [0.; 4];
+    for (row, c) in pos.iter_mut().enumerate() {
+        *c = m[0][row] * x + m[1][row] * y + m[2][row] * z + m[3][row];
+    }
+
+    let [u, v] = *vertex.uv;
+    let [sky, block] = *vertex.light;
+    let [r, g, b] = *vertex.tint;
+
+    ClipVertex {
+        pos,
+        attribs: [u, v, *vertex.ao, sky, block, r, g, b],
+    }
+}
+
+// Transform a point in the world into clip space like
+// outline_vs.glsl does.
+fn clip_point(view_projection: &Mat4x4, [x, y, z]: [f32; 3]) -> ClipVertex {
+    let m = &view_projection.0;
+
+    let mut pos = [0.; 4];
+    for (row, c) in pos.iter_mut().enumerate() {
+        *c = m[0][row] * x + m[1][row] * y + m[2][row] * z + m[3][row];
+    }
+
+    ClipVertex {
+        pos,
+        attribs: [0.; ATTRIBS],
+    }
+}
+
+// Clip a line against the near plane, or return ``None``
+// if it lies entirely behind it.
+fn clip_line_near([a, b]: [ClipVertex; 2]) -> Option<[ClipVertex; 2]> {
+    let distance = |v: &ClipVertex| v.pos[2] + v.pos[3];
+    let (da, db) = (distance(&a), distance(&b));
+
+    if da < 0. && db < 0. {
+        return None;
+    }
+
+    let t = da / (da - db);
+    let mut cut = a;
+    for (c, (a, b)) in cut.pos.iter_mut().zip(a.pos.iter().zip(&b.pos)) {
+        *c = a + (b - a) * t;
+    }
+
+    Some(if da < 0. {
+        [cut, b]
+    } else if db < 0. {
+        [a, cut]
+    } else {
+        [a, b]
+    })
+}
+
+// Clip a polygon against the near plane, on which z = -w.
+fn clip_near(polygon: &[ClipVertex]) -> Vec<ClipVertex> {
+    let distance = |v: &ClipVertex| v.pos[2] + v.pos[3];
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        let (da, db) = (distance(a), distance(b));
+
+        if da >= 0. {
+            clipped.push(*a);
+        }
+
+        if (da >= 0.) != (db >= 0.) {
+            let t = da / (da - db);
+            let mut v = *a;
+
+            for (c, (a, b)) in v.pos.iter_mut().zip(a.pos.iter().zip(&b.pos)) {
+                *c = a + (b - a) * t;
+            }
+
+            for (c, (a, b)) in v.attribs.iter_mut().zip(a.attribs.iter().zip(&b.attribs)) {
+                *c = a + (b - a) * t;
+            }
+
+            clipped.push(v);
+        }
+    }
+
+    clipped
+}
+
+// Rotate a normal by the model matrix like vs.glsl does.
+fn transform_normal(model: &Mat4x4, [x, y, z]: [f32; 3]) -> [f32; 3] {
+    let m = &model.0;
+
+    [
+        m[0][0] * x + m[1][0] * y + m[2][0] * z,
+        m[0][1] * x + m[1][1] * y + m[2][1] * z,
+        m[0][2] * x + m[1][2] * y + m[2][2] * z,
+    ]
+}
+
+// Return how brightly the sun lights a face like fs.glsl does.
+fn sun_shade(normal: [f32; 3]) -> f32 {
+    let length = |v: [f32; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    let sun = SUN_DIRECTION;
+
+    let dot = normal[0] * sun[0] + normal[1] * sun[1] + normal[2] * sun[2];
+    let facing = (dot / (length(normal) * length(sun))).max(0.);
+
+    (1. - SUN_STRENGTH) + SUN_STRENGTH * facing
+}
+
+// Return twice the signed area of the triangle between the
+// edge from ``a`` to ``b`` and the point ``(x, y)``.
+fn edge(a: &ScreenVertex, b: &ScreenVertex, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block::Block,
+        entity::{
+            camera::Camera,
+            player::{Player, EYE_HEIGHT},
+            sector::{
+                gen_terrain, gen_terrain_greedy, LightChannel, PaddedSector, SectorIndex,
+                VertexFormat,
+            },
+        },
+        maths::matrix::{Projection, Transform},
+        resource::TextureManager,
+    };
+    use std::{env, fs::File, io::BufWriter, path::Path};
+
+    // The image that ``synthetic_sector`` is expected to look
+    // like. Set ``UPDATE_GOLDEN`` to write it anew after the
+    // shading changed on purpose.
+    const GOLDEN: &str = "res/golden/sector.png";
+
+    const WIDTH: u32 = 320;
+    const HEIGHT: u32 = 180;
+
+    // Pixels that differ by no more than this in every channel
+    // count as equal, and at most one in this many pixels may
+    // differ by more.
+    const TOLERANCE: u8 = 2;
+    const MAX_DIFFERENT: usize = 1000;
+
+    // A sector with a floor of grass, rows of slabs, a brick
+    // wall, a stone pillar and a torch. The sky light fades
+    // along X, so that every light level is shown, and the
+    // torch lights the voxels around it.
+    fn synthetic_sector() -> PaddedSector {
+        PaddedSector::new(
+            SectorIndex(0, 0, 0),
+            |c| match (c.0, c.1, c.2) {
+                (_, 0..=1, _) => Block::Grass,
+                (x, 2, z) if x % 3 == 0 && z > 3 => Block::Slab,
+                (4..=12, 2..=4, 2) => Block::Bricks,
+                (14, 2..=6, 14) => Block::Stone,
+                (8, 2, 10) => Block::Torch,
+                _ => Block::Air,
+            },
+            |c, channel| match channel {
+                LightChannel::Sky => (c.0 as u8).min(MAX_LIGHT),
+                LightChannel::Block => {
+                    let distance =
+                        (c.0 as i32 - 8).abs() + (c.1 as i32 - 2).abs() + (c.2 as i32 - 10).abs();
+                    (Block::Torch.light_emission() as i32 - distance).max(0) as u8
+                }
+            },
+        )
+    }
+
+    fn render(atlas: &TextureAtlas, pre_geo: &PreGeometry) -> Image {
+        let feet = (8., 7. - EYE_HEIGHT, 22.);
+        let player = Player::with_pos_rot(feet, (-25f32.to_radians(), 0.));
+        let mut camera = Camera::new();
+        camera.snap_to(&player);
+
+        let projection =
+            Projection::new(40f32.to_radians(), WIDTH as f32 / HEIGHT as f32, 0.1, 1000.);
+
+        let mut rasterizer = Rasterizer::new(
+            atlas,
+            WIDTH,
+            HEIGHT,
+            &projection.to_matrix(),
+            &camera.to_matrix(),
+        );
+        rasterizer.draw(&SectorIndex(0, 0, 0).translation(), pre_geo);
+        rasterizer.into_image()
+    }
+
+    #[test]
+    fn sectors_are_drawn_like_the_golden_image() {
+        let atlas = TextureManager::load_terrain_atlas().unwrap();
+        let textures = TextureManager::terrain_textures(&atlas, atlas.layout());
+        let source = synthetic_sector();
+
+        let pre_geo = gen_terrain(&textures, &source, VertexFormat::Full).unwrap();
+        let image = render(&atlas, &pre_geo);
+
+        if env::var_os("UPDATE_GOLDEN").is_some() {
+            let w = BufWriter::new(File::create(GOLDEN).unwrap());
+            image.write_png(w).unwrap();
+        }
+
+        let golden = Image::read_png(File::open(Path::new(GOLDEN)).unwrap()).unwrap();
+        let different = image.differences(&golden, TOLERANCE);
+        assert!(
+            different <= (WIDTH * HEIGHT) as usize / MAX_DIFFERENT,
+            "{} pixels differ from {}",
+            different,
+            GOLDEN
+        );
+
+        // Packed vertices unpack to the same vertices, and
+        // merged quads cover the same faces.
+        let packed = gen_terrain(&textures, &source, VertexFormat::Packed).unwrap();
+        assert_eq!(render(&atlas, &packed).differences(&image, TOLERANCE), 0);
+
+        let greedy = gen_terrain_greedy(&textures, &source, VertexFormat::Full).unwrap();
+        let different = render(&atlas, &greedy).differences(&golden, TOLERANCE);
+        assert!(different <= (WIDTH * HEIGHT) as usize / MAX_DIFFERENT);
+    }
+}
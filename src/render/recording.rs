@@ -0,0 +1,105 @@
+//! Implements a ``Renderer`` that draws nothing.
+//!
+//! It keeps every mesh it is given until the mesh is dropped,
+//! along with the last frame, so that tests can check what
+//! would have been drawn, or draw it with a ``Rasterizer``.
+
+use super::{Frame, Image, MeshId, Rasterizer, Renderer};
+use crate::{entity::sector::PreGeometry, resource::TextureAtlas};
+use std::collections::HashMap;
+
+/// A ``Renderer`` that only records what it is asked
+/// to draw.
+pub struct RecordingRenderer {
+    meshes: HashMap<MeshId, PreGeometry>,
+    next_id: u32,
+    frames: usize,
+    last_frame: Option<Frame>,
+}
+
+impl RecordingRenderer {
+    /// Create a new ``RecordingRenderer`` without any
+    /// meshes or frames.
+    pub fn new() -> RecordingRenderer {
+        RecordingRenderer {
+            meshes: HashMap::new(),
+            next_id: 0,
+            frames: 0,
+            last_frame: None,
+        }
+    }
+
+    /// Return the mesh with the given ID, or ``None`` if
+    /// it has been dropped.
+    pub fn mesh(&self, mesh: MeshId) -> Option<&PreGeometry> {
+        self.meshes.get(&mesh)
+    }
+
+    /// Return the number of meshes that have been
+    /// uploaded and not dropped yet.
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Return the number of frames drawn so far.
+    pub fn frame_count(&self) -> usize {
+        self.frames
+    }
+
+    /// Return the last frame that was drawn, if any.
+    pub fn last_frame(&self) -> Option<&Frame> {
+        self.last_frame.as_ref()
+    }
+
+    /// Draw the last frame with a ``Rasterizer`` into an
+    /// image of the given size, or return ``None`` if no
+    /// frame has been drawn yet.
+    ///
+    /// The meshes must have been generated with the
+    /// ``TextureLayout`` of ``atlas``.
+    pub fn rasterize(&self, atlas: &TextureAtlas, width: u32, height: u32) -> Option<Image> {
+        let frame = self.last_frame.as_ref()?;
+        let mut rasterizer = Rasterizer::new(atlas, width, height, &frame.projection, &frame.view);
+
+        for draw in &frame.draws {
+            if let Some(pre_geo) = self.meshes.get(&draw.mesh) {
+                rasterizer.draw(&draw.model, pre_geo);
+            }
+        }
+
+        for outline in &frame.outlines {
+            rasterizer.draw_outline(outline);
+        }
+
+        Some(rasterizer.into_image())
+    }
+}
+
+impl Default for RecordingRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    fn upload_mesh(&mut self, pre_geo: PreGeometry) -> MeshId {
+        let id = MeshId(self.next_id);
+        self.next_id += 1;
+        self.meshes.insert(id, pre_geo);
+
+        id
+    }
+
+    /// Panics if the mesh is unknown, which catches meshes
+    /// that are dropped twice.
+    fn drop_mesh(&mut self, mesh: MeshId) {
+        if self.meshes.remove(&mesh).is_none() {
+            panic!("{:?} was dropped but is not uploaded", mesh);
+        }
+    }
+
+    fn draw_frame(&mut self, frame: &Frame) {
+        self.frames += 1;
+        self.last_frame = Some(frame.clone());
+    }
+}
@@ -3,14 +3,25 @@
 //! Currently, only textures are managed by this implementation,
 //! but in the future, sounds or models could be loaded as well.
 
+mod atlas;
+mod image;
+
+pub use atlas::TextureAtlas;
+pub use image::{read_png, write_png, RGB};
+
+use crate::{
+    block::BlockTextures,
+    entity::sector::{TerrainTextures, TextureLayout},
+};
 use luminance::{
     context::GraphicsContext,
     pixel::RGB32F,
-    texture::{Dim2, Flat, MagFilter, MinFilter, Sampler, Texture},
+    texture::{Dim2, Dim2Array, Flat, MagFilter, MinFilter, Sampler, Texture, Wrap},
 };
-use png::{self, Decoder, OutputInfo};
+use png::{self, OutputInfo};
 use std::{
     fs::File,
+    io,
     path::{Path, PathBuf},
     rc::Rc,
 };
@@ -55,13 +66,20 @@ impl ResourceManager {
 /// reference count update, but it is not horribly expensive
 /// since the update is non-atomic.
 pub struct TextureManager {
+    terrain_atlas: Rc<TextureAtlas>,
     terrain_tex: Rc<Texture2D>,
+    terrain_array: Rc<TextureArray>,
 }
 
 impl TextureManager {
     const TEXTURE_PATH: &'static str = "tex";
 
-    const TERRAIN: &'static str = "terrain.png";
+    const TERRAIN: &str = "terrain";
+
+    // The width of the border around every terrain tile.
+    // A single pixel keeps both nearest and linear filtering
+    // inside of a tile.
+    const TERRAIN_PADDING: u32 = 1;
 
     /// Load all textures and store them in a new
     /// ``TextureManager`` instance.
@@ -71,23 +89,90 @@ impl TextureManager {
     /// parameter represents the OpenGL context.
     /// The current GLFW window normally should be
     /// supplied for ``ctx``.
+    ///
+    /// The terrain is loaded both as an atlas and as
+    /// a texture array, so that either ``TextureLayout``
+    /// can be rendered.
     pub fn load_all<C: GraphicsContext>(ctx: &mut C) -> TextureManager {
-        let tex_path: PathBuf = [RESOURCE_PATH, Self::TEXTURE_PATH].iter().collect();
+        let atlas = Self::load_terrain_atlas().unwrap();
 
-        let terrain_path = tex_path.join(Self::TERRAIN);
+        let sampler = Sampler {
+            min_filter: MinFilter::Nearest,
+            mag_filter: MagFilter::Nearest,
+            ..Sampler::default()
+        };
 
-        let mut sampler = Sampler::default();
-        sampler.min_filter = MinFilter::Nearest;
-        sampler.mag_filter = MagFilter::Nearest;
+        // Each layer of the array holds a single tile, so
+        // it can repeat and be mipmapped without bleeding.
+        let array_sampler = Sampler {
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            min_filter: MinFilter::NearestMipmapLinear,
+            mag_filter: MagFilter::Nearest,
+            ..Sampler::default()
+        };
 
         TextureManager {
-            terrain_tex: Rc::new(Texture2D::with_path(ctx, terrain_path, &sampler)),
+            terrain_tex: Rc::new(Texture2D::from_rgb(
+                ctx,
+                [atlas.width(), atlas.height()],
+                atlas.pixels(),
+                &sampler,
+            )),
+            terrain_array: Rc::new(TextureArray::from_atlas(ctx, &atlas, &array_sampler)),
+            terrain_atlas: Rc::new(atlas),
         }
     }
 
+    /// Return the terrain atlas that the terrain
+    /// textures were created from.
+    pub fn terrain_atlas(&self) -> Rc<TextureAtlas> {
+        Rc::clone(&self.terrain_atlas)
+    }
+
     pub fn terrain(&self) -> Rc<Texture2D> {
         Rc::clone(&self.terrain_tex)
     }
+
+    /// Return the terrain texture with one tile
+    /// in each layer of a texture array.
+    pub fn terrain_array(&self) -> Rc<TextureArray> {
+        Rc::clone(&self.terrain_array)
+    }
+
+    /// Assemble the terrain atlas from the terrain tile
+    /// images without uploading it.
+    ///
+    /// No ``GraphicsContext`` is needed, so this works in
+    /// tools that never open a window. The tile images are
+    /// looked up relative to the working directory, and the
+    /// returned error names the directory if they cannot be
+    /// loaded from it.
+    pub fn load_terrain_atlas() -> io::Result<TextureAtlas> {
+        let path = Self::terrain_path();
+
+        TextureAtlas::from_dir(&path, Self::TERRAIN_PADDING)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+    }
+
+    /// Return the block textures for meshes with the given
+    /// ``layout`` that are textured with ``atlas``.
+    ///
+    /// Panics if a block refers to a tile that the atlas
+    /// does not have.
+    pub fn terrain_textures(atlas: &TextureAtlas, layout: TextureLayout) -> TerrainTextures {
+        let blocks = BlockTextures::resolve(|name| atlas.tile_id(name)).unwrap();
+
+        TerrainTextures::new(layout, blocks)
+    }
+
+    // Return the path of the directory that holds
+    // the terrain tile images.
+    fn terrain_path() -> PathBuf {
+        let tex_path: PathBuf = [RESOURCE_PATH, Self::TEXTURE_PATH].iter().collect();
+
+        tex_path.join(Self::TERRAIN)
+    }
 }
 
 /// The type of a low-level simple 2D texture.
@@ -123,8 +208,29 @@ impl Texture2D {
     where
         C: GraphicsContext,
     {
-        let (inner, info) = load_png(ctx, file, sampler);
-        Self::new(inner, info)
+        let (width, height, pixels) = read_png(file).unwrap();
+        Self::from_rgb(ctx, [width, height], &pixels, sampler)
+    }
+
+    /// Create a new 2D texture from 8-bit RGB ``pixels``,
+    /// stored row by row, of an image of the given ``size``.
+    ///
+    /// The ``sampler`` is passed on to ``luminance``
+    /// to control how the image is sampled by the
+    /// OpenGL backend.
+    pub fn from_rgb<C>(ctx: &mut C, size: [u32; 2], pixels: &[u8], sampler: &Sampler) -> Texture2D
+    where
+        C: GraphicsContext,
+    {
+        let info = OutputInfo {
+            width: size[0],
+            height: size[1],
+            color_type: png::ColorType::RGB,
+            bit_depth: png::BitDepth::Eight,
+            line_size: size[0] as usize * RGB,
+        };
+
+        Self::new(upload_rgb(ctx, size, pixels, sampler), info)
     }
 
     /// Create a new 2D texture by loading the texture
@@ -152,38 +258,87 @@ impl Texture2D {
     }
 }
 
-/// Load a PNG image from the given ``File``.
+/// The type of a low-level 2D texture array.
 ///
-/// The ``sampler`` parameter allows the caller
-/// to customize how the image data is sampled
-/// by OpenGL.
-#[rustfmt::skip]
-fn load_png<C>(ctx: &mut C, file: File, sampler: &Sampler) -> (Tex2DInner, OutputInfo)
+/// This is an alias to the underlying ``luminance``
+/// texture. If you are not talking directly to the
+/// graphics API, use ``TextureArray`` instead.
+pub type TexArrayInner = Texture<Flat, Dim2Array, RGB32F>;
+
+/// A 2D texture array with one square tile in each layer.
+pub struct TextureArray {
+    inner: TexArrayInner,
+    tile_size: u32,
+    layers: u32,
+}
+
+impl TextureArray {
+    /// Create a new texture array with one tile of
+    /// ``atlas`` in each layer.
+    ///
+    /// The layer of a tile is its texture ID, and its
+    /// border is left out. A mipmap chain down to a
+    /// single pixel per tile is generated.
+    pub fn from_atlas<C>(ctx: &mut C, atlas: &TextureAtlas, sampler: &Sampler) -> TextureArray
+    where
+        C: GraphicsContext,
+    {
+        let tile_size = atlas.tile_size();
+        let layers = atlas.tile_count();
+
+        let mut image = Vec::with_capacity((layers * tile_size * tile_size) as usize);
+        for layer in 0..layers {
+            image.extend(atlas.tile_pixels(layer).chunks(3).map(texel));
+        }
+
+        // Halve the tile until it is a single pixel wide.
+        let mipmaps = (31 - tile_size.leading_zeros()) as usize;
+        let inner =
+            TexArrayInner::new(ctx, ([tile_size, tile_size], layers), mipmaps, sampler).unwrap();
+
+        inner.upload(true, &image);
+
+        TextureArray {
+            inner,
+            tile_size,
+            layers,
+        }
+    }
+
+    /// Return the low-level inner ``luminance`` texture.
+    pub fn inner(&self) -> &TexArrayInner {
+        &self.inner
+    }
+
+    /// Return the edge length of a layer in pixels.
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Return the number of layers.
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+}
+
+/// Upload 8-bit RGB ``pixels`` into a new texture.
+fn upload_rgb<C>(ctx: &mut C, size: [u32; 2], pixels: &[u8], sampler: &Sampler) -> Tex2DInner
 where
     C: GraphicsContext,
 {
-    let decoder = Decoder::new(file);
-    let (info, mut reader) = decoder.read_info().unwrap();
-    
-    assert_eq!(info.color_type, png::ColorType::RGB);
-    assert_eq!(info.bit_depth, png::BitDepth::Eight);
-    
-    let mut data = vec![0; info.buffer_size()];
-    
-    reader.next_frame(&mut data).unwrap();
-    
-    let mut image = Vec::with_capacity(data.len() / 3);
-    for i in 0..(data.len() / 3) {
-        let idx = i * 3;
-        
-        image.push((data[idx]     as f32 / 255.,
-                    data[idx + 1] as f32 / 255.,
-                    data[idx + 2] as f32 / 255.));
-    }
-    
-    let tex = Tex2DInner::new(ctx, [info.width, info.height], 0, sampler).unwrap();
-    
+    let image: Vec<_> = pixels.chunks(RGB).map(texel).collect();
+
+    let tex = Tex2DInner::new(ctx, size, 0, sampler).unwrap();
+
     tex.upload(false, &image);
-    
-    (tex, info)
+
+    tex
+}
+
+// Convert one 8-bit RGB pixel into a floating-point texel.
+#[rustfmt::skip]
+fn texel(pixel: &[u8]) -> (f32, f32, f32) {
+    (pixel[0] as f32 / 255.,
+     pixel[1] as f32 / 255.,
+     pixel[2] as f32 / 255.)
 }
@@ -0,0 +1,413 @@
+//! Assembles the texture atlas from individual tile images.
+//!
+//! Every PNG image in a directory becomes one tile of the atlas,
+//! named after its file. All tiles must be square and equally
+//! large, but any size works. The tiles are sorted by name and
+//! laid out row by row, so their IDs only change when tiles are
+//! added or removed.
+//!
+//! An image that is several times as wide as it is tall is a
+//! strip of tiles instead, which keeps large sets of related
+//! tiles in a single file. The tiles of ``name.png`` are named
+//! ``name_0``, ``name_1`` and so on, from left to right.
+//!
+//! Each tile is surrounded by a border that repeats its edge
+//! pixels. Sampling slightly outside of a tile therefore picks
+//! up the tile itself rather than its neighbor.
+
+use super::image::{self, invalid, read_png, RGB};
+use crate::{block::BlockTextureID, entity::sector::TextureLayout};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+/// A texture atlas that was assembled from tile images.
+///
+/// The pixels are stored as 8-bit RGB, row by row.
+pub struct TextureAtlas {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    padding: u32,
+    columns: u32,
+    ids: HashMap<String, BlockTextureID>,
+}
+
+impl TextureAtlas {
+    /// Build an atlas out of every PNG image in ``dir``.
+    ///
+    /// Each tile is surrounded by a border that is ``padding``
+    /// pixels wide. An error is returned if ``dir`` holds no
+    /// images, or if an image is neither square nor a strip of
+    /// square tiles, not the same size as the others, or not
+    /// 8-bit RGB or RGBA. Alpha is discarded. Two tiles may not
+    /// share a name either, as ``grass_0.png`` would with the
+    /// first tile of ``grass.png`` if that were a strip.
+    pub fn from_dir(dir: impl AsRef<Path>, padding: u32) -> io::Result<TextureAtlas> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            if path.extension().is_some_and(|ext| ext == "png") {
+                paths.push(path);
+            }
+        }
+
+        if paths.is_empty() {
+            return Err(invalid("no tile images found"));
+        }
+
+        // Sort the tiles so that their IDs do not depend on
+        // the order in which the directory is listed.
+        paths.sort();
+
+        let mut tile_size = None;
+        let mut tiles = Vec::with_capacity(paths.len());
+        let mut ids = HashMap::new();
+
+        for path in &paths {
+            let (size, strip) = read_tiles(path)?;
+
+            if *tile_size.get_or_insert(size) != size {
+                return Err(invalid(&format!(
+                    "{} does not match the size of the other tiles",
+                    path.display()
+                )));
+            }
+
+            let stem = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let n_strip = strip.len();
+
+            for (i, pixels) in strip.into_iter().enumerate() {
+                let name = if n_strip == 1 {
+                    stem.clone()
+                } else {
+                    format!("{}_{}", stem, i)
+                };
+
+                if ids.insert(name, tiles.len() as BlockTextureID).is_some() {
+                    return Err(invalid(&format!(
+                        "{} holds a tile whose name is already taken",
+                        path.display()
+                    )));
+                }
+
+                tiles.push(pixels);
+            }
+        }
+
+        let tile_size = tile_size.unwrap();
+        let cell = tile_size + 2 * padding;
+
+        // Keep the atlas roughly square.
+        let n_tiles = tiles.len() as u32;
+        let columns = (1..).find(|c| c * c >= n_tiles).unwrap();
+        let rows = n_tiles.div_ceil(columns);
+
+        let (width, height) = (columns * cell, rows * cell);
+
+        let mut atlas = TextureAtlas {
+            pixels: vec![0; (width * height) as usize * RGB],
+            width,
+            height,
+            tile_size,
+            padding,
+            columns,
+            ids,
+        };
+
+        for (id, tile) in tiles.iter().enumerate() {
+            atlas.blit(id as BlockTextureID, tile);
+        }
+
+        Ok(atlas)
+    }
+
+    /// Return the width of the atlas in pixels.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Return the height of the atlas in pixels.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Return the edge length of a tile in pixels,
+    /// not counting its border.
+    pub fn tile_size(&self) -> u32 {
+        self.tile_size
+    }
+
+    /// Return the width of the border around
+    /// every tile in pixels.
+    pub fn padding(&self) -> u32 {
+        self.padding
+    }
+
+    /// Return the number of tiles in the atlas.
+    pub fn tile_count(&self) -> u32 {
+        self.ids.len() as u32
+    }
+
+    /// Return the RGB pixels of the whole atlas.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Return the ID of the tile with the given name,
+    /// or ``None`` if there is no such tile.
+    pub fn tile_id(&self, name: &str) -> Option<BlockTextureID> {
+        self.ids.get(name).cloned()
+    }
+
+    /// Return the IDs of all tiles by name.
+    pub fn tile_ids(&self) -> &HashMap<String, BlockTextureID> {
+        &self.ids
+    }
+
+    /// Return the RGB pixels of the tile with the
+    /// given ID, without its border.
+    pub fn tile_pixels(&self, id: BlockTextureID) -> Vec<u8> {
+        let (x, y) = self.tile_origin(id);
+        let row_len = self.tile_size as usize * RGB;
+
+        let mut pixels = Vec::with_capacity(row_len * self.tile_size as usize);
+        for row in y..y + self.tile_size {
+            let start = self.index(x, row);
+            pixels.extend_from_slice(&self.pixels[start..start + row_len]);
+        }
+
+        pixels
+    }
+
+    /// Return the ``TextureLayout`` of meshes that sample
+    /// this atlas.
+    pub fn layout(&self) -> TextureLayout {
+        TextureLayout::Atlas {
+            width: self.width,
+            height: self.height,
+            tile_size: self.tile_size,
+            padding: self.padding,
+        }
+    }
+
+    /// Return the size of a single tile, relative to
+    /// the size of the whole atlas.
+    ///
+    /// The fragment shader needs this value to wrap
+    /// texture coordinates into a tile.
+    pub fn tile_extent(&self) -> [f32; 2] {
+        [
+            self.tile_size as f32 / self.width as f32,
+            self.tile_size as f32 / self.height as f32,
+        ]
+    }
+
+    /// Return the number of tiles in each row.
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    /// Return the size of a tile together with its border,
+    /// relative to the size of the whole atlas.
+    ///
+    /// Along with ``padding_extent`` and ``columns``, it lets
+    /// the packed vertex shader find the origin of a tile.
+    pub fn cell_extent(&self) -> [f32; 2] {
+        let cell = (self.tile_size + 2 * self.padding) as f32;
+
+        [cell / self.width as f32, cell / self.height as f32]
+    }
+
+    /// Return the width of the border around every tile,
+    /// relative to the size of the whole atlas.
+    pub fn padding_extent(&self) -> [f32; 2] {
+        [
+            self.padding as f32 / self.width as f32,
+            self.padding as f32 / self.height as f32,
+        ]
+    }
+
+    /// Encode the atlas as a PNG image.
+    pub fn write_png(&self, w: impl Write) -> io::Result<()> {
+        image::write_png(w, self.width, self.height, &self.pixels)
+    }
+
+    // Copy the pixels of a tile into its cell, and repeat
+    // its edge pixels across the border of the cell.
+    fn blit(&mut self, id: BlockTextureID, tile: &[u8]) {
+        let (x, y) = self.tile_origin(id);
+        let size = self.tile_size as i64;
+        let padding = self.padding as i64;
+
+        for dy in -padding..size + padding {
+            for dx in -padding..size + padding {
+                let src_x = dx.max(0).min(size - 1) as usize;
+                let src_y = dy.max(0).min(size - 1) as usize;
+                let src = (src_x + src_y * size as usize) * RGB;
+
+                let dst = self.index((x as i64 + dx) as u32, (y as i64 + dy) as u32);
+
+                self.pixels[dst..dst + RGB].copy_from_slice(&tile[src..src + RGB]);
+            }
+        }
+    }
+
+    // Return the upper left pixel of a tile, inside of
+    // its border.
+    fn tile_origin(&self, id: BlockTextureID) -> (u32, u32) {
+        let cell = self.tile_size + 2 * self.padding;
+
+        (
+            (id % self.columns) * cell + self.padding,
+            (id / self.columns) * cell + self.padding,
+        )
+    }
+
+    // Return the index of the first byte of a pixel.
+    fn index(&self, x: u32, y: u32) -> usize {
+        (x + y * self.width) as usize * RGB
+    }
+}
+
+// Decode a tile image or a strip of tiles, and return the
+// edge length of a tile along with the RGB pixels of each.
+fn read_tiles(path: &Path) -> io::Result<(u32, Vec<Vec<u8>>)> {
+    let (width, height, pixels) =
+        read_png(File::open(path)?).map_err(|e| invalid(&format!("{}: {}", path.display(), e)))?;
+
+    if width % height != 0 {
+        return Err(invalid(&format!(
+            "{} is not a strip of square tiles",
+            path.display()
+        )));
+    }
+
+    // Cut the strip into tiles, one row of pixels at a time.
+    let size = height as usize;
+    let mut tiles = vec![Vec::with_capacity(size * size * RGB); width as usize / size];
+    for row in pixels.chunks(width as usize * RGB) {
+        for (tile, tile_row) in tiles.iter_mut().zip(row.chunks(size * RGB)) {
+            tile.extend_from_slice(tile_row);
+        }
+    }
+
+    Ok((height, tiles))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf, process};
+
+    // A directory of tile images that is removed again
+    // when the test ends.
+    struct TileDir(PathBuf);
+
+    impl TileDir {
+        fn new(test: &str) -> TileDir {
+            let dir = env::temp_dir().join(format!("sandbox-atlas-{}-{}", process::id(), test));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            TileDir(dir)
+        }
+
+        // Write an image of ``width`` by ``height`` pixels, each
+        // of which is colored after its position.
+        fn add(&self, name: &str, width: u32, height: u32) {
+            let pixels: Vec<u8> = (0..height)
+                .flat_map(|y| (0..width).flat_map(move |x| vec![x as u8, y as u8, 7]))
+                .collect();
+
+            let file = File::create(self.0.join(name)).unwrap();
+            image::write_png(file, width, height, &pixels).unwrap();
+        }
+
+        fn atlas(&self, padding: u32) -> io::Result<TextureAtlas> {
+            TextureAtlas::from_dir(&self.0, padding)
+        }
+    }
+
+    impl Drop for TileDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Return the pixel at ``(x, y)`` of the whole atlas.
+    fn pixel(atlas: &TextureAtlas, x: u32, y: u32) -> &[u8] {
+        let i = atlas.index(x, y);
+        &atlas.pixels()[i..i + RGB]
+    }
+
+    #[test]
+    fn strips_are_cut_into_named_tiles() {
+        let dir = TileDir::new("strips");
+        dir.add("a.png", 4, 4);
+        dir.add("b.png", 12, 4);
+
+        let atlas = dir.atlas(0).unwrap();
+        assert_eq!(atlas.tile_count(), 4);
+        assert_eq!(atlas.tile_size(), 4);
+        assert_eq!(atlas.tile_id("b"), None);
+
+        for i in 0..3 {
+            let id = atlas.tile_id(&format!("b_{}", i)).unwrap();
+            let pixels = atlas.tile_pixels(id);
+
+            // Each tile starts where the last one ended.
+            assert_eq!(&pixels[..RGB], &[4 * i as u8, 0, 7]);
+            assert_eq!(&pixels[pixels.len() - RGB..], &[4 * i as u8 + 3, 3, 7]);
+        }
+    }
+
+    #[test]
+    fn borders_repeat_the_edges_of_tiles() {
+        let dir = TileDir::new("borders");
+        dir.add("a.png", 4, 4);
+        dir.add("b.png", 4, 4);
+
+        let atlas = dir.atlas(2).unwrap();
+        assert_eq!((atlas.width(), atlas.height()), (16, 8));
+
+        let id = atlas.tile_id("b").unwrap();
+        let (x, y) = atlas.tile_origin(id);
+        assert_eq!((x, y), (10, 2));
+
+        // The corners of the border take the corner pixels of
+        // the tile, and its sides take the edge next to them.
+        assert_eq!(pixel(&atlas, x - 2, y - 2), &[0, 0, 7]);
+        assert_eq!(pixel(&atlas, x + 5, y + 5), &[3, 3, 7]);
+        assert_eq!(pixel(&atlas, x + 2, y - 1), &[2, 0, 7]);
+        assert_eq!(pixel(&atlas, x - 1, y + 3), &[0, 3, 7]);
+        assert_eq!(pixel(&atlas, x + 4, y + 1), &[3, 1, 7]);
+    }
+
+    #[test]
+    fn tiles_of_other_sizes_are_rejected() {
+        let dir = TileDir::new("sizes");
+        dir.add("a.png", 4, 4);
+        dir.add("b.png", 8, 8);
+        assert!(dir.atlas(0).is_err());
+
+        let dir = TileDir::new("strip_sizes");
+        dir.add("a.png", 10, 4);
+        assert!(dir.atlas(0).is_err());
+    }
+
+    #[test]
+    fn tile_names_must_be_unique() {
+        let dir = TileDir::new("names");
+        dir.add("a.png", 8, 4);
+        dir.add("a_0.png", 4, 4);
+
+        let e = dir.atlas(0).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,55 @@
+//! Reads and writes PNG images.
+//!
+//! Images are handled as 8-bit RGB pixels, stored row by
+//! row from the top, which is what both the texture atlas
+//! and the software rasterizer work with.
+
+use png::{self, BitDepth, ColorType, Decoder, Encoder, HasParameters};
+use std::io::{self, Read, Write};
+
+/// The number of bytes of an RGB pixel.
+pub const RGB: usize = 3;
+
+/// Decode a PNG image, and return its width, height and
+/// RGB pixels.
+///
+/// An error is returned if the image is not 8-bit RGB or
+/// RGBA. Alpha is discarded.
+pub fn read_png(r: impl Read) -> io::Result<(u32, u32, Vec<u8>)> {
+    let (info, mut reader) = Decoder::new(r).read_info()?;
+
+    if info.bit_depth != BitDepth::Eight {
+        return Err(invalid("the image is not 8-bit"));
+    }
+
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data)?;
+
+    let pixels = match info.color_type {
+        ColorType::RGB => data,
+        ColorType::RGBA => data
+            .chunks(4)
+            .flat_map(|pixel| pixel[..RGB].iter().cloned())
+            .collect(),
+        _ => return Err(invalid("the image is not RGB")),
+    };
+
+    Ok((info.width, info.height, pixels))
+}
+
+/// Encode ``width`` by ``height`` RGB pixels as a PNG
+/// image.
+pub fn write_png(w: impl Write, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let mut encoder = Encoder::new(w, width, height);
+    encoder.set(ColorType::RGB).set(BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+
+    Ok(())
+}
+
+/// Return an error about invalid image data.
+pub(crate) fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
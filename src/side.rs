@@ -11,3 +11,27 @@ pub enum Side {
     Top,
     Bottom,
 }
+
+impl Side {
+    /// All six sides, in declaration order.
+    pub const ALL: [Side; 6] = [
+        Side::Front,
+        Side::Back,
+        Side::RightSide,
+        Side::LeftSide,
+        Side::Top,
+        Side::Bottom,
+    ];
+
+    /// Returns the side across the cube from this one.
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Front => Side::Back,
+            Side::Back => Side::Front,
+            Side::RightSide => Side::LeftSide,
+            Side::LeftSide => Side::RightSide,
+            Side::Top => Side::Bottom,
+            Side::Bottom => Side::Top,
+        }
+    }
+}
@@ -0,0 +1,8 @@
+uniform sampler2DArray terrain_array;
+
+vec3 terrain_color() {
+    // Every layer holds a single tile that repeats on its
+    // own, so the coordinate needs no wrapping, and the
+    // mipmap level is chosen correctly across merged quads.
+    return texture(terrain_array, vec3(pass_uv, pass_layer)).rgb;
+}
@@ -0,0 +1,12 @@
+uniform sampler2D terrain_texture;
+uniform vec2 tile_size;
+
+vec3 terrain_color() {
+    // Wrap the repeating coordinate into the tile. It is kept
+    // half a texel away from the tile's edges, so that fragments
+    // from the neighboring tile are never sampled.
+    vec2 half_texel = 0.5 / vec2(textureSize(terrain_texture, 0));
+    vec2 local = clamp(fract(pass_uv) * tile_size, half_texel, tile_size - half_texel);
+    
+    return texture(terrain_texture, pass_tile + local).rgb;
+}
@@ -1,5 +1,7 @@
 //! Utilities for handling and storing data
 //!
-//! Currently only includes a compact bitvector array.
+//! Includes a compact bitvector array and deterministic
+//! position hashing.
 
 pub mod bool_vec;
+pub mod hash;
@@ -0,0 +1,32 @@
+//! Provides deterministic hashing of integer positions.
+//!
+//! World generation and mesh generation frequently need
+//! "random" values that are nonetheless stable: the same
+//! world position must always produce the same value, on
+//! every run and on every machine. The functions here use
+//! only wrapping integer arithmetic to guarantee that.
+
+/// Hash three integer coordinates and a seed into a
+/// pseudo-random ``u32``.
+pub fn hash3(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed ^ 0x9E37_79B9;
+
+    h = mix(h ^ (x as u32).wrapping_mul(0x85EB_CA6B));
+    h = mix(h ^ (y as u32).wrapping_mul(0xC2B2_AE35));
+    h = mix(h ^ (z as u32).wrapping_mul(0x27D4_EB2F));
+
+    h
+}
+
+/// Scramble the bits of ``h`` thoroughly.
+///
+/// This is the finalizer of the MurmurHash3 algorithm.
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85EB_CA6B);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xC2B2_AE35);
+    h ^= h >> 16;
+
+    h
+}
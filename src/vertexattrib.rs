@@ -15,11 +15,76 @@ pub enum Semantic {
 
     #[sem(name = "uv", repr = "[f32; 2]", type_name = "UvAttrib")]
     Color,
+
+    #[sem(name = "tile", repr = "[f32; 2]", type_name = "TileAttrib")]
+    Tile,
+
+    #[sem(name = "ao", repr = "f32", type_name = "AoAttrib")]
+    Ao,
+
+    #[sem(name = "light", repr = "[f32; 2]", type_name = "LightAttrib")]
+    Light,
+
+    #[sem(name = "normal", repr = "[f32; 3]", type_name = "NormalAttrib")]
+    Normal,
+
+    #[sem(name = "layer", repr = "f32", type_name = "LayerAttrib")]
+    Layer,
+
+    #[sem(name = "packed_data", repr = "[u32; 3]", type_name = "PackedAttrib")]
+    Packed,
+
+    #[sem(name = "tint", repr = "[f32; 3]", type_name = "TintAttrib")]
+    Tint,
 }
 
+/// A vertex of a sector mesh.
+///
+/// ``uv`` is measured in whole tiles from the corner of
+/// the quad, so that it repeats across merged quads.
+/// ``tile`` is the origin of the texture atlas tile that
+/// the repeated coordinate is wrapped into, while ``layer``
+/// is the texture array layer it is sampled from instead.
+/// Which of the two is used depends on the ``TextureLayout``
+/// that the mesh was generated with.
+/// ``ao`` is the ambient occlusion of the vertex, from
+/// zero (fully occluded) to three (not occluded).
+/// ``light`` holds the sky and block light levels at the
+/// vertex, each scaled into the range [0, 1].
+/// ``normal`` is the unit normal of the face that the
+/// vertex belongs to.
+/// ``tint`` is the color that the texture is multiplied
+/// by, as decided by the block's ``Tint``.
 #[derive(Clone, Copy, Debug, PartialEq, Vertex)]
 #[vertex(sem = "Semantic")]
 pub struct VoxelVertex {
     pub pos: PosAttrib,
     pub uv: UvAttrib,
+    pub tile: TileAttrib,
+    pub ao: AoAttrib,
+    pub light: LightAttrib,
+    pub normal: NormalAttrib,
+    pub layer: LayerAttrib,
+    pub tint: TintAttrib,
+}
+
+/// A vertex of the lines of an ``Outline``, given in
+/// world coordinates.
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+#[vertex(sem = "Semantic")]
+pub struct OutlineVertex {
+    pub pos: PosAttrib,
+}
+
+/// A sector mesh vertex in the packed format.
+///
+/// Every attribute of a ``VoxelVertex`` is a small integer
+/// within a sector or an 8-bit color, so all of them fit
+/// into three words. The bit layout is described at
+/// ``pack_vertex``, and the vertex shader ``vs_packed.glsl``
+/// decodes it.
+#[derive(Clone, Copy, Debug, PartialEq, Vertex)]
+#[vertex(sem = "Semantic")]
+pub struct PackedVertex {
+    pub data: PackedAttrib,
 }
@@ -1,7 +1,19 @@
 layout (location = 0) in vec3 pos;
 layout (location = 1) in vec2 uv;
+layout (location = 2) in vec2 tile;
+layout (location = 3) in float ao;
+layout (location = 4) in vec2 light;
+layout (location = 5) in vec3 normal;
+layout (location = 6) in float layer;
+layout (location = 8) in vec3 tint;
 
 out vec2 pass_uv;
+out vec2 pass_tile;
+out float pass_ao;
+out vec2 pass_light;
+out vec3 pass_normal;
+out float pass_layer;
+out vec3 pass_tint;
 
 //uniform float time;
 uniform mat4 model_mat;
@@ -13,4 +25,10 @@ void main(void) {
     
     //pass_color = vec4(color * max(0.2, sin(time)), 1.0);
     pass_uv = uv;
+    pass_tile = tile;
+    pass_ao = ao;
+    pass_light = light;
+    pass_normal = mat3(model_mat) * normal;
+    pass_layer = layer;
+    pass_tint = tint;
 }
@@ -0,0 +1,69 @@
+// Decodes the packed vertex format. The bit layout is
+// described in src/entity/sector/meshgen/packed.rs, and
+// the outputs match those of vs.glsl.
+layout (location = 7) in uvec3 packed_data;
+
+out vec2 pass_uv;
+out vec2 pass_tile;
+out float pass_ao;
+out vec2 pass_light;
+out vec3 pass_normal;
+out float pass_layer;
+out vec3 pass_tint;
+
+uniform mat4 model_mat;
+uniform mat4 view_mat;
+uniform mat4 projection_mat;
+
+// The number of tiles in each row of the atlas, and the
+// size of a tile's cell and border relative to the atlas.
+uniform uint atlas_columns;
+uniform vec2 atlas_cell;
+uniform vec2 atlas_padding;
+
+// Positions are stored with this offset added.
+const float POS_OFFSET = 8.0;
+
+// Positions and texture coordinates are stored in this
+// many steps per block.
+const float STEPS = 16.0;
+
+// The highest light level, in steps of a quarter level.
+const float MAX_LIGHT = 60.0;
+
+// The normal of each side, in the order of Side::ALL.
+const vec3 NORMALS[6] = vec3[6](
+    vec3(0.0, 0.0, 1.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(1.0, 0.0, 0.0),
+    vec3(-1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, -1.0, 0.0)
+);
+
+// Read the field of the given width at the given offset.
+uint field(uint word, uint offset, uint width) {
+    return (word >> offset) & ((1u << width) - 1u);
+}
+
+void main(void) {
+    uint first = packed_data.x;
+    uint second = packed_data.y;
+    uint third = packed_data.z;
+    
+    vec3 pos = vec3(field(first, 0u, 9u), field(first, 9u, 9u), field(first, 18u, 9u));
+    pos = pos / STEPS - POS_OFFSET;
+    uint side = field(first, 27u, 3u);
+    uint texture_id = field(third, 24u, 8u);
+    
+    gl_Position = projection_mat * view_mat * model_mat * vec4(pos, 1.0);
+    
+    pass_uv = vec2(field(second, 0u, 9u), field(second, 9u, 9u)) / STEPS;
+    pass_tile = vec2(texture_id % atlas_columns, texture_id / atlas_columns) * atlas_cell
+        + atlas_padding;
+    pass_ao = float(field(first, 30u, 2u));
+    pass_light = vec2(field(second, 18u, 6u), field(second, 24u, 6u)) / MAX_LIGHT;
+    pass_normal = mat3(model_mat) * NORMALS[side];
+    pass_layer = float(texture_id);
+    pass_tint = vec3(field(third, 0u, 8u), field(third, 8u, 8u), field(third, 16u, 8u)) / 255.0;
+}